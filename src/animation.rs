@@ -0,0 +1,328 @@
+//! Keyframed transforms for motion blur of arbitrary hitables.
+//!
+//! A keyframe holds a translation, a rotation and a scale at a point in time.
+//! Between keyframes translation and scale are interpolated linearly and the
+//! rotation spherically along the shorter arc, so turns of more than half a
+//! revolution need intermediate keyframes. Before the first and after the
+//! last keyframe the transform holds still. Rays are transformed with the
+//! transform at their time, which the camera spreads over `time0..time1`.
+//!
+//! The bounding box encloses the object over all keyframes, so the
+//! `BvhTree` built once for the scene stays valid for every ray time. The
+//! tree has no notion of time, a fast moving object therefore has a large
+//! box that rays at any time have to enter, which costs traversal time but
+//! not correctness. Only the object itself is tested in its pose at the ray
+//! time.
+//!
+//! Light sampling has no ray time, animated lights are sampled in their pose
+//! halfway through the keyframes. The estimate stays unbiased as the
+//! material lobe it is mixed with still covers every direction, moving
+//! lights only get noisier the further they are from that pose.
+
+use crate::hitable::{Hitable, HitRecord, transform_box};
+use crate::matrix::{self, Mat4};
+use crate::aabb::AABB;
+use crate::ray::Ray;
+use crate::vec::Vec3;
+
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+// bounding box samples between two keyframes
+const BOUND_STEPS: usize = 32;
+
+#[derive(Clone, Copy)]
+pub struct Keyframe {
+    pub time: f32,
+    pub translation: Vec3,
+    pub rotation: [f32; 4],
+    pub scale: Vec3
+}
+
+impl Keyframe {
+    // rotation as a unit quaternion [x, y, z, w], see `matrix::quaternion_axis`
+    pub fn new(time: f32, translation: Vec3, rotation: [f32; 4], scale: Vec3) -> Self {
+        Keyframe { time, translation, rotation, scale }
+    }
+
+    pub fn translation(time: f32, translation: Vec3) -> Self {
+        Keyframe::new(time, translation, [0.0, 0.0, 0.0, 1.0], Vec3::new(1.0, 1.0, 1.0))
+    }
+
+    fn matrix(&self) -> Mat4 {
+        Mat4::translation(self.translation) * Mat4::rotation(self.rotation) * Mat4::scale(self.scale)
+    }
+
+    // built from the parts so it never needs a general inverse, the scales
+    // of keyframes and their interpolations are never zero as
+    // `AnimatedTransform::new` rejects the ones that are or get there
+    fn inverse(&self) -> Mat4 {
+        let [x, y, z, w] = self.rotation;
+        let scale = Vec3::new(1.0 / self.scale.x, 1.0 / self.scale.y, 1.0 / self.scale.z);
+
+        Mat4::scale(scale) * Mat4::rotation([-x, -y, -z, w]) * Mat4::translation(-self.translation)
+    }
+}
+
+pub struct AnimatedTransform {
+    hitable: Arc<Hitable>,
+    keyframes: Vec<Keyframe>,
+    bbox: Option<AABB>
+}
+
+impl AnimatedTransform {
+    // None without keyframes, for times that are not finite and for scales
+    // that are zero or change sign between keyframes, as the transform has
+    // no inverse somewhere along the way then
+    pub fn new(hitable: Arc<Hitable>, mut keyframes: Vec<Keyframe>) -> Option<Self> {
+        if keyframes.is_empty() || keyframes.iter().any(|keyframe| !keyframe.time.is_finite()) {
+            return None;
+        }
+
+        keyframes.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap_or(Ordering::Equal));
+
+        let invertible = |scale: Vec3| scale.x != 0.0 && scale.y != 0.0 && scale.z != 0.0;
+        let same_sign = |a: Vec3, b: Vec3| a.x * b.x > 0.0 && a.y * b.y > 0.0 && a.z * b.z > 0.0;
+        if !keyframes.iter().all(|keyframe| invertible(keyframe.scale))
+            || !keyframes.windows(2).all(|pair| same_sign(pair[0].scale, pair[1].scale)) {
+            return None;
+        }
+
+        let bbox = hitable.bounding_box().map(|bbox| motion_box(&keyframes, &bbox));
+
+        Some(AnimatedTransform { hitable, keyframes, bbox })
+    }
+
+    fn sampling_keyframe(&self) -> Keyframe {
+        let (first, last) = (self.keyframes[0].time, self.keyframes[self.keyframes.len() - 1].time);
+        self.keyframe_at(0.5 * (first + last))
+    }
+
+    fn keyframe_at(&self, time: f32) -> Keyframe {
+        let keyframes = &self.keyframes;
+        let last = keyframes.len() - 1;
+
+        if time <= keyframes[0].time {
+            return keyframes[0];
+        }
+        if time >= keyframes[last].time {
+            return keyframes[last];
+        }
+
+        let next = keyframes.iter().position(|keyframe| keyframe.time > time).unwrap();
+        let (a, b) = (&keyframes[next - 1], &keyframes[next]);
+
+        interpolate(a, b, (time - a.time) / (b.time - a.time))
+    }
+}
+
+impl Hitable for AnimatedTransform {
+    fn hit(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
+        let keyframe = self.keyframe_at(ray.time);
+        let matrix = keyframe.matrix();
+        let inverse = keyframe.inverse();
+
+        let object_ray = Ray::new(inverse.transform_point(ray.origin), inverse.transform_vector(ray.direction), ray.time)
+            .with_wavelength(ray.wavelength);
+        let mut hit = self.hitable.hit(&object_ray, t_range)?;
+
+        hit.p = matrix.transform_point(hit.p);
+        hit.normal = inverse.transform_normal(hit.normal).normalize();
+        hit.shading_normal = inverse.transform_normal(hit.shading_normal).normalize();
+        hit.tangent = hit.tangent.map(|tangent| matrix.transform_vector(tangent));
+        hit.bitangent = hit.bitangent.map(|bitangent| matrix.transform_vector(bitangent));
+
+        Some(hit)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.bbox
+    }
+
+    fn volume_emission(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Vec3 {
+        let inverse = self.keyframe_at(ray.time).inverse();
+        let object_ray = Ray::new(inverse.transform_point(ray.origin), inverse.transform_vector(ray.direction), ray.time)
+            .with_wavelength(ray.wavelength);
+
+        self.hitable.volume_emission(&object_ray, t_range)
+    }
+
+    fn has_volume_emission(&self) -> bool {
+        self.hitable.has_volume_emission()
+    }
+
+    // as a `Transform` frozen in the sampling pose, see the module docs
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f32 {
+        let keyframe = self.sampling_keyframe();
+        let inverse = keyframe.inverse();
+
+        let object_direction = inverse.transform_vector(direction.normalize());
+        let pdf = self.hitable.pdf_value(inverse.transform_point(origin), object_direction);
+        let inverse_determinant = 1.0 / (keyframe.scale.x * keyframe.scale.y * keyframe.scale.z);
+
+        pdf * inverse_determinant.abs() / object_direction.len().powi(3)
+    }
+
+    fn random(&self, origin: Vec3) -> Vec3 {
+        let keyframe = self.sampling_keyframe();
+        keyframe.matrix().transform_vector(self.hitable.random(keyframe.inverse().transform_point(origin)))
+    }
+}
+
+fn interpolate(a: &Keyframe, b: &Keyframe, t: f32) -> Keyframe {
+    Keyframe {
+        time: a.time + (b.time - a.time) * t,
+        translation: a.translation + (b.translation - a.translation) * t,
+        rotation: matrix::slerp(a.rotation, b.rotation, t),
+        scale: a.scale + (b.scale - a.scale) * t
+    }
+}
+
+// union of the transformed box sampled along every keyframe interval. The
+// corners move on arcs between the samples, the boxes are padded by the
+// largest distance of such an arc from its chord.
+fn motion_box(keyframes: &[Keyframe], bbox: &AABB) -> AABB {
+    let mut result = transform_box(&keyframes[0].matrix(), bbox);
+
+    let corner = Vec3::new(
+        bbox.min.x.abs().max(bbox.max.x.abs()),
+        bbox.min.y.abs().max(bbox.max.y.abs()),
+        bbox.min.z.abs().max(bbox.max.z.abs())
+    );
+
+    for pair in keyframes.windows(2) {
+        let (a, b) = (&pair[0], &pair[1]);
+
+        let cos_half = (a.rotation[0] * b.rotation[0] + a.rotation[1] * b.rotation[1]
+            + a.rotation[2] * b.rotation[2] + a.rotation[3] * b.rotation[3]).abs().min(1.0);
+        let step_angle = 2.0 * cos_half.acos() / BOUND_STEPS as f32;
+
+        let max_scale = a.scale.x.abs().max(a.scale.y.abs()).max(a.scale.z.abs())
+            .max(b.scale.x.abs().max(b.scale.y.abs()).max(b.scale.z.abs()));
+        let radius = corner.len() * max_scale;
+        let pad = radius * (1.0 - (step_angle / 2.0).cos()) / (step_angle / 2.0).cos() + 0.0001;
+
+        for i in 1..=BOUND_STEPS {
+            let keyframe = interpolate(a, b, i as f32 / BOUND_STEPS as f32);
+            let sample = transform_box(&keyframe.matrix(), bbox);
+            result = AABB::surrounding_box(&result, &AABB::new(sample.min - pad, sample.max + pad));
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Material, PassThrough};
+    use crate::sphere::{self, Sphere};
+    use crate::quad::Quad;
+
+    fn unused() -> Arc<Material> {
+        Arc::new(PassThrough::new(Vec3::zero()))
+    }
+
+    fn unit_sphere() -> Arc<Hitable> {
+        Arc::new(Sphere::new(Vec3::zero(), 1.0, unused()))
+    }
+
+    fn towards_z(x: f32, time: f32) -> Ray {
+        Ray::new(Vec3::new(x, 0.0, -10.0), Vec3::new(0.0, 0.0, 1.0), time)
+    }
+
+    #[test]
+    fn rays_see_the_pose_at_their_time() {
+        let moving = AnimatedTransform::new(unit_sphere(), vec![
+            Keyframe::translation(1.0, Vec3::new(4.0, 0.0, 0.0)),
+            Keyframe::translation(0.0, Vec3::zero())
+        ]).unwrap();
+
+        assert!(moving.hit(&towards_z(0.0, 0.0), 0.001..std::f32::MAX).is_some());
+        assert!(moving.hit(&towards_z(0.0, 1.0), 0.001..std::f32::MAX).is_none());
+        assert!(moving.hit(&towards_z(2.0, 0.5), 0.001..std::f32::MAX).is_some());
+
+        // held still outside of the keyframes
+        assert!(moving.hit(&towards_z(4.0, 7.0), 0.001..std::f32::MAX).is_some());
+        assert!(moving.hit(&towards_z(0.0, -3.0), 0.001..std::f32::MAX).is_some());
+    }
+
+    #[test]
+    fn rotation_and_scale_are_interpolated() {
+        let turning = AnimatedTransform::new(unit_sphere(), vec![
+            Keyframe::new(0.0, Vec3::zero(), matrix::quaternion_axis(Vec3::new(0.0, 1.0, 0.0), 0.0), Vec3::new(3.0, 1.0, 1.0)),
+            Keyframe::new(1.0, Vec3::zero(), matrix::quaternion_axis(Vec3::new(0.0, 1.0, 0.0), 90.0), Vec3::new(1.0, 1.0, 1.0))
+        ]).unwrap();
+
+        // stretched along x at the start, a unit sphere at the end
+        let record = turning.hit(&Ray::new(Vec3::new(-10.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0), 0.001..std::f32::MAX).unwrap();
+        assert!((record.p.x + 3.0).abs() < 1e-4);
+        assert!((record.normal - Vec3::new(-1.0, 0.0, 0.0)).len() < 1e-4);
+
+        let record = turning.hit(&Ray::new(Vec3::new(-10.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), 1.0), 0.001..std::f32::MAX).unwrap();
+        assert!((record.p.x + 1.0).abs() < 1e-4);
+
+        // halfway the long axis of scale 2 points between x and -z
+        let record = turning.hit(&towards_z(0.0, 0.5), 0.001..std::f32::MAX).unwrap();
+        let expected = 1.0 / ((0.5f32 / 4.0) + 0.5).sqrt();
+        assert!((record.p.z + expected).abs() < 1e-3, "{} vs {}", record.p.z, -expected);
+    }
+
+    #[test]
+    fn invalid_keyframes_are_rejected() {
+        let at = |time: f32, scale: Vec3| Keyframe::new(time, Vec3::zero(), [0.0, 0.0, 0.0, 1.0], scale);
+        let one = Vec3::new(1.0, 1.0, 1.0);
+
+        assert!(AnimatedTransform::new(unit_sphere(), Vec::new()).is_none());
+        assert!(AnimatedTransform::new(unit_sphere(), vec![at(0.0, one), at(std::f32::NAN, one)]).is_none());
+        assert!(AnimatedTransform::new(unit_sphere(), vec![at(std::f32::INFINITY, one)]).is_none());
+        assert!(AnimatedTransform::new(unit_sphere(), vec![at(0.0, Vec3::new(1.0, 0.0, 1.0))]).is_none());
+
+        // mirroring over time passes through a zero scale
+        assert!(AnimatedTransform::new(unit_sphere(), vec![at(0.0, one), at(1.0, Vec3::new(-1.0, 1.0, 1.0))]).is_none());
+        assert!(AnimatedTransform::new(unit_sphere(), vec![at(0.0, -one), at(1.0, Vec3::new(-2.0, -1.0, -1.0))]).is_some());
+    }
+
+    #[test]
+    fn motion_box_covers_every_pose() {
+        let block: Arc<Hitable> = Arc::new(Quad::new(Vec3::new(1.0, -0.5, -0.5), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0), unused()));
+        let animated = AnimatedTransform::new(block, vec![
+            Keyframe::new(0.0, Vec3::zero(), matrix::quaternion_axis(Vec3::new(0.0, 0.0, 1.0), 0.0), Vec3::new(1.0, 1.0, 1.0)),
+            Keyframe::new(1.0, Vec3::new(0.0, 2.0, 0.0), matrix::quaternion_axis(Vec3::new(0.0, 0.0, 1.0), 170.0), Vec3::new(2.0, 2.0, 2.0))
+        ]).unwrap();
+        let bbox = animated.bounding_box().unwrap();
+
+        for i in 0..=100 {
+            let keyframe = animated.keyframe_at(i as f32 / 100.0);
+            for &(y, z) in [(-0.5, -0.5), (0.5, -0.5), (-0.5, 0.5), (0.5, 0.5)].iter() {
+                let p = keyframe.matrix().transform_point(Vec3::new(1.0, y, z));
+                assert!(p.x >= bbox.min.x && p.y >= bbox.min.y && p.z >= bbox.min.z, "{:?} at {}", p, i);
+                assert!(p.x <= bbox.max.x && p.y <= bbox.max.y && p.z <= bbox.max.z, "{:?} at {}", p, i);
+            }
+        }
+    }
+
+    #[test]
+    fn animated_lights_are_sampled_in_the_middle_pose() {
+        let panel: Arc<Hitable> = Arc::new(Quad::new(Vec3::new(-0.5, 0.0, -0.5), Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 0.0), unused()));
+        let light = AnimatedTransform::new(panel, vec![
+            Keyframe::new(0.0, Vec3::new(-2.0, 3.0, 0.0), [0.0, 0.0, 0.0, 1.0], Vec3::new(1.0, 1.0, 1.0)),
+            Keyframe::new(1.0, Vec3::new(2.0, 3.0, 0.0), [0.0, 0.0, 0.0, 1.0], Vec3::new(3.0, 1.0, 1.0))
+        ]).unwrap();
+        let origin = Vec3::zero();
+
+        for _ in 0..100 {
+            let direction = light.random(origin);
+            assert!(light.hit(&Ray::new(origin, direction, 0.5), 0.001..std::f32::MAX).is_some());
+        }
+
+        // the density over all directions integrates to one
+        let samples = 200000;
+        let sum: f32 = (0..samples)
+            .map(|_| light.pdf_value(origin, sphere::random_in_unit_sphere().normalize()))
+            .sum();
+        let integral = 4.0 * std::f32::consts::PI * sum / samples as f32;
+
+        assert!((integral - 1.0).abs() < 0.05, "{}", integral);
+    }
+}
@@ -1,41 +1,46 @@
-use crate::hitable::*;
-use crate::vec::Vec3;
-use crate::material::Material;
-use crate::xy_rect::*;
-use crate::ray::Ray;
-use crate::aabb::AABB;
-
-use std::sync::Arc;
-
-pub struct BoxModel {
-    hitable: Box<Hitable>,
-    pmin: Vec3,
-    pmax: Vec3
-}
-
-impl BoxModel {
-    pub fn new(p0: Vec3, p1: Vec3, material: Arc<Material>) -> Self {
-        let mut world: Vec<Box<Hitable>> = Vec::new();
-
-        world.push(Box::new(XYRect::new(p0.x, p1.x, p0.y, p1.y, p1.z, Arc::clone(&material))));
-        world.push(Box::new(FlipNormal::new(Box::new(XYRect::new(p0.x, p1.x, p0.y, p1.y, p0.z, Arc::clone(&material))))));
-
-        world.push(Box::new(XZRect::new(p0.x, p1.x, p0.z, p1.z, p1.y, Arc::clone(&material))));
-        world.push(Box::new(FlipNormal::new(Box::new(XZRect::new(p0.x, p1.x, p0.z, p1.z, p0.y, Arc::clone(&material))))));
-
-        world.push(Box::new(YZRect::new(p0.y, p1.y, p0.z, p1.z, p1.x, Arc::clone(&material))));
-        world.push(Box::new(FlipNormal::new(Box::new(YZRect::new(p0.y, p1.y, p0.z, p1.z, p0.x, Arc::clone(&material))))));
-
-        BoxModel { pmin: p0, pmax: p1, hitable: Box::new(HitableList::from_list(world)) }
-    }
-}
-
-impl Hitable for BoxModel {
-    fn hit(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
-        self.hitable.hit(ray, t_range)
-    }
-
-    fn bounding_box(&self) -> Option<AABB> {
-        Some(AABB::new(self.pmin, self.pmax))
-    }
+use crate::hitable::*;
+use crate::vec::Vec3;
+use crate::material::Material;
+use crate::quad::Quad;
+use crate::ray::Ray;
+use crate::aabb::AABB;
+
+use std::sync::Arc;
+
+pub struct BoxModel {
+    hitable: Box<Hitable>,
+    pmin: Vec3,
+    pmax: Vec3
+}
+
+impl BoxModel {
+    pub fn new(p0: Vec3, p1: Vec3, material: Arc<Material>) -> Self {
+        let mut world: Vec<Box<Hitable>> = Vec::new();
+
+        let min = Vec3::new(p0.x.min(p1.x), p0.y.min(p1.y), p0.z.min(p1.z));
+        let max = Vec3::new(p0.x.max(p1.x), p0.y.max(p1.y), p0.z.max(p1.z));
+        let dx = Vec3::new(max.x - min.x, 0.0, 0.0);
+        let dy = Vec3::new(0.0, max.y - min.y, 0.0);
+        let dz = Vec3::new(0.0, 0.0, max.z - min.z);
+
+        // edges are ordered so that all normals point outwards
+        world.push(Box::new(Quad::new(Vec3::new(min.x, min.y, max.z), dx, dy, Arc::clone(&material))));
+        world.push(Box::new(Quad::new(Vec3::new(max.x, min.y, max.z), -dz, dy, Arc::clone(&material))));
+        world.push(Box::new(Quad::new(Vec3::new(max.x, min.y, min.z), -dx, dy, Arc::clone(&material))));
+        world.push(Box::new(Quad::new(Vec3::new(min.x, min.y, min.z), dz, dy, Arc::clone(&material))));
+        world.push(Box::new(Quad::new(Vec3::new(min.x, max.y, max.z), dx, -dz, Arc::clone(&material))));
+        world.push(Box::new(Quad::new(Vec3::new(min.x, min.y, min.z), dx, dz, Arc::clone(&material))));
+
+        BoxModel { pmin: min, pmax: max, hitable: Box::new(HitableList::from_list(world)) }
+    }
+}
+
+impl Hitable for BoxModel {
+    fn hit(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
+        self.hitable.hit(ray, t_range)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(AABB::new(self.pmin, self.pmax))
+    }
 }
\ No newline at end of file
@@ -1,189 +1,288 @@
-use crate::aabb::AABB;
-use crate::hitable::Hitable;
-use crate::ray::Ray;
-use crate::hitable::HitRecord;
-
-use std::cmp::Ordering;
-
-use rand::Rng;
-
-#[derive(Copy, Clone)]
-struct NodeId {
-    index: usize
-}
-
-pub struct BvhTree<'a> {
-    nodes: Vec<BvhNode<'a>>,
-    root: NodeId
-}
-
-struct BvhNode<'a> {
-    bbox: Option<AABB>,
-    left: Option<NodeId>,
-    right: Option<NodeId>,
-    hitable: Option<&'a Box<Hitable>>
-}
-
-impl<'a> BvhTree<'a> {
-    pub fn new(l: &'a mut [Box<Hitable>]) -> Self {
-        let mut tree = BvhTree { nodes: Vec::new(), root: NodeId { index: 0 }};
-        tree.root = tree.build(l);
-        return tree;
-    }
-
-    pub fn print(&self) {
-      println!("BVH Tree with {} Nodes", self.nodes.len());
-    }
-
-    fn build(&mut self, l: &'a mut [Box<Hitable>]) -> NodeId {
-        let axis: i32 = rand::thread_rng().gen_range(0, 3);
-        let left: NodeId;
-        let right: NodeId;
-
-        match axis {
-            0 => l.sort_by(|a, b| box_x_compare(a, b)),
-            1 => l.sort_by(|a, b| box_y_compare(a, b)),
-            2 => l.sort_by(|a, b| box_z_compare(a, b)),
-            _ => panic!("unexpected axis")
-        }
-
-        if l.len() == 1 {
-            return self.add_leaf(&l[0]);
-        } else if l.len() == 2 {
-            left = self.add_leaf(&l[0]);
-            right = self.add_leaf(&l[1]);
-        } else {
-            let half_len = l.len() / 2;
-            let (left_hitables, right_hitables) = l.split_at_mut(half_len);
-
-            left = self.build(left_hitables);
-            right = self.build(right_hitables);
-        }
-
-        if let Some(left_box) = self.nodes[left.index].bbox {
-            if let Some(right_box) = self.nodes[right.index].bbox {
-                return self.add_node(AABB::surrounding_box(&left_box, &right_box), left, right);
-            }
-        }
-
-        panic!("No bounding box in bvh_tree constructor");
-    }
-
-    fn add_leaf(&mut self, hitable: &'a Box<Hitable>) -> NodeId {
-        let next_index = self.nodes.len();
-
-        self.nodes.push(BvhNode {
-            bbox: hitable.bounding_box(),
-            left: None,
-            right: None,
-            hitable: Some(hitable)
-        });
-
-        NodeId { index: next_index }
-    }
-
-    fn add_node(&mut self, bbox: AABB, left: NodeId, right: NodeId) -> NodeId {
-        let next_index = self.nodes.len();
-
-        self.nodes.push(BvhNode {
-            left: Some(left),
-            right: Some(right),
-            bbox: Some(bbox),
-            hitable: None
-        });
-
-        NodeId { index: next_index }
-    }
-
-    fn hit_tree(&self, id: NodeId, r: &Ray, tmin: f32, tmax: f32) -> Option<HitRecord> {
-      let node = &self.nodes[id.index];
-
-      if node.bbox.is_none() || node.bbox.is_some() && node.bbox.unwrap().hit(r, tmin, tmax) {
-            match node.hitable {
-                Some(ref hitable) => return hitable.hit(r, tmin..tmax),
-                None => { }
-            }
-
-            let mut hit_left: Option<HitRecord> = None;
-            let mut hit_right: Option<HitRecord> = None;
-
-            if let Some(ref left) = node.left {
-                //println!("check if left node is hit");
-                hit_left = self.hit_tree(*left, r, tmin, tmax);
-            }
-
-            if let Some(ref right) = node.right {
-                //println!("check if right node is hit");
-                hit_right = self.hit_tree(*right, r, tmin, tmax);
-            }
-
-            match hit_left {
-              Some(left) => {
-                match hit_right {
-                  Some(right) => if left.t < right.t { return hit_left; } else { return hit_right; },
-                  None => return hit_left
-                }
-              },
-              None => {}
-            }
-
-            match hit_right {
-              Some(_right) => return hit_right,
-              None => {}
-            }
-        }
-        return None;
-    }
-}
-
-impl<'a> Hitable for BvhTree<'a> {
-    fn bounding_box(&self) -> Option<AABB> {
-        self.nodes[self.root.index].bbox
-    }
-
-    fn hit(&self, r: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
-        self.hit_tree(self.root, r, t_range.start, t_range.end)
-    }
-}
-
-fn box_x_compare(a: &Box<Hitable>, b: &Box<Hitable>) -> Ordering {
-  if let Some(box_left) = a.bounding_box() {
-    if let Some(box_right) = b.bounding_box() {
-      if let Some(cmp) = box_left.min.x.partial_cmp(&box_right.min.x) {
-        return cmp;
-      } else {
-        panic!("Can't compare");
-      }
-    }
-  }
-
-  panic!("No bounding box in BvhNode::new");
-}
-
-fn box_y_compare(a: &Box<Hitable>, b: &Box<Hitable>) -> Ordering {
-  if let Some(box_left) = a.bounding_box() {
-    if let Some(box_right) = b.bounding_box() {
-      if let Some(cmp) = box_left.min.y.partial_cmp(&box_right.min.y) {
-        return cmp;
-      } else {
-        panic!("Can't compare");
-      }
-    }
-  }
-
-  panic!("No bounding box in BvhNode::new");
-}
-
-fn box_z_compare(a: &Box<Hitable>, b: &Box<Hitable>) -> Ordering {
-  if let Some(box_left) = a.bounding_box() {
-    if let Some(box_right) = b.bounding_box() {
-      if let Some(cmp) = box_left.min.z.partial_cmp(&box_right.min.z) {
-        return cmp;
-      } else {
-        panic!("Can't compare");
-      }
-    }
-  }
-
-  panic!("No bounding box in BvhNode::new");
+use crate::aabb::AABB;
+use crate::hitable::Hitable;
+use crate::ray::Ray;
+use crate::hitable::HitRecord;
+use crate::vec::Vec3;
+
+use std::cmp::Ordering;
+
+use rand::Rng;
+
+#[derive(Copy, Clone)]
+struct NodeId {
+    index: usize
+}
+
+pub struct BvhTree<'a> {
+    hitables: &'a [Box<Hitable>],
+    nodes: Nodes
+}
+
+// owns its hitables so that it can be shared and wrapped like any other
+// hitable, e.g. a mesh placed with a Transform
+pub struct Bvh {
+    hitables: Vec<Box<Hitable>>,
+    nodes: Nodes
+}
+
+struct Nodes {
+    nodes: Vec<BvhNode>,
+    root: NodeId
+}
+
+// leaves refer to hitables by their index in the sorted hitable slice
+struct BvhNode {
+    bbox: Option<AABB>,
+    left: Option<NodeId>,
+    right: Option<NodeId>,
+    hitable: Option<usize>,
+    volume_emission: bool
+}
+
+impl<'a> BvhTree<'a> {
+    pub fn new(l: &'a mut [Box<Hitable>]) -> Self {
+        let nodes = Nodes::new(l);
+        BvhTree { hitables: l, nodes }
+    }
+
+    pub fn print(&self) {
+      println!("BVH Tree with {} Nodes", self.nodes.nodes.len());
+    }
+}
+
+impl Bvh {
+    pub fn new(mut hitables: Vec<Box<Hitable>>) -> Self {
+        let nodes = Nodes::new(&mut hitables);
+        Bvh { hitables, nodes }
+    }
+}
+
+impl Nodes {
+    fn new(l: &mut [Box<Hitable>]) -> Self {
+        let mut nodes = Nodes { nodes: Vec::new(), root: NodeId { index: 0 }};
+        nodes.root = nodes.build(l, 0);
+        return nodes;
+    }
+
+    // sorting only ever happens on the slice being built, so the leaf indices
+    // stay valid once the children are done
+    fn build(&mut self, l: &mut [Box<Hitable>], offset: usize) -> NodeId {
+        let axis: i32 = rand::thread_rng().gen_range(0, 3);
+        let left: NodeId;
+        let right: NodeId;
+
+        match axis {
+            0 => l.sort_by(|a, b| box_x_compare(a, b)),
+            1 => l.sort_by(|a, b| box_y_compare(a, b)),
+            2 => l.sort_by(|a, b| box_z_compare(a, b)),
+            _ => panic!("unexpected axis")
+        }
+
+        if l.len() == 1 {
+            return self.add_leaf(&l[0], offset);
+        } else if l.len() == 2 {
+            left = self.add_leaf(&l[0], offset);
+            right = self.add_leaf(&l[1], offset + 1);
+        } else {
+            let half_len = l.len() / 2;
+            let (left_hitables, right_hitables) = l.split_at_mut(half_len);
+
+            left = self.build(left_hitables, offset);
+            right = self.build(right_hitables, offset + half_len);
+        }
+
+        if let Some(left_box) = self.nodes[left.index].bbox {
+            if let Some(right_box) = self.nodes[right.index].bbox {
+                return self.add_node(AABB::surrounding_box(&left_box, &right_box), left, right);
+            }
+        }
+
+        panic!("No bounding box in bvh_tree constructor");
+    }
+
+    fn add_leaf(&mut self, hitable: &Box<Hitable>, index: usize) -> NodeId {
+        let next_index = self.nodes.len();
+
+        self.nodes.push(BvhNode {
+            bbox: hitable.bounding_box(),
+            left: None,
+            right: None,
+            hitable: Some(index),
+            volume_emission: hitable.has_volume_emission()
+        });
+
+        NodeId { index: next_index }
+    }
+
+    fn add_node(&mut self, bbox: AABB, left: NodeId, right: NodeId) -> NodeId {
+        let next_index = self.nodes.len();
+
+        let volume_emission = self.nodes[left.index].volume_emission || self.nodes[right.index].volume_emission;
+
+        self.nodes.push(BvhNode {
+            left: Some(left),
+            right: Some(right),
+            bbox: Some(bbox),
+            hitable: None,
+            volume_emission
+        });
+
+        NodeId { index: next_index }
+    }
+
+    fn hit_tree<'h>(&self, hitables: &'h [Box<Hitable>], id: NodeId, r: &Ray, tmin: f32, tmax: f32) -> Option<HitRecord<'h>> {
+      let node = &self.nodes[id.index];
+
+      if node.bbox.is_none() || node.bbox.is_some() && node.bbox.unwrap().hit(r, tmin, tmax) {
+            match node.hitable {
+                Some(index) => return hitables[index].hit(r, tmin..tmax),
+                None => { }
+            }
+
+            let mut hit_left: Option<HitRecord> = None;
+            let mut hit_right: Option<HitRecord> = None;
+
+            if let Some(ref left) = node.left {
+                //println!("check if left node is hit");
+                hit_left = self.hit_tree(hitables, *left, r, tmin, tmax);
+            }
+
+            // only hits closer than the left one can matter, which keeps
+            // instances from traversing their whole bottom level tree
+            if let Some(ref right) = node.right {
+                //println!("check if right node is hit");
+                let tmax = hit_left.map_or(tmax, |left| left.t);
+                hit_right = self.hit_tree(hitables, *right, r, tmin, tmax);
+            }
+
+            match hit_left {
+              Some(left) => {
+                match hit_right {
+                  Some(right) => if left.t < right.t { return hit_left; } else { return hit_right; },
+                  None => return hit_left
+                }
+              },
+              None => {}
+            }
+
+            match hit_right {
+              Some(_right) => return hit_right,
+              None => {}
+            }
+        }
+        return None;
+    }
+
+    fn volume_emission_tree(&self, hitables: &[Box<Hitable>], id: NodeId, r: &Ray, tmin: f32, tmax: f32) -> Vec3 {
+        let node = &self.nodes[id.index];
+
+        if !node.volume_emission || node.bbox.is_some() && !node.bbox.unwrap().hit(r, tmin, tmax) {
+            return Vec3::zero();
+        }
+
+        if let Some(index) = node.hitable {
+            return hitables[index].volume_emission(r, tmin..tmax);
+        }
+
+        let mut emission = Vec3::zero();
+
+        if let Some(left) = node.left {
+            emission += self.volume_emission_tree(hitables, left, r, tmin, tmax);
+        }
+
+        if let Some(right) = node.right {
+            emission += self.volume_emission_tree(hitables, right, r, tmin, tmax);
+        }
+
+        emission
+    }
+}
+
+impl<'a> Hitable for BvhTree<'a> {
+    fn bounding_box(&self) -> Option<AABB> {
+        self.nodes.nodes[self.nodes.root.index].bbox
+    }
+
+    fn hit(&self, r: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
+        self.nodes.hit_tree(self.hitables, self.nodes.root, r, t_range.start, t_range.end)
+    }
+
+    fn volume_emission(&self, r: &Ray, t_range: ::std::ops::Range<f32>) -> Vec3 {
+        self.nodes.volume_emission_tree(self.hitables, self.nodes.root, r, t_range.start, t_range.end)
+    }
+
+    fn has_volume_emission(&self) -> bool {
+        self.nodes.nodes[self.nodes.root.index].volume_emission
+    }
+}
+
+impl Hitable for Bvh {
+    fn bounding_box(&self) -> Option<AABB> {
+        self.nodes.nodes[self.nodes.root.index].bbox
+    }
+
+    fn hit(&self, r: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
+        self.nodes.hit_tree(&self.hitables, self.nodes.root, r, t_range.start, t_range.end)
+    }
+
+    fn volume_emission(&self, r: &Ray, t_range: ::std::ops::Range<f32>) -> Vec3 {
+        self.nodes.volume_emission_tree(&self.hitables, self.nodes.root, r, t_range.start, t_range.end)
+    }
+
+    fn has_volume_emission(&self) -> bool {
+        self.nodes.nodes[self.nodes.root.index].volume_emission
+    }
+
+    // picks one of the hitables uniformly, as a list of lights does
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f32 {
+        let sum: f32 = self.hitables.iter().map(|hitable| hitable.pdf_value(origin, direction)).sum();
+        sum / self.hitables.len() as f32
+    }
+
+    fn random(&self, origin: Vec3) -> Vec3 {
+        let index = ((rand::random::<f32>() * self.hitables.len() as f32) as usize).min(self.hitables.len() - 1);
+        self.hitables[index].random(origin)
+    }
+}
+
+fn box_x_compare(a: &Box<Hitable>, b: &Box<Hitable>) -> Ordering {
+  if let Some(box_left) = a.bounding_box() {
+    if let Some(box_right) = b.bounding_box() {
+      if let Some(cmp) = box_left.min.x.partial_cmp(&box_right.min.x) {
+        return cmp;
+      } else {
+        panic!("Can't compare");
+      }
+    }
+  }
+
+  panic!("No bounding box in BvhNode::new");
+}
+
+fn box_y_compare(a: &Box<Hitable>, b: &Box<Hitable>) -> Ordering {
+  if let Some(box_left) = a.bounding_box() {
+    if let Some(box_right) = b.bounding_box() {
+      if let Some(cmp) = box_left.min.y.partial_cmp(&box_right.min.y) {
+        return cmp;
+      } else {
+        panic!("Can't compare");
+      }
+    }
+  }
+
+  panic!("No bounding box in BvhNode::new");
+}
+
+fn box_z_compare(a: &Box<Hitable>, b: &Box<Hitable>) -> Ordering {
+  if let Some(box_left) = a.bounding_box() {
+    if let Some(box_right) = b.bounding_box() {
+      if let Some(cmp) = box_left.min.z.partial_cmp(&box_right.min.z) {
+        return cmp;
+      } else {
+        panic!("Can't compare");
+      }
+    }
+  }
+
+  panic!("No bounding box in BvhNode::new");
 }
\ No newline at end of file
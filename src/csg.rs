@@ -0,0 +1,239 @@
+use crate::hitable::Hitable;
+use crate::hitable::HitRecord;
+use crate::hitable::step_past;
+use crate::aabb::AABB;
+use crate::ray::Ray;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Operands have to be closed surfaces with outward normals. Every boundary
+// crossing of both operands along the whole ray is collected, the inside
+// state of each operand follows from the side the ray crosses from, and the
+// first crossing in range that changes the combined state is the hit.
+
+// upper bound of crossings collected per operand and ray, crossings behind
+// it are ignored and counted in `truncated_rays`
+const MAX_CROSSINGS: usize = 64;
+
+static TRUNCATED_RAYS: AtomicUsize = AtomicUsize::new(0);
+
+// number of operand crossings lists cut off at MAX_CROSSINGS so far, surfaces
+// behind the cut may be missing from the image
+pub fn truncated_rays() -> usize {
+    TRUNCATED_RAYS.load(Ordering::Relaxed)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Operation {
+    Union,
+    Intersection,
+    Difference
+}
+
+impl Operation {
+    fn inside(&self, left: bool, right: bool) -> bool {
+        match self {
+            Operation::Union => left || right,
+            Operation::Intersection => left && right,
+            Operation::Difference => left && !right
+        }
+    }
+}
+
+pub struct Csg {
+    left: Arc<Hitable>,
+    right: Arc<Hitable>,
+    operation: Operation
+}
+
+impl Csg {
+    pub fn new(left: Arc<Hitable>, right: Arc<Hitable>, operation: Operation) -> Self {
+        Csg { left, right, operation }
+    }
+
+    pub fn union(left: Arc<Hitable>, right: Arc<Hitable>) -> Self {
+        Csg::new(left, right, Operation::Union)
+    }
+
+    pub fn intersection(left: Arc<Hitable>, right: Arc<Hitable>) -> Self {
+        Csg::new(left, right, Operation::Intersection)
+    }
+
+    // left with right carved out of it
+    pub fn difference(left: Arc<Hitable>, right: Arc<Hitable>) -> Self {
+        Csg::new(left, right, Operation::Difference)
+    }
+}
+
+impl Hitable for Csg {
+    fn hit(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
+        let left = crossings(&*self.left, ray);
+        let right = crossings(&*self.right, ray);
+
+        // the first crossing leaving an operand means the ray started inside
+        let mut inside_left = left.first().map_or(false, |record| !entering(record, ray));
+        let mut inside_right = right.first().map_or(false, |record| !entering(record, ray));
+        let mut inside = self.operation.inside(inside_left, inside_right);
+
+        let (mut i, mut j) = (0, 0);
+        while i < left.len() || j < right.len() {
+            let record = if j >= right.len() || (i < left.len() && left[i].t <= right[j].t) {
+                inside_left = entering(&left[i], ray);
+                i += 1;
+                left[i - 1]
+            } else {
+                inside_right = entering(&right[j], ray);
+                j += 1;
+                right[j - 1]
+            };
+
+            if record.t >= t_range.end {
+                break;
+            }
+
+            let was_inside = inside;
+            inside = self.operation.inside(inside_left, inside_right);
+
+            if inside != was_inside && record.t > t_range.start {
+                // carved surfaces face into the operand they came from
+                let mut record = record;
+                if entering(&record, ray) != inside {
+                    record.normal = -record.normal;
+                    record.shading_normal = -record.shading_normal;
+                }
+
+                return Some(record);
+            }
+        }
+
+        None
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        let left = self.left.bounding_box();
+        let right = self.right.bounding_box();
+
+        match self.operation {
+            Operation::Union => match (left, right) {
+                (Some(left), Some(right)) => Some(AABB::surrounding_box(&left, &right)),
+                _ => None
+            },
+            Operation::Intersection => match (left, right) {
+                (Some(left), Some(right)) => Some(AABB::overlap_box(&left, &right)),
+                (left, right) => left.or(right)
+            },
+            Operation::Difference => left
+        }
+    }
+}
+
+// all boundary crossings along the ray in increasing order, including the
+// ones behind the origin so rays starting inside get the right state
+fn crossings<'a>(hitable: &'a Hitable, ray: &Ray) -> Vec<HitRecord<'a>> {
+    let mut records = Vec::new();
+    let mut start = std::f32::MIN;
+
+    while let Some(record) = hitable.hit(ray, start..std::f32::MAX) {
+        if records.len() == MAX_CROSSINGS {
+            TRUNCATED_RAYS.fetch_add(1, Ordering::Relaxed);
+            break;
+        }
+
+        let next = step_past(record.t);
+        records.push(record);
+
+        if next <= start {
+            break;
+        }
+        start = next;
+    }
+
+    records
+}
+
+fn entering(record: &HitRecord, ray: &Ray) -> bool {
+    record.normal.dot(ray.direction) < 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sphere::Sphere;
+    use crate::material::{Material, PassThrough};
+    use crate::hitable::HitableList;
+    use crate::vec::Vec3;
+
+    fn sphere(x: f32, radius: f32) -> Arc<Hitable> {
+        let material: Arc<Material> = Arc::new(PassThrough::new(Vec3::zero()));
+        Arc::new(Sphere::new(Vec3::new(x, 0.0, 0.0), radius, material))
+    }
+
+    // positions and normal x of the hits along the x axis, stepping past each one
+    fn hits_along_x(hitable: &Hitable, origin: f32) -> Vec<(f32, f32)> {
+        let ray = Ray::new(Vec3::new(origin, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        let mut hits = Vec::new();
+        let mut start = 0.001;
+
+        while let Some(record) = hitable.hit(&ray, start..std::f32::MAX) {
+            hits.push((origin + record.t, record.normal.x));
+            start = step_past(record.t);
+        }
+
+        hits
+    }
+
+    fn assert_hits(hits: Vec<(f32, f32)>, expected: &[(f32, f32)]) {
+        assert_eq!(hits.len(), expected.len(), "{:?}", hits);
+        for (hit, expected) in hits.iter().zip(expected) {
+            assert!((hit.0 - expected.0).abs() < 1e-3 && (hit.1 - expected.1).abs() < 1e-3, "{:?} vs {:?}", hits, expected);
+        }
+    }
+
+    // unit spheres around -0.5 and 1 overlap on [0, 0.5] along x
+    #[test]
+    fn intervals_combine_by_the_operation() {
+        let union = Csg::union(sphere(-0.5, 1.0), sphere(1.0, 1.0));
+        assert_hits(hits_along_x(&union, -5.0), &[(-1.5, -1.0), (2.0, 1.0)]);
+
+        let intersection = Csg::intersection(sphere(-0.5, 1.0), sphere(1.0, 1.0));
+        assert_hits(hits_along_x(&intersection, -5.0), &[(0.0, -1.0), (0.5, 1.0)]);
+
+        // the carved surface faces into the hole
+        let difference = Csg::difference(sphere(-0.5, 1.0), sphere(1.0, 1.0));
+        assert_hits(hits_along_x(&difference, -5.0), &[(-1.5, -1.0), (0.0, 1.0)]);
+    }
+
+    #[test]
+    fn rays_starting_inside_keep_the_state() {
+        let difference = Csg::difference(sphere(0.0, 2.0), sphere(0.0, 1.0));
+
+        // from inside the shell, inside the hole and behind everything
+        assert_hits(hits_along_x(&difference, -1.5), &[(-1.0, 1.0), (1.0, -1.0), (2.0, 1.0)]);
+        assert_hits(hits_along_x(&difference, 0.0), &[(1.0, -1.0), (2.0, 1.0)]);
+        assert_hits(hits_along_x(&difference, 3.0), &[]);
+    }
+
+    #[test]
+    fn far_operands_are_not_lost() {
+        let union = Csg::union(sphere(5000.0, 100.0), sphere(5150.0, 100.0));
+        assert_hits(hits_along_x(&union, 0.0), &[(4900.0, -1.0), (5250.0, 1.0)]);
+    }
+
+    #[test]
+    fn crossings_are_capped() {
+        // concentric shells cross the ray twice each
+        let shells: Vec<Box<Hitable>> = (1..=40)
+            .map(|i| Box::new(Sphere::new(Vec3::zero(), i as f32, Arc::new(PassThrough::new(Vec3::zero())))) as Box<Hitable>)
+            .collect();
+        let shells = HitableList::from_list(shells);
+
+        let ray = Ray::new(Vec3::new(-50.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        let truncated = truncated_rays();
+        let records = crossings(&shells, &ray);
+
+        assert_eq!(records.len(), MAX_CROSSINGS);
+        assert!(truncated_rays() > truncated);
+        assert!(records.windows(2).all(|pair| pair[0].t < pair[1].t));
+    }
+}
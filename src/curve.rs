@@ -0,0 +1,353 @@
+//! Cubic Bezier curves for hair, fur and grass, and a loader for curve
+//! list files.
+//!
+//! Curves are intersected after Nakamaru and Nishita as in pbrt: the control
+//! points are moved into a frame where the ray runs along z from the origin,
+//! the curve is subdivided until the pieces are close to straight and the
+//! pieces are tested for passing within half their width of the ray. The
+//! width is interpolated linearly from the start to the end of the curve.
+//! Flat curves are ribbons always facing the ray, cylinder curves bend their
+//! normal across the width like a tube. Hits report the position along the
+//! curve as u, the position across it as v and the curve direction as
+//! tangent, which is what the `Hair` material shades with.
+//!
+//! Curve list files are plain text with one curve per line, `#` starts a
+//! comment:
+//!
+//! ```text
+//! x0 y0 z0  x1 y1 z1  x2 y2 z2  x3 y3 z3  width0 [width1]
+//! ```
+//!
+//! The four points are the Bezier control points, the end width defaults to
+//! the start width.
+
+use crate::ray::Ray;
+use crate::vec::Vec3;
+use crate::hitable::{Hitable, HitRecord};
+use crate::material::Material;
+use crate::aabb::AABB;
+use crate::onb::Onb;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+const MAX_DEPTH: i32 = 10;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum CurveType {
+    Flat,
+    Cylinder
+}
+
+pub struct Curve {
+    points: [Vec3; 4],
+    widths: (f32, f32),
+    kind: CurveType,
+    material: Arc<Material>,
+    depth: i32
+}
+
+impl Curve {
+    pub fn new(points: [Vec3; 4], widths: (f32, f32), kind: CurveType, material: Arc<Material>) -> Self {
+        // subdivisions needed for the pieces to deviate from a straight line
+        // by less than a twentieth of the width
+        let curvature = (0..2)
+            .map(|i| (points[i] - points[i + 1] * 2.0 + points[i + 2]).len())
+            .fold(0.0, f32::max);
+        let epsilon = widths.0.max(widths.1) * 0.05;
+        let depth = ((std::f32::consts::SQRT_2 * 6.0 * curvature / (8.0 * epsilon)).log2() / 2.0) as i32;
+
+        Curve { points, widths, kind, material, depth: depth.max(0).min(MAX_DEPTH) }
+    }
+
+    fn width(&self, u: f32) -> f32 {
+        lerp(self.widths.0, self.widths.1, u)
+    }
+
+    // closest hit of the ray space control points within the z range as
+    // (z, u, offset), the offset runs from -1 to 1 across the width
+    fn recursive_hit(&self, cp: &[Vec3; 4], u0: f32, u1: f32, depth: i32, z_range: (f32, f32)) -> Option<(f32, f32, f32)> {
+        let half_width = self.width(u0).max(self.width(u1)) * 0.5;
+
+        let min = cp.iter().fold(Vec3::max(), |m, p| Vec3::new(m.x.min(p.x), m.y.min(p.y), m.z.min(p.z))) - half_width;
+        let max = cp.iter().fold(Vec3::min(), |m, p| Vec3::new(m.x.max(p.x), m.y.max(p.y), m.z.max(p.z))) + half_width;
+
+        if min.x > 0.0 || max.x < 0.0 || min.y > 0.0 || max.y < 0.0 || max.z < z_range.0 || min.z > z_range.1 {
+            return None;
+        }
+
+        if depth > 0 {
+            let split = subdivide(cp);
+            let middle = (u0 + u1) * 0.5;
+
+            let first = self.recursive_hit(&[split[0], split[1], split[2], split[3]], u0, middle, depth - 1, z_range);
+            let z_end = first.map_or(z_range.1, |hit| hit.0);
+            let second = self.recursive_hit(&[split[3], split[4], split[5], split[6]], middle, u1, depth - 1, (z_range.0, z_end));
+
+            return second.or(first);
+        }
+
+        // the ray has to pass between the lines perpendicular to the curve
+        // at both ends of the piece
+        let start_edge = (cp[1].y - cp[0].y) * -cp[0].y + cp[0].x * (cp[0].x - cp[1].x);
+        let end_edge = (cp[2].y - cp[3].y) * -cp[3].y + cp[3].x * (cp[3].x - cp[2].x);
+        if start_edge < 0.0 || end_edge < 0.0 {
+            return None;
+        }
+
+        let dx = cp[3].x - cp[0].x;
+        let dy = cp[3].y - cp[0].y;
+        let denominator = dx * dx + dy * dy;
+        if denominator == 0.0 {
+            return None;
+        }
+
+        // the piece is close enough to its chord to measure against that
+        let w = clamp(-(cp[0].x * dx + cp[0].y * dy) / denominator, 0.0, 1.0);
+        let u = lerp(u0, u1, w);
+        let half_width = self.width(u) * 0.5;
+
+        let pc = lerp_vec(cp[0], cp[3], w);
+        let distance_squared = pc.x * pc.x + pc.y * pc.y;
+        if distance_squared > half_width * half_width || pc.z < z_range.0 || pc.z > z_range.1 {
+            return None;
+        }
+
+        let side = if dx * -pc.y + pc.x * dy > 0.0 { 1.0 } else { -1.0 };
+
+        Some((pc.z, u, side * distance_squared.sqrt() / half_width))
+    }
+}
+
+impl Hitable for Curve {
+    fn hit(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
+        let length = ray.direction.len();
+        let direction = ray.direction / length;
+        let frame = Onb::from_w(direction);
+
+        let mut cp = [Vec3::zero(); 4];
+        for (local, point) in cp.iter_mut().zip(self.points.iter()) {
+            *local = frame.to_local(*point - ray.origin);
+        }
+
+        let (z, u, offset) = self.recursive_hit(&cp, 0.0, 1.0, self.depth, (t_range.start * length, t_range.end * length))?;
+        let derivative = evaluate_derivative(&self.points, u);
+        let tangent = derivative.normalize();
+
+        let (t, normal) = match self.kind {
+            CurveType::Flat => (z / length, -direction),
+            CurveType::Cylinder => {
+                // facing direction and the direction from the curve towards
+                // the ray, both perpendicular to the curve
+                let facing = (-direction - tangent * (-direction).dot(tangent)).normalize();
+                let radial = ray.point_at_parameter(z / length) - evaluate(&self.points, u);
+                let radial = radial - tangent * radial.dot(tangent) - facing * radial.dot(facing);
+                let across = if radial.len_squared() > 0.0 { radial.normalize() } else { Vec3::zero() };
+                let bulge = (1.0 - offset * offset).max(0.0).sqrt();
+
+                // the hit lies on the curve center plane, move it onto the tube
+                let t = (z - bulge * self.width(u) * 0.5) / length;
+                let t = if t > t_range.start { t } else { z / length };
+
+                (t, (facing * bulge + across * offset.abs()).normalize())
+            }
+        };
+
+        // v runs across the width, perpendicular to the curve and the normal
+        let bitangent = normal.cross(tangent) * self.width(u);
+
+        Some(HitRecord::new(t, ray.point_at_parameter(t), normal, &*self.material, u, (offset + 1.0) * 0.5).with_frame(derivative, bitangent))
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        let half_width = self.widths.0.max(self.widths.1) * 0.5;
+        let min = self.points.iter().fold(Vec3::max(), |m, p| Vec3::new(m.x.min(p.x), m.y.min(p.y), m.z.min(p.z)));
+        let max = self.points.iter().fold(Vec3::min(), |m, p| Vec3::new(m.x.max(p.x), m.y.max(p.y), m.z.max(p.z)));
+
+        Some(AABB::new(min - half_width, max + half_width))
+    }
+}
+
+// curves as read from a curve list file: control points, start and end width
+pub struct CurveSet {
+    pub curves: Vec<([Vec3; 4], f32, f32)>
+}
+
+impl CurveSet {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        CurveSet::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut curves = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let values = line.split_ascii_whitespace()
+                .map(|value| value.parse::<f32>().map_err(|_| invalid_data(format!("invalid number '{}' on curve line {}", value, number + 1))))
+                .collect::<io::Result<Vec<f32>>>()?;
+
+            if values.len() != 13 && values.len() != 14 {
+                return Err(invalid_data(format!("curve line {} has {} values, expected 13 or 14", number + 1, values.len())));
+            }
+
+            let point = |i: usize| Vec3::new(values[3 * i], values[3 * i + 1], values[3 * i + 2]);
+            let width1 = if values.len() == 14 { values[13] } else { values[12] };
+
+            curves.push(([point(0), point(1), point(2), point(3)], values[12], width1));
+        }
+
+        Ok(CurveSet { curves })
+    }
+
+    pub fn hitables(&self, kind: CurveType, material: Arc<Material>) -> Vec<Box<Hitable>> {
+        self.curves.iter()
+            .map(|(points, width0, width1)| Box::new(Curve::new(*points, (*width0, *width1), kind, material.clone())) as Box<Hitable>)
+            .collect()
+    }
+}
+
+fn evaluate(cp: &[Vec3; 4], u: f32) -> Vec3 {
+    let a = lerp_vec(cp[0], cp[1], u);
+    let b = lerp_vec(cp[1], cp[2], u);
+    let c = lerp_vec(cp[2], cp[3], u);
+
+    lerp_vec(lerp_vec(a, b, u), lerp_vec(b, c, u), u)
+}
+
+fn evaluate_derivative(cp: &[Vec3; 4], u: f32) -> Vec3 {
+    let a = lerp_vec(cp[0], cp[1], u);
+    let b = lerp_vec(cp[1], cp[2], u);
+    let c = lerp_vec(cp[2], cp[3], u);
+
+    (lerp_vec(b, c, u) - lerp_vec(a, b, u)) * 3.0
+}
+
+// de Casteljau split at the middle, the halves share the fourth point
+fn subdivide(cp: &[Vec3; 4]) -> [Vec3; 7] {
+    let a = (cp[0] + cp[1]) * 0.5;
+    let b = (cp[1] + cp[2]) * 0.5;
+    let c = (cp[2] + cp[3]) * 0.5;
+    let ab = (a + b) * 0.5;
+    let bc = (b + c) * 0.5;
+
+    [cp[0], a, ab, (ab + bc) * 0.5, bc, c, cp[3]]
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn lerp_vec(a: Vec3, b: Vec3, t: f32) -> Vec3 {
+    a + (b - a) * t
+}
+
+fn clamp(x: f32, min: f32, max: f32) -> f32 {
+    x.max(min).min(max)
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::PassThrough;
+
+    fn unused() -> Arc<Material> {
+        Arc::new(PassThrough::new(Vec3::zero()))
+    }
+
+    // a straight curve along x from -1 to 1 in the xz plane
+    fn straight(widths: (f32, f32), kind: CurveType) -> Curve {
+        let points = [Vec3::new(-1.0, 0.0, 0.0), Vec3::new(-1.0 / 3.0, 0.0, 0.0), Vec3::new(1.0 / 3.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)];
+        Curve::new(points, widths, kind, unused())
+    }
+
+    fn down(x: f32, z: f32) -> Ray {
+        Ray::new(Vec3::new(x, 5.0, z), Vec3::new(0.0, -2.0, 0.0), 0.0)
+    }
+
+    #[test]
+    fn flat_curves_face_the_ray() {
+        let curve = straight((0.2, 0.2), CurveType::Flat);
+        assert_eq!(curve.depth, 0);
+
+        let record = curve.hit(&down(0.5, 0.05), 0.001..std::f32::MAX).unwrap();
+        assert!((record.t - 2.5).abs() < 1e-4);
+        assert!((record.u - 0.75).abs() < 1e-4);
+        assert!((record.v - 0.25).abs() < 1e-3 || (record.v - 0.75).abs() < 1e-3);
+        assert!((record.normal - Vec3::new(0.0, 1.0, 0.0)).len() < 1e-5);
+        assert!((record.tangent.unwrap().normalize() - Vec3::new(1.0, 0.0, 0.0)).len() < 1e-4);
+
+        assert!(curve.hit(&down(0.5, 0.15), 0.001..std::f32::MAX).is_none());
+        assert!(curve.hit(&down(1.2, 0.0), 0.001..std::f32::MAX).is_none());
+    }
+
+    #[test]
+    fn widths_taper_along_the_curve() {
+        let curve = straight((0.4, 0.0), CurveType::Flat);
+
+        assert!(curve.hit(&down(-0.9, 0.15), 0.001..std::f32::MAX).is_some());
+        assert!(curve.hit(&down(0.9, 0.15), 0.001..std::f32::MAX).is_none());
+        assert!(curve.hit(&down(0.9, 0.0), 0.001..std::f32::MAX).is_some());
+    }
+
+    #[test]
+    fn cylinder_curves_are_round() {
+        let curve = straight((0.2, 0.2), CurveType::Cylinder);
+
+        // the center is hit on top of the tube, the sides bend away
+        let top = curve.hit(&down(0.0, 0.0), 0.001..std::f32::MAX).unwrap();
+        assert!((top.p.y - 0.1).abs() < 1e-4);
+        assert!((top.normal - Vec3::new(0.0, 1.0, 0.0)).len() < 1e-3);
+
+        let side = curve.hit(&down(0.0, 0.08), 0.001..std::f32::MAX).unwrap();
+        assert!(side.normal.z > 0.7 && side.normal.y > 0.0);
+        assert!((side.normal.len() - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn bent_curves_are_subdivided() {
+        let points = [Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 2.0), Vec3::new(2.0, 0.0, 2.0), Vec3::new(2.0, 0.0, 0.0)];
+        let curve = Curve::new(points, (0.05, 0.05), CurveType::Flat, unused());
+        assert!(curve.depth > 2);
+
+        // rays through points on the curve hit it right there
+        for &u in [0.1, 0.3, 0.5, 0.8].iter() {
+            let on_curve = evaluate(&points, u);
+            let record = curve.hit(&down(on_curve.x, on_curve.z), 0.001..std::f32::MAX).unwrap();
+            assert!((record.u - u).abs() < 0.02, "{} vs {}", record.u, u);
+            assert!((record.p - on_curve).len() < 0.03);
+        }
+
+        let bbox = curve.bounding_box().unwrap();
+        assert!(bbox.min.z <= -0.025 && bbox.max.z >= 1.5);
+        assert!(curve.hit(&down(1.0, 0.5), 0.001..std::f32::MAX).is_none());
+    }
+
+    #[test]
+    fn curve_files_are_parsed() {
+        let set = CurveSet::parse("# two hairs\n0 0 0  0 1 0  0 2 0  0 3 0  0.1\n\n1 0 0 1 1 0 1 2 0 1 3 0 0.2 0.05 # tapered\n").unwrap();
+
+        assert_eq!(set.curves.len(), 2);
+        assert_eq!(set.curves[0].1, 0.1);
+        assert_eq!(set.curves[0].2, 0.1);
+        assert_eq!((set.curves[1].1, set.curves[1].2), (0.2, 0.05));
+        assert_eq!(set.curves[1].0[3], Vec3::new(1.0, 3.0, 0.0));
+        assert_eq!(set.hitables(CurveType::Cylinder, unused()).len(), 2);
+
+        let error = CurveSet::parse("0 0 0 0 1 0 0 2 0 0 3 0\n").err().unwrap();
+        assert_eq!(error.to_string(), "curve line 1 has 12 values, expected 13 or 14");
+
+        let error = CurveSet::parse("\n0 0 0 0 1 0 0 2 0 0 3 x 0.1\n").err().unwrap();
+        assert_eq!(error.to_string(), "invalid number 'x' on curve line 2");
+    }
+}
@@ -0,0 +1,374 @@
+//! Import of glTF 2.0 scenes from `.gltf` (with external or data uri
+//! buffers) and `.glb` files.
+//!
+//! The default scene, or the first one, is walked from its root nodes:
+//!
+//! * nodes with a mesh become an `Instance` with the node's world matrix
+//!   around a `Bvh` of the mesh's triangles. Meshes used by several nodes are
+//!   built once and shared. Only triangle list primitives are imported.
+//!   Nodes whose world matrix is not invertible, e.g. scaled to zero to hide
+//!   them, are skipped.
+//! * perspective cameras become a `Camera` looking down the node's -z axis
+//!   with the aspect ratio of the render. Orthographic cameras are skipped.
+//! * metal-roughness materials become a `Principled` material with the base
+//!   color factor and texture, metallic and roughness factors and the
+//!   transmission and ior extensions. Metallic-roughness textures are not
+//!   used. Materials with an emissive factor become a `DiffuseLight`.
+//!
+//! Images have to be PNG files, they are decoded with lodepng.
+
+use crate::vec::Vec3;
+use crate::hitable::Hitable;
+use crate::material::{Material, DiffuseLight};
+use crate::principled::Principled;
+use crate::texture::{Texture, ConstantTexture, ImageTexture, ScaleTexture};
+use crate::mesh::TriangleMesh;
+use crate::instance::{self, Instance};
+use crate::matrix::Mat4;
+use crate::camera::Camera;
+
+use gltf::Gltf;
+use gltf::camera::Projection;
+use gltf::mesh::Mode;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub struct GltfScene {
+    pub hitables: Vec<Box<Hitable>>,
+    pub cameras: Vec<Camera>
+}
+
+impl GltfScene {
+    pub fn load<P: AsRef<Path>>(path: P, aspect: f32) -> io::Result<Self> {
+        let path = path.as_ref();
+        let gltf = Gltf::open(path).map_err(|e| invalid_data(format!("can not read gltf file {}: {}", path.display(), e)))?;
+        let directory = path.parent().map_or(PathBuf::new(), |p| p.to_path_buf());
+
+        let mut importer = Importer {
+            buffers: load_buffers(&gltf, &directory)?,
+            directory,
+            textures: HashMap::new(),
+            materials: HashMap::new(),
+            meshes: HashMap::new()
+        };
+
+        let scene = gltf.default_scene().or_else(|| gltf.scenes().next())
+            .ok_or_else(|| invalid_data("gltf file contains no scene".to_string()))?;
+
+        let mut result = GltfScene { hitables: Vec::new(), cameras: Vec::new() };
+        for node in scene.nodes() {
+            importer.node(&node, Mat4::identity(), aspect, &mut result)?;
+        }
+
+        Ok(result)
+    }
+}
+
+struct Importer {
+    buffers: Vec<Vec<u8>>,
+    directory: PathBuf,
+    textures: HashMap<usize, Arc<Texture>>,
+    materials: HashMap<Option<usize>, Arc<Material>>,
+    meshes: HashMap<usize, Option<Arc<Hitable>>>
+}
+
+impl Importer {
+    fn node(&mut self, node: &gltf::Node, parent: Mat4, aspect: f32, scene: &mut GltfScene) -> io::Result<()> {
+        let world = parent * Mat4::from_columns(node.transform().matrix());
+
+        if let Some(mesh) = node.mesh() {
+            if let Some(hitable) = self.mesh(&mesh)? {
+                if let Some(instance) = Instance::new(hitable, world) {
+                    scene.hitables.push(Box::new(instance));
+                }
+            }
+        }
+
+        if let Some(camera) = node.camera() {
+            if let Projection::Perspective(perspective) = camera.projection() {
+                let origin = world.transform_point(Vec3::zero());
+                let forward = world.transform_vector(Vec3::new(0.0, 0.0, -1.0));
+                let up = world.transform_vector(Vec3::new(0.0, 1.0, 0.0));
+                let vfov = perspective.yfov().to_degrees();
+
+                scene.cameras.push(Camera::new(origin, origin + forward, up, vfov, aspect, 0.0, 1.0, 0.0, 1.0));
+            }
+        }
+
+        for child in node.children() {
+            self.node(&child, world, aspect, scene)?;
+        }
+
+        Ok(())
+    }
+
+    fn mesh(&mut self, mesh: &gltf::Mesh) -> io::Result<Option<Arc<Hitable>>> {
+        if let Some(hitable) = self.meshes.get(&mesh.index()) {
+            return Ok(hitable.clone());
+        }
+
+        let mut triangles: Vec<Box<Hitable>> = Vec::new();
+
+        for primitive in mesh.primitives() {
+            if primitive.mode() != Mode::Triangles {
+                continue;
+            }
+
+            let buffers = &self.buffers;
+            let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| &data[..]));
+
+            let positions: Vec<Vec3> = reader.read_positions()
+                .ok_or_else(|| invalid_data(format!("primitive {} of mesh {} has no positions", primitive.index(), mesh.index())))?
+                .map(|p| Vec3::new(p[0], p[1], p[2]))
+                .collect();
+            let normals = reader.read_normals()
+                .map(|normals| normals.map(|n| Vec3::new(n[0], n[1], n[2])).collect());
+            // glTF puts the uv origin at the top left, image textures at the bottom left
+            let uvs = reader.read_tex_coords(0)
+                .map(|uvs| uvs.into_f32().map(|uv| (uv[0], 1.0 - uv[1])).collect());
+            let indices: Vec<usize> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().map(|i| i as usize).collect(),
+                None => (0..positions.len()).collect()
+            };
+
+            if indices.len() < 3 {
+                continue;
+            }
+
+            let faces = indices.chunks(3).filter(|c| c.len() == 3).map(|c| [c[0], c[1], c[2]]).collect();
+            let triangle_mesh = TriangleMesh { positions, normals, uvs, colors: None, faces };
+            triangle_mesh.validate()?;
+
+            let material = self.material(&primitive.material())?;
+            triangles.extend(triangle_mesh.triangles(material));
+        }
+
+        let hitable: Option<Arc<Hitable>> = if triangles.is_empty() {
+            None
+        } else {
+            Some(instance::shared(triangles))
+        };
+
+        self.meshes.insert(mesh.index(), hitable.clone());
+        Ok(hitable)
+    }
+
+    fn material(&mut self, material: &gltf::Material) -> io::Result<Arc<Material>> {
+        if let Some(material) = self.materials.get(&material.index()) {
+            return Ok(material.clone());
+        }
+
+        let [r, g, b] = material.emissive_factor();
+        let emission = Vec3::new(r, g, b) * material.emissive_strength().unwrap_or(1.0);
+
+        let result: Arc<Material> = if emission != Vec3::zero() {
+            Arc::new(DiffuseLight::new(Arc::new(ConstantTexture::new(emission))))
+        } else {
+            let pbr = material.pbr_metallic_roughness();
+            let [r, g, b, _] = pbr.base_color_factor();
+            let factor = Vec3::new(r, g, b);
+
+            let base_color: Arc<Texture> = match pbr.base_color_texture() {
+                Some(info) => Arc::new(ScaleTexture::new(self.texture(&info.texture())?, factor)),
+                None => Arc::new(ConstantTexture::new(factor))
+            };
+
+            let mut principled = Principled::new(base_color)
+                .with_metallic(pbr.metallic_factor())
+                .with_roughness(pbr.roughness_factor());
+
+            if let Some(transmission) = material.transmission() {
+                principled = principled.with_transmission(transmission.transmission_factor(), material.ior().unwrap_or(1.5));
+            }
+
+            Arc::new(principled)
+        };
+
+        self.materials.insert(material.index(), result.clone());
+        Ok(result)
+    }
+
+    fn texture(&mut self, texture: &gltf::Texture) -> io::Result<Arc<Texture>> {
+        let image = texture.source();
+        if let Some(texture) = self.textures.get(&image.index()) {
+            return Ok(texture.clone());
+        }
+
+        let bytes = match image.source() {
+            gltf::image::Source::View { view, .. } => {
+                let buffer = &self.buffers[view.buffer().index()];
+                buffer.get(view.offset()..view.offset() + view.length())
+                    .ok_or_else(|| invalid_data(format!("buffer view {} is out of range", view.index())))?
+                    .to_vec()
+            },
+            gltf::image::Source::Uri { uri, .. } => read_uri(uri, &self.directory)?
+        };
+
+        let bitmap = lodepng::decode24(&bytes)
+            .map_err(|e| invalid_data(format!("can not decode image {} (only PNG is supported): {}", image.index(), e)))?;
+        let result: Arc<Texture> = Arc::new(ImageTexture::new(bitmap.buffer, bitmap.width, bitmap.height));
+
+        self.textures.insert(image.index(), result.clone());
+        Ok(result)
+    }
+}
+
+fn load_buffers(gltf: &Gltf, directory: &Path) -> io::Result<Vec<Vec<u8>>> {
+    let mut buffers = Vec::new();
+
+    for buffer in gltf.buffers() {
+        let data = match buffer.source() {
+            gltf::buffer::Source::Bin => gltf.blob.clone()
+                .ok_or_else(|| invalid_data("gltf buffer refers to a missing binary chunk".to_string()))?,
+            gltf::buffer::Source::Uri(uri) => read_uri(uri, directory)?
+        };
+
+        if data.len() < buffer.length() {
+            return Err(invalid_data(format!(
+                "gltf buffer {} has {} bytes, expected {}", buffer.index(), data.len(), buffer.length()
+            )));
+        }
+
+        buffers.push(data);
+    }
+
+    Ok(buffers)
+}
+
+// base64 data uris or paths relative to the gltf file
+fn read_uri(uri: &str, directory: &Path) -> io::Result<Vec<u8>> {
+    if uri.starts_with("data:") {
+        let comma = uri.find(',').ok_or_else(|| invalid_data("malformed data uri".to_string()))?;
+        if !uri[..comma].ends_with(";base64") {
+            return Err(invalid_data("only base64 data uris are supported".to_string()));
+        }
+
+        return decode_base64(&uri[comma + 1..]);
+    }
+
+    fs::read(directory.join(percent_decode(uri)))
+}
+
+fn decode_base64(text: &str) -> io::Result<Vec<u8>> {
+    let value = |c: u8| match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' | b'-' => Some(62),
+        b'/' | b'_' => Some(63),
+        _ => None
+    };
+
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let mut bits = 0u32;
+    let mut count = 0;
+
+    for c in text.bytes().take_while(|&c| c != b'=') {
+        let v = value(c).ok_or_else(|| invalid_data(format!("invalid base64 character '{}'", c as char)))?;
+        bits = bits << 6 | v as u32;
+        count += 6;
+
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+        }
+    }
+
+    Ok(bytes)
+}
+
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let hex = if bytes[i] == b'%' && i + 2 < bytes.len() {
+            std::str::from_utf8(&bytes[i + 1..i + 3]).ok().and_then(|h| u8::from_str_radix(h, 16).ok())
+        } else {
+            None
+        };
+
+        match hex {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            },
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+
+    // positions and normals of one triangle in the xy plane
+    const BUFFER: &str = "AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAAAAAAAAAAAAIA/AAAAAAAAAAAAAIA/AAAAAAAAAAAAAIA/";
+
+    // the triangle placed once per node scale
+    fn triangle_gltf(normal_count: usize, scales: &[f32]) -> String {
+        let nodes: Vec<String> = scales.iter().map(|s| format!(r#"{{"mesh": 0, "scale": [{0}, {0}, {0}]}}"#, s)).collect();
+        let roots: Vec<String> = (0..scales.len()).map(|i| i.to_string()).collect();
+
+        format!(r#"{{
+            "asset": {{"version": "2.0"}},
+            "scene": 0,
+            "scenes": [{{"nodes": [{}]}}],
+            "nodes": [{}],
+            "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0, "NORMAL": 1}}}}]}}],
+            "accessors": [
+                {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0]}},
+                {{"bufferView": 0, "byteOffset": 36, "componentType": 5126, "count": {}, "type": "VEC3"}}
+            ],
+            "bufferViews": [{{"buffer": 0, "byteLength": 72}}],
+            "buffers": [{{"byteLength": 72, "uri": "data:application/octet-stream;base64,{}"}}]
+        }}"#, roots.join(", "), nodes.join(", "), normal_count, BUFFER)
+    }
+
+    fn load(name: &str, gltf: &str) -> io::Result<GltfScene> {
+        let path = std::env::temp_dir().join(format!("{}-{}.gltf", name, std::process::id()));
+        fs::write(&path, gltf)?;
+        let scene = GltfScene::load(&path, 1.0);
+        fs::remove_file(&path)?;
+        scene
+    }
+
+    #[test]
+    fn nodes_place_instances_of_their_mesh() {
+        let scene = load("instances", &triangle_gltf(3, &[1.0, 2.0])).unwrap();
+        assert_eq!(scene.hitables.len(), 2);
+
+        // only the doubled triangle reaches (0.8, 0.8)
+        let ray = Ray::new(Vec3::new(0.8, 0.8, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hits = scene.hitables.iter().filter(|h| h.hit(&ray, 0.001..std::f32::MAX).is_some()).count();
+        assert_eq!(hits, 1);
+    }
+
+    #[test]
+    fn zero_scale_nodes_are_skipped() {
+        let scene = load("zero-scale", &triangle_gltf(3, &[0.0, 1.0])).unwrap();
+        assert_eq!(scene.hitables.len(), 1);
+    }
+
+    #[test]
+    fn mismatched_attribute_counts_are_rejected() {
+        let err = load("mismatch", &triangle_gltf(2, &[1.0])).err().expect("mismatched normals were accepted");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("3 vertices but 2 normals"), "{}", err);
+    }
+}
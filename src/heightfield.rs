@@ -0,0 +1,406 @@
+//! Heightfield terrain over a regular grid of height samples.
+//!
+//! Samples are stored with x varying fastest and span the xz extent of the
+//! field, heights from 0 to 1 are scaled to its y extent. Every grid cell is
+//! split into two triangles with normals interpolated from the vertex
+//! normals, u and v run from 0 to 1 across x and z.
+//!
+//! Rays are traversed through a min/max mipmap over the cells: level 0 holds
+//! the height range of each cell, every further level the range of 2x2
+//! blocks of the level below, until a single node covers the whole field.
+
+use crate::hitable::Hitable;
+use crate::hitable::HitRecord;
+use crate::aabb::AABB;
+use crate::vec::Vec3;
+use crate::material::Material;
+use crate::ray::Ray;
+
+use std::cmp::Ordering;
+use std::io;
+use std::sync::Arc;
+
+// mipmap levels of the largest supported field, enough for any usize resolution
+const MAX_LEVELS: usize = 8 * std::mem::size_of::<usize>() + 1;
+
+struct Level {
+    nx: usize,
+    nz: usize,
+    ranges: Vec<(f32, f32)>
+}
+
+pub struct Heightfield {
+    nx: usize,
+    nz: usize,
+    points: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    levels: Vec<Level>,
+    corner: Vec3,
+    cell_size: (f32, f32),
+    material: Arc<Material>
+}
+
+impl Heightfield {
+    // corner is the minimum of the field and size its extent, nx and nz are
+    // the number of samples along x and z, at least two each
+    pub fn new(heights: Vec<f32>, nx: usize, nz: usize, corner: Vec3, size: Vec3, material: Arc<Material>) -> Self {
+        assert!(nx >= 2 && nz >= 2, "heightfield needs at least 2x2 samples");
+        assert_eq!(heights.len(), nx * nz, "heightfield sample count does not match the resolution");
+
+        let cell_size = (size.x / (nx - 1) as f32, size.z / (nz - 1) as f32);
+
+        let mut points = Vec::with_capacity(nx * nz);
+        for j in 0..nz {
+            for i in 0..nx {
+                points.push(corner + Vec3::new(
+                    i as f32 * cell_size.0,
+                    heights[j * nx + i] * size.y,
+                    j as f32 * cell_size.1
+                ));
+            }
+        }
+
+        let normals = vertex_normals(&points, nx, nz);
+        let levels = min_max_levels(&points, nx, nz);
+
+        Heightfield { nx, nz, points, normals, levels, corner, cell_size, material }
+    }
+
+    // samples a function of the world x and z on the grid
+    pub fn from_function<F>(nx: usize, nz: usize, corner: Vec3, size: Vec3, f: F, material: Arc<Material>) -> Self
+        where F: Fn(f32, f32) -> f32 {
+
+        let mut heights = Vec::with_capacity(nx * nz);
+        for j in 0..nz {
+            for i in 0..nx {
+                let x = corner.x + size.x * i as f32 / (nx - 1) as f32;
+                let z = corner.z + size.z * j as f32 / (nz - 1) as f32;
+                heights.push(f(x, z));
+            }
+        }
+
+        Heightfield::new(heights, nx, nz, corner, size, material)
+    }
+
+    // one sample per pixel of a grayscale image, color images use the mean
+    // of their channels. Image rows run along z.
+    pub fn from_image(filepath: &str, corner: Vec3, size: Vec3, material: Arc<Material>) -> io::Result<Self> {
+        let bitmap = lodepng::decode24_file(filepath)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("can not decode {}: {}", filepath, e)))?;
+
+        if bitmap.width < 2 || bitmap.height < 2 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} is smaller than 2x2 pixels", filepath)));
+        }
+
+        let heights = bitmap.buffer.iter()
+            .map(|pixel| (pixel.r as f32 + pixel.g as f32 + pixel.b as f32) / (3.0 * 255.0))
+            .collect();
+
+        Ok(Heightfield::new(heights, bitmap.width, bitmap.height, corner, size, material))
+    }
+
+    fn cell_hit(&self, i: usize, j: usize, ray: &Ray, t_range: &::std::ops::Range<f32>) -> Option<HitRecord> {
+        let v00 = j * self.nx + i;
+        let v10 = v00 + 1;
+        let v01 = v00 + self.nx;
+        let v11 = v01 + 1;
+
+        let mut closest: Option<HitRecord> = None;
+        for triangle in [[v00, v10, v11], [v00, v11, v01]].iter() {
+            let t_end = closest.map_or(t_range.end, |hit| hit.t);
+            if let Some(hit) = self.triangle_hit(*triangle, ray, t_range.start..t_end) {
+                closest = Some(hit);
+            }
+        }
+
+        closest
+    }
+
+    // Moeller-Trumbore
+    fn triangle_hit(&self, vertices: [usize; 3], ray: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
+        let [i0, i1, i2] = vertices;
+        let v0 = self.points[i0];
+        let edge1 = self.points[i1] - v0;
+        let edge2 = self.points[i2] - v0;
+
+        let pvec = ray.direction.cross(edge2);
+        let det = edge1.dot(pvec);
+        if det.abs() < 1e-12 {
+            return None;
+        }
+
+        let inv_det = 1.0 / det;
+        let tvec = ray.origin - v0;
+        let b1 = tvec.dot(pvec) * inv_det;
+        if b1 < 0.0 || b1 > 1.0 {
+            return None;
+        }
+
+        let qvec = tvec.cross(edge1);
+        let b2 = ray.direction.dot(qvec) * inv_det;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return None;
+        }
+
+        let t = edge2.dot(qvec) * inv_det;
+        if t < t_range.start || t > t_range.end {
+            return None;
+        }
+
+        let b0 = 1.0 - b1 - b2;
+        let normal = (self.normals[i0] * b0 + self.normals[i1] * b1 + self.normals[i2] * b2).normalize();
+
+        let p = ray.point_at_parameter(t);
+        let size_x = self.cell_size.0 * (self.nx - 1) as f32;
+        let size_z = self.cell_size.1 * (self.nz - 1) as f32;
+        let u = (p.x - self.corner.x) / size_x;
+        let v = (p.z - self.corner.z) / size_z;
+
+        // u and v move along x and z, the height follows the triangle plane
+        let face = edge1.cross(edge2);
+        let dpdu = Vec3::new(size_x, -face.x / face.y * size_x, 0.0);
+        let dpdv = Vec3::new(0.0, -face.z / face.y * size_z, size_z);
+
+        Some(HitRecord::new(t, p, normal, &*self.material, u, v).with_frame(dpdu, dpdv))
+    }
+
+    // bounds of a mipmap node, padded so flat areas keep a volume
+    fn node_box(&self, level: usize, i: usize, j: usize) -> AABB {
+        let span = 1 << level;
+        let (min_y, max_y) = self.levels[level].ranges[j * self.levels[level].nx + i];
+
+        let x0 = i * span;
+        let z0 = j * span;
+        let x1 = ((i + 1) * span).min(self.nx - 1);
+        let z1 = ((j + 1) * span).min(self.nz - 1);
+
+        AABB::new(
+            Vec3::new(self.corner.x + x0 as f32 * self.cell_size.0 - 0.0001, min_y - 0.0001, self.corner.z + z0 as f32 * self.cell_size.1 - 0.0001),
+            Vec3::new(self.corner.x + x1 as f32 * self.cell_size.0 + 0.0001, max_y + 0.0001, self.corner.z + z1 as f32 * self.cell_size.1 + 0.0001)
+        )
+    }
+}
+
+impl Hitable for Heightfield {
+    fn hit(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
+        let mut closest: Option<HitRecord> = None;
+
+        // every level leaves at most three siblings behind on the stack
+        let mut stack = [(0, 0, 0); 3 * MAX_LEVELS + 1];
+        stack[0] = (self.levels.len() - 1, 0, 0);
+        let mut stack_size = 1;
+
+        while stack_size > 0 {
+            stack_size -= 1;
+            let (level, i, j) = stack[stack_size];
+
+            let t_end = closest.map_or(t_range.end, |hit| hit.t);
+            if self.node_box(level, i, j).intersect(ray, t_range.start, t_end).is_none() {
+                continue;
+            }
+
+            if level == 0 {
+                if let Some(hit) = self.cell_hit(i, j, ray, &(t_range.start..t_end)) {
+                    closest = Some(hit);
+                }
+                continue;
+            }
+
+            // visit the children front to back so hits shrink the range early
+            let below = &self.levels[level - 1];
+            let mut children = [(0.0, 0, 0); 4];
+            let mut child_count = 0;
+            for (ci, cj) in [(2 * i, 2 * j), (2 * i + 1, 2 * j), (2 * i, 2 * j + 1), (2 * i + 1, 2 * j + 1)].iter() {
+                if *ci >= below.nx || *cj >= below.nz {
+                    continue;
+                }
+
+                if let Some((t_enter, _)) = self.node_box(level - 1, *ci, *cj).intersect(ray, t_range.start, t_end) {
+                    children[child_count] = (t_enter, *ci, *cj);
+                    child_count += 1;
+                }
+            }
+
+            children[..child_count].sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+            for &(_, ci, cj) in children[..child_count].iter() {
+                stack[stack_size] = (level - 1, ci, cj);
+                stack_size += 1;
+            }
+        }
+
+        closest
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        let top = self.levels.len() - 1;
+        Some(self.node_box(top, 0, 0))
+    }
+}
+
+// central differences of the heights, one sided at the border
+fn vertex_normals(points: &[Vec3], nx: usize, nz: usize) -> Vec<Vec3> {
+    let mut normals = Vec::with_capacity(nx * nz);
+
+    for j in 0..nz {
+        for i in 0..nx {
+            let left = points[j * nx + i.saturating_sub(1)];
+            let right = points[j * nx + (i + 1).min(nx - 1)];
+            let back = points[j.saturating_sub(1) * nx + i];
+            let front = points[(j + 1).min(nz - 1) * nx + i];
+
+            let dx = (right.y - left.y) / (right.x - left.x);
+            let dz = (front.y - back.y) / (front.z - back.z);
+
+            normals.push(Vec3::new(-dx, 1.0, -dz).normalize());
+        }
+    }
+
+    normals
+}
+
+fn min_max_levels(points: &[Vec3], nx: usize, nz: usize) -> Vec<Level> {
+    let (cells_x, cells_z) = (nx - 1, nz - 1);
+    let mut ranges = Vec::with_capacity(cells_x * cells_z);
+
+    for j in 0..cells_z {
+        for i in 0..cells_x {
+            let corners = [
+                points[j * nx + i].y,
+                points[j * nx + i + 1].y,
+                points[(j + 1) * nx + i].y,
+                points[(j + 1) * nx + i + 1].y
+            ];
+
+            let min = corners.iter().cloned().fold(std::f32::MAX, f32::min);
+            let max = corners.iter().cloned().fold(std::f32::MIN, f32::max);
+            ranges.push((min, max));
+        }
+    }
+
+    let mut levels = vec![Level { nx: cells_x, nz: cells_z, ranges }];
+
+    while levels.last().map_or(false, |level| level.nx > 1 || level.nz > 1) {
+        let below = levels.last().unwrap();
+        let (level_x, level_z) = ((below.nx + 1) / 2, (below.nz + 1) / 2);
+        let mut ranges = Vec::with_capacity(level_x * level_z);
+
+        for j in 0..level_z {
+            for i in 0..level_x {
+                let mut range = (std::f32::MAX, std::f32::MIN);
+
+                for cj in (2 * j)..(2 * j + 2).min(below.nz) {
+                    for ci in (2 * i)..(2 * i + 2).min(below.nx) {
+                        let (min, max) = below.ranges[cj * below.nx + ci];
+                        range = (range.0.min(min), range.1.max(max));
+                    }
+                }
+
+                ranges.push(range);
+            }
+        }
+
+        levels.push(Level { nx: level_x, nz: level_z, ranges });
+    }
+
+    levels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::PassThrough;
+
+    fn unused() -> Arc<Material> {
+        Arc::new(PassThrough::new(Vec3::zero()))
+    }
+
+    fn down(x: f32, z: f32) -> Ray {
+        Ray::new(Vec3::new(x, 10.0, z), Vec3::new(0.0, -1.0, 0.0), 0.0)
+    }
+
+    // the closest hit by testing every cell, without the mipmap
+    fn brute_force_hit<'a>(field: &'a Heightfield, ray: &Ray) -> Option<HitRecord<'a>> {
+        let mut closest: Option<HitRecord> = None;
+        for j in 0..field.nz - 1 {
+            for i in 0..field.nx - 1 {
+                let t_end = closest.map_or(std::f32::MAX, |hit| hit.t);
+                if let Some(hit) = field.cell_hit(i, j, ray, &(0.001..t_end)) {
+                    closest = Some(hit);
+                }
+            }
+        }
+
+        closest
+    }
+
+    fn hills(x: f32, z: f32) -> f32 {
+        0.5 + 0.25 * (x * 1.3).sin() * (z * 0.7).cos()
+    }
+
+    #[test]
+    fn flat_field_reports_height_normal_and_uv() {
+        let field = Heightfield::new(vec![0.5; 9], 3, 3, Vec3::new(-1.0, 0.0, -1.0), Vec3::new(2.0, 4.0, 2.0), unused());
+        let record = field.hit(&down(0.5, -0.5), 0.001..std::f32::MAX).unwrap();
+
+        assert!((record.t - 8.0).abs() < 1e-4);
+        assert!((record.normal - Vec3::new(0.0, 1.0, 0.0)).len() < 1e-5);
+        assert!((record.u - 0.75).abs() < 1e-5 && (record.v - 0.25).abs() < 1e-5);
+
+        assert!(field.hit(&down(1.5, 0.0), 0.001..std::f32::MAX).is_none());
+    }
+
+    #[test]
+    fn mipmap_traversal_finds_the_closest_cell() {
+        // odd resolutions leave partial nodes at the borders
+        let field = Heightfield::from_function(37, 21, Vec3::new(-4.0, 0.0, -3.0), Vec3::new(8.0, 2.0, 6.0), hills, unused());
+
+        for i in 0..200 {
+            let angle = i as f32 * 0.37;
+            let origin = Vec3::new(6.0 * angle.cos(), 1.5 + (i % 7) as f32 * 0.3, 6.0 * angle.sin());
+            let target = Vec3::new((i % 11) as f32 * 0.6 - 3.0, 0.5, (i % 13) as f32 * 0.4 - 2.4);
+            let ray = Ray::new(origin, target - origin, 0.0);
+
+            let traversed = field.hit(&ray, 0.001..std::f32::MAX).map(|record| record.t);
+            let expected = brute_force_hit(&field, &ray).map(|record| record.t);
+
+            assert_eq!(traversed.is_some(), expected.is_some(), "ray {}", i);
+            if let (Some(t), Some(expected)) = (traversed, expected) {
+                assert!((t - expected).abs() < 1e-4, "ray {}: {} vs {}", i, t, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn interpolated_normals_follow_the_slope() {
+        // a ramp rising by one over two units of x
+        let field = Heightfield::from_function(5, 5, Vec3::zero(), Vec3::new(2.0, 1.0, 2.0), |x, _| x / 2.0, unused());
+        let record = field.hit(&down(1.1, 0.7), 0.001..std::f32::MAX).unwrap();
+
+        assert!((record.p.y - 0.55).abs() < 1e-4);
+        assert!((record.normal - Vec3::new(-0.5, 1.0, 0.0).normalize()).len() < 1e-4);
+        assert!(record.tangent.unwrap().dot(record.normal).abs() < 1e-4 && record.bitangent.unwrap().dot(record.normal).abs() < 1e-4);
+    }
+
+    #[test]
+    fn invalid_values_do_not_panic() {
+        let mut heights: Vec<f32> = (0..64).map(|i| (i % 8) as f32 / 8.0).collect();
+        heights[10] = std::f32::NAN;
+        heights[40] = std::f32::INFINITY;
+        let field = Heightfield::new(heights, 8, 8, Vec3::zero(), Vec3::new(1.0, 1.0, 1.0), unused());
+
+        for i in 0..64 {
+            field.hit(&Ray::new(Vec3::new(-1.0, 2.0, (i as f32 + 0.5) / 64.0), Vec3::new(1.0, -1.0, 0.0), 0.0), 0.001..std::f32::MAX);
+        }
+
+        // a NaN range start carries through the box tests into the entry distances
+        field.hit(&down(0.5, 0.5), std::f32::NAN..std::f32::MAX);
+    }
+
+    // decoding itself trips a debug assertion inside lodepng, only the
+    // error path can be checked in test builds
+    #[test]
+    fn missing_images_are_an_error() {
+        let result = Heightfield::from_image("missing.png", Vec3::zero(), Vec3::new(1.0, 1.0, 1.0), unused());
+        assert_eq!(result.err().unwrap().kind(), io::ErrorKind::InvalidData);
+    }
+}
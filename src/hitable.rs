@@ -1,317 +1,1124 @@
-use crate::ray::Ray;
-use crate::vec::Vec3;
-use crate::material::Material;
-use crate::aabb::AABB;
-use crate::texture::Texture;
-use crate::material::Isotropic;
-
-use std::sync::Arc;
-use rand::prelude::*;
-
-#[derive(Clone, Copy)]
-pub struct HitRecord<'a> {
-    pub t: f32,
-    pub p: Vec3,
-    pub u: f32,
-    pub v: f32,
-    pub normal: Vec3,
-    pub material: &'a Material
-}
-
-impl<'a> HitRecord<'a> {
-    pub fn new(t: f32, p: Vec3, n: Vec3, material: &'a Material, u: f32, v: f32) -> Self {
-        HitRecord { t, p, normal: n, material, u, v }
-    }
-}
-
-pub trait Hitable: Send + Sync {
-    fn hit(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord>;
-    fn bounding_box(&self) -> Option<AABB>;
-}
-
-pub struct HitableList {
-  list: Vec<Box<Hitable>>
-}
-
-impl HitableList {
-  pub fn new() -> Self {
-    HitableList { list: Vec::new() }
-  }
-
-  pub fn from_list(list: Vec<Box<Hitable>>) -> Self {
-    HitableList { list }
-  }
-
-  pub fn push(&mut self, hitable: Box<Hitable>) {
-    self.list.push(hitable);
-  }
-}
-
-impl Hitable for HitableList {
-  fn hit(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
-    let mut current = None;
-    let mut closest_so_far = t_range.end;
-
-    for hitable in &(self.list) {
-      let hr = hitable.hit(ray, t_range.start..closest_so_far);
-      if let Some(HitRecord {t, ..}) = hr {
-        closest_so_far = t;
-        current = hr;
-      }
-    }
-    
-    return current;
-  }
-
-  fn bounding_box(&self) -> Option<AABB> {
-    if self.list.len() < 1 {
-      return None;
-    }
-
-    let mut surrounding_box: AABB;
-    let first = self.list[0].bounding_box();
-
-    match first {
-      Some(b) => surrounding_box = b,
-      None => return None
-    }
-
-    for i in 1..self.list.len() {
-      if let Some(bbox) = self.list[i].bounding_box() {
-        surrounding_box = AABB::surrounding_box(&bbox, &surrounding_box);
-      } else {
-        return None;
-      }
-    }
-
-    Some(surrounding_box)
-  }
-}
-
-pub struct FlipNormal {
-  pub hitable: Box<Hitable>
-}
-
-impl FlipNormal {
-  pub fn new(hitable: Box<Hitable>) -> Self {
-    FlipNormal { hitable }
-  }
-}
-
-impl Hitable for FlipNormal {
-   fn hit(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
-     if let Some(mut hit) = self.hitable.hit(ray, t_range) {
-       hit.normal = hit.normal * -1.0;
-       return Some(hit);
-     }
-     
-     None
-   }
-
-   fn bounding_box(&self) -> Option<AABB> {
-     self.hitable.bounding_box()
-   }
-}
-
-pub struct Translate {
-  offset: Vec3,
-  hitable: Arc<Hitable>
-}
-
-impl Translate {
-  pub fn new(hitable: Arc<Hitable>, offset: Vec3) -> Self {
-    Translate { offset, hitable }
-  }
-}
-
-impl Hitable for Translate {
-  fn hit(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
-    let ray_moved = Ray::new(ray.origin - self.offset, ray.direction, ray.time);
-    if let Some(hit)  = self.hitable.hit(&ray_moved, t_range) {
-      return Some(HitRecord::new(
-          hit.t, 
-          hit.p + self.offset,
-          hit.normal, 
-          hit.material,
-          hit.u, 
-          hit.v
-        ));
-    }
-
-    None
-  }
-
-  fn bounding_box(&self) -> Option<AABB> {
-    if let Some(bbox) = self.hitable.bounding_box() {
-      return Some(AABB::new(bbox.min + self.offset, bbox.max + self.offset));
-    }
-    None
-  }
-}
-
-pub struct RotateY {
-  hitable: Arc<Hitable>,
-  bbox: Option<AABB>,
-  sin_theta: f32,
-  cos_theta: f32
-}
-
-impl RotateY {
-  pub fn new(hitable: Arc<Hitable>, angle: f32) -> Self {
-    let radians = (std::f32::consts::PI / 180.0) * angle;
-    let sin_theta = radians.sin();
-    let cos_theta = radians.cos();
-
-    let bbox = hitable.bounding_box().unwrap();
-    let mut min = Vec3::max();
-    let mut max = Vec3::min();
-
-    for i in 0..2 {
-      for j in 0..2 {
-        for k in 0..2 {
-          let x = i as f32 * bbox.max.x + (1 - i) as f32 * bbox.min.x;
-          let y = j as f32 * bbox.max.y + (1 - j) as f32 * bbox.min.y;
-          let z = k as f32 * bbox.max.z + (1 - k) as f32 * bbox.min.z;
-
-          let newx = cos_theta * x + sin_theta * z;
-          let newz = -sin_theta * x + cos_theta * z;
-
-          let tester = Vec3::new(newx, y, newz);
-
-          set_min_max(&mut min, &mut max, &tester);
-        }
-      }
-    }
-
-    RotateY { hitable, sin_theta, cos_theta, bbox: Some(AABB::new(min, max)) }
-  }
-}
-
-impl Hitable for RotateY {
-  fn hit(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
-    let mut origin: Vec3 = ray.origin;
-    let mut direction: Vec3 = ray.direction;
-
-    origin.x = self.cos_theta * ray.origin.x - self.sin_theta * ray.origin.z;
-    origin.z = self.sin_theta * ray.origin.x + self.cos_theta * ray.origin.z;
-
-    direction.x = self.cos_theta * ray.direction.x - self.sin_theta * ray.direction.z;
-    direction.z = self.sin_theta * ray.direction.x + self.cos_theta * ray.direction.z;
-
-    let ray_rotated = Ray::new(origin, direction, ray.time);
-
-    if let Some(hitRecord) = self.hitable.hit(&ray_rotated, t_range) {
-      let mut p: Vec3 = hitRecord.p;
-      let mut normal: Vec3 = hitRecord.normal;
-      p.x = self.cos_theta * hitRecord.p.x + self.sin_theta * hitRecord.p.z;
-      p.y = -self.sin_theta * hitRecord.p.x + self.cos_theta * hitRecord.p.z;
-
-      normal.x = self.cos_theta * hitRecord.normal.x + self.sin_theta * hitRecord.p.z;
-      normal.z = -self.sin_theta * hitRecord.normal.x + self.cos_theta * hitRecord.p.z;
-
-      return Some(HitRecord::new(
-        hitRecord.t,
-        p,
-        normal,
-        hitRecord.material,
-        hitRecord.u,
-        hitRecord.v
-      ));
-    }
-    None
-  }
-
-  fn bounding_box(&self) -> Option<AABB> {
-    self.bbox
-  }
-}
-
-pub struct ConstantMedium {
-  boundary: Arc<Hitable>,
-  density: f32,
-  phase_function: Isotropic
-}
-
-impl ConstantMedium {
-  pub fn new(boundary: Arc<Hitable>, density: f32, texture: Arc<Texture>) -> Self {
-    ConstantMedium { boundary, density, phase_function: Isotropic::new(texture) }
-  }
-}
-
-impl Hitable for ConstantMedium {
-  fn hit(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
-    if let Some(record1) = self.boundary.hit(ray, std::f32::MIN..std::f32::MAX) {
-      if let Some(record2) = self.boundary.hit(ray, record1.t+0.0001..std::f32::MAX) {
-        let mut tmin = record1.t;
-        let mut tmax = record2.t;
-
-        if tmin < t_range.start {
-          tmin = t_range.start;
-        }
-
-        if tmax > t_range.end {
-          tmax = t_range.end;
-        }
-
-        if tmin >= tmax {
-          return None;
-        }
-
-        if tmin < 0.0 {
-          tmin = 0.0;
-        }
-
-        let distance_inside_boundary = (tmax - tmin) * ray.direction.len();
-        let hit_distance = -(1.0 / self.density) * random::<f32>().log2();
-
-        if hit_distance < distance_inside_boundary {
-          let t = record1.t + hit_distance / ray.direction.len();
-          let p = ray.point_at_parameter(t);
-          let normal = Vec3::new(1.0, 0.0, 0.0);
-          
-          return Some(HitRecord::new(
-            t,
-            p,
-            normal,
-            &self.phase_function,
-            0.0,
-            0.0
-          ));
-        }
-
-        return None;
-      }
-    }
-    None
-  }
-
-  fn bounding_box(&self) -> Option<AABB> {
-    self.boundary.bounding_box()
-  }
-}
-
-fn set_min_max(min: &mut Vec3, max: &mut Vec3, minMax: &Vec3) {
-  if minMax.x > max.x {
-    max.x = minMax.x;
-  }
-
-  if minMax.y > max.y {
-    max.y = minMax.y;
-  }
-
-  if minMax.z > max.z {
-    max.z = minMax.z;
-  }
-
-  if minMax.x < min.x {
-    min.x = minMax.x;
-  }
-
-  if minMax.y < min.y {
-    min.y = minMax.y;
-  }
-
-  if minMax.z < min.z {
-    min.z = minMax.z;
-  }
-}
\ No newline at end of file
+use crate::ray::Ray;
+use crate::vec::Vec3;
+use crate::material::Material;
+use crate::aabb::AABB;
+use crate::texture::Texture;
+use crate::material::Isotropic;
+use crate::material::{Dielectric, PassThrough, DiffuseLight};
+use crate::voxel_grid::VoxelGrid;
+use crate::texture::ConstantTexture;
+use crate::spectrum;
+use crate::matrix::Mat4;
+
+use std::sync::Arc;
+use rand::prelude::*;
+
+#[derive(Clone, Copy)]
+pub struct HitRecord<'a> {
+    pub t: f32,
+    pub p: Vec3,
+    pub u: f32,
+    pub v: f32,
+    pub normal: Vec3,
+    // normal that materials shade with, the geometric normal unless a normal
+    // or bump map tilted it. `normal` stays the true surface normal, which
+    // decides the side a ray arrives from.
+    pub shading_normal: Vec3,
+    pub material: &'a Material,
+    // shading frame, the derivatives of the position by u and v. They are
+    // not normalized so that steps in u and v map to distances on the
+    // surface. None for surfaces without a parameterization.
+    pub tangent: Option<Vec3>,
+    pub bitangent: Option<Vec3>
+}
+
+impl<'a> HitRecord<'a> {
+    pub fn new(t: f32, p: Vec3, n: Vec3, material: &'a Material, u: f32, v: f32) -> Self {
+        HitRecord { t, p, normal: n, shading_normal: n, material, u, v, tangent: None, bitangent: None }
+    }
+
+    pub fn with_frame(mut self, tangent: Vec3, bitangent: Vec3) -> Self {
+        self.tangent = Some(tangent);
+        self.bitangent = Some(bitangent);
+        self
+    }
+}
+
+pub trait Hitable: Send + Sync {
+    fn hit(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord>;
+    fn bounding_box(&self) -> Option<AABB>;
+
+    // radiance emitted by participating media along the ray segment
+    fn volume_emission(&self, _ray: &Ray, _t_range: ::std::ops::Range<f32>) -> Vec3 {
+        Vec3::zero()
+    }
+
+    fn has_volume_emission(&self) -> bool {
+        false
+    }
+
+    // area sampling hooks for shapes that can be sampled directly, e.g.
+    // lights: the solid angle density of picking direction from origin with
+    // random(), and a random direction from origin towards the surface
+    fn pdf_value(&self, _origin: Vec3, _direction: Vec3) -> f32 {
+        0.0
+    }
+
+    fn random(&self, _origin: Vec3) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
+}
+
+pub struct HitableList {
+  list: Vec<Box<Hitable>>
+}
+
+impl HitableList {
+  pub fn new() -> Self {
+    HitableList { list: Vec::new() }
+  }
+
+  pub fn from_list(list: Vec<Box<Hitable>>) -> Self {
+    HitableList { list }
+  }
+
+  pub fn push(&mut self, hitable: Box<Hitable>) {
+    self.list.push(hitable);
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.list.is_empty()
+  }
+}
+
+impl Hitable for HitableList {
+  fn hit(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
+    let mut current = None;
+    let mut closest_so_far = t_range.end;
+
+    for hitable in &(self.list) {
+      let hr = hitable.hit(ray, t_range.start..closest_so_far);
+      if let Some(HitRecord {t, ..}) = hr {
+        closest_so_far = t;
+        current = hr;
+      }
+    }
+    
+    return current;
+  }
+
+  fn bounding_box(&self) -> Option<AABB> {
+    if self.list.len() < 1 {
+      return None;
+    }
+
+    let mut surrounding_box: AABB;
+    let first = self.list[0].bounding_box();
+
+    match first {
+      Some(b) => surrounding_box = b,
+      None => return None
+    }
+
+    for i in 1..self.list.len() {
+      if let Some(bbox) = self.list[i].bounding_box() {
+        surrounding_box = AABB::surrounding_box(&bbox, &surrounding_box);
+      } else {
+        return None;
+      }
+    }
+
+    Some(surrounding_box)
+  }
+
+  fn volume_emission(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Vec3 {
+    let mut emission = Vec3::zero();
+
+    for hitable in &(self.list) {
+      emission += hitable.volume_emission(ray, t_range.clone());
+    }
+
+    emission
+  }
+
+  fn has_volume_emission(&self) -> bool {
+    self.list.iter().any(|hitable| hitable.has_volume_emission())
+  }
+
+  // picks one of the hitables uniformly
+  fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f32 {
+    if self.list.is_empty() {
+      return 0.0;
+    }
+
+    let sum: f32 = self.list.iter().map(|hitable| hitable.pdf_value(origin, direction)).sum();
+    sum / self.list.len() as f32
+  }
+
+  fn random(&self, origin: Vec3) -> Vec3 {
+    if self.list.is_empty() {
+      return Vec3::new(1.0, 0.0, 0.0);
+    }
+
+    let index = ((random::<f32>() * self.list.len() as f32) as usize).min(self.list.len() - 1);
+    self.list[index].random(origin)
+  }
+}
+
+pub struct FlipNormal {
+  pub hitable: Box<Hitable>
+}
+
+impl FlipNormal {
+  pub fn new(hitable: Box<Hitable>) -> Self {
+    FlipNormal { hitable }
+  }
+}
+
+impl Hitable for FlipNormal {
+   fn hit(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
+     if let Some(mut hit) = self.hitable.hit(ray, t_range) {
+       hit.normal = hit.normal * -1.0;
+       hit.shading_normal = hit.shading_normal * -1.0;
+       return Some(hit);
+     }
+     
+     None
+   }
+
+   fn bounding_box(&self) -> Option<AABB> {
+     self.hitable.bounding_box()
+   }
+
+   fn volume_emission(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Vec3 {
+     self.hitable.volume_emission(ray, t_range)
+   }
+
+   fn has_volume_emission(&self) -> bool {
+     self.hitable.has_volume_emission()
+   }
+
+   fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f32 {
+     self.hitable.pdf_value(origin, direction)
+   }
+
+   fn random(&self, origin: Vec3) -> Vec3 {
+     self.hitable.random(origin)
+   }
+}
+
+// cuts holes into a surface, rays pass through wherever the mask texture is
+// transparent and fractional opacity is resolved stochastically
+pub struct AlphaMask {
+  hitable: Arc<Hitable>,
+  mask: Arc<Texture>
+}
+
+impl AlphaMask {
+  pub fn new(hitable: Arc<Hitable>, mask: Arc<Texture>) -> Self {
+    AlphaMask { hitable, mask }
+  }
+}
+
+impl Hitable for AlphaMask {
+  fn hit(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
+    let mut t_start = t_range.start;
+
+    loop {
+      let hit = self.hitable.hit(ray, t_start..t_range.end)?;
+      let alpha = self.mask.scalar(hit.u, hit.v, &hit.p);
+
+      if alpha >= 1.0 || alpha > 0.0 && random::<f32>() < alpha {
+        return Some(hit);
+      }
+
+      // step past the rejected surface so it is not found again
+      let next = step_past(hit.t);
+      if next <= t_start {
+        return None;
+      }
+      t_start = next;
+    }
+  }
+
+  fn bounding_box(&self) -> Option<AABB> {
+    self.hitable.bounding_box()
+  }
+}
+
+// start of the search for the next surface behind a hit at t, a fixed step
+// vanishes in the float spacing past |t| of about 2048 so far hits step by
+// a few ulps instead
+pub fn step_past(t: f32) -> f32 {
+  t + (1e-4f32).max(t.abs() * 1e-6)
+}
+
+// surfaces with a known area, lights on them can be given by their power
+pub trait Area {
+  fn area(&self) -> f32;
+}
+
+// a lambertian emitter of the given radiant power spread uniformly over the
+// surface of a shape, one watt maps to one unit of scene radiance. The
+// emission replaces the material of the shape.
+pub struct AreaLight<H> {
+  shape: H,
+  light: DiffuseLight
+}
+
+impl<H: Hitable + Area> AreaLight<H> {
+  pub fn from_power(shape: H, color: Vec3, watts: f32, two_sided: bool) -> Self {
+    let luminance = spectrum::luminance(color);
+    let sides = if two_sided { 2.0 } else { 1.0 };
+    let area = shape.area();
+    let radiance = if luminance > 0.0 && area > 0.0 {
+      color * (watts / (luminance * sides * ::std::f32::consts::PI * area))
+    } else {
+      Vec3::zero()
+    };
+
+    let emit = Arc::new(ConstantTexture::new(radiance));
+    let light = if two_sided { DiffuseLight::new(emit) } else { DiffuseLight::one_sided(emit) };
+
+    AreaLight { shape, light }
+  }
+
+  // luminous power, converted to watts with the peak luminous efficacy
+  pub fn from_lumens(shape: H, color: Vec3, lumens: f32, two_sided: bool) -> Self {
+    AreaLight::from_power(shape, color, lumens / spectrum::LUMINOUS_EFFICACY, two_sided)
+  }
+}
+
+impl<H: Hitable + Area> Hitable for AreaLight<H> {
+  fn hit(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
+    let mut record = self.shape.hit(ray, t_range)?;
+    record.material = &self.light;
+    Some(record)
+  }
+
+  fn bounding_box(&self) -> Option<AABB> {
+    self.shape.bounding_box()
+  }
+
+  fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f32 {
+    self.shape.pdf_value(origin, direction)
+  }
+
+  fn random(&self, origin: Vec3) -> Vec3 {
+    self.shape.random(origin)
+  }
+}
+
+pub struct Translate {
+  offset: Vec3,
+  hitable: Arc<Hitable>
+}
+
+impl Translate {
+  pub fn new(hitable: Arc<Hitable>, offset: Vec3) -> Self {
+    Translate { offset, hitable }
+  }
+}
+
+impl Hitable for Translate {
+  fn hit(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
+    let ray_moved = Ray::new(ray.origin - self.offset, ray.direction, ray.time).with_wavelength(ray.wavelength);
+    if let Some(mut hit) = self.hitable.hit(&ray_moved, t_range) {
+      hit.p = hit.p + self.offset;
+      return Some(hit);
+    }
+
+    None
+  }
+
+  fn bounding_box(&self) -> Option<AABB> {
+    if let Some(bbox) = self.hitable.bounding_box() {
+      return Some(AABB::new(bbox.min + self.offset, bbox.max + self.offset));
+    }
+    None
+  }
+
+  fn volume_emission(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Vec3 {
+    let ray_moved = Ray::new(ray.origin - self.offset, ray.direction, ray.time);
+    self.hitable.volume_emission(&ray_moved, t_range)
+  }
+
+  fn has_volume_emission(&self) -> bool {
+    self.hitable.has_volume_emission()
+  }
+
+  fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f32 {
+    self.hitable.pdf_value(origin - self.offset, direction)
+  }
+
+  fn random(&self, origin: Vec3) -> Vec3 {
+    self.hitable.random(origin - self.offset)
+  }
+}
+
+pub struct RotateY {
+  hitable: Arc<Hitable>,
+  bbox: Option<AABB>,
+  sin_theta: f32,
+  cos_theta: f32
+}
+
+impl RotateY {
+  pub fn new(hitable: Arc<Hitable>, angle: f32) -> Self {
+    let radians = (std::f32::consts::PI / 180.0) * angle;
+    let sin_theta = radians.sin();
+    let cos_theta = radians.cos();
+
+    let bbox = hitable.bounding_box().unwrap();
+    let mut min = Vec3::max();
+    let mut max = Vec3::min();
+
+    for i in 0..2 {
+      for j in 0..2 {
+        for k in 0..2 {
+          let x = i as f32 * bbox.max.x + (1 - i) as f32 * bbox.min.x;
+          let y = j as f32 * bbox.max.y + (1 - j) as f32 * bbox.min.y;
+          let z = k as f32 * bbox.max.z + (1 - k) as f32 * bbox.min.z;
+
+          let newx = cos_theta * x + sin_theta * z;
+          let newz = -sin_theta * x + cos_theta * z;
+
+          let tester = Vec3::new(newx, y, newz);
+
+          set_min_max(&mut min, &mut max, &tester);
+        }
+      }
+    }
+
+    RotateY { hitable, sin_theta, cos_theta, bbox: Some(AABB::new(min, max)) }
+  }
+
+  fn rotate_ray(&self, ray: &Ray) -> Ray {
+    Ray::new(self.rotate(ray.origin), self.rotate(ray.direction), ray.time).with_wavelength(ray.wavelength)
+  }
+
+  // into object space
+  fn rotate(&self, v: Vec3) -> Vec3 {
+    Vec3::new(
+      self.cos_theta * v.x - self.sin_theta * v.z,
+      v.y,
+      self.sin_theta * v.x + self.cos_theta * v.z
+    )
+  }
+
+  // back from object space, the inverse of the rotation applied to rays
+  fn rotate_back(&self, v: Vec3) -> Vec3 {
+    Vec3::new(
+      self.cos_theta * v.x + self.sin_theta * v.z,
+      v.y,
+      -self.sin_theta * v.x + self.cos_theta * v.z
+    )
+  }
+}
+
+impl Hitable for RotateY {
+  fn hit(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
+    let ray_rotated = self.rotate_ray(ray);
+
+    if let Some(mut hit) = self.hitable.hit(&ray_rotated, t_range) {
+      hit.p = self.rotate_back(hit.p);
+      hit.normal = self.rotate_back(hit.normal);
+      hit.shading_normal = self.rotate_back(hit.shading_normal);
+      hit.tangent = hit.tangent.map(|tangent| self.rotate_back(tangent));
+      hit.bitangent = hit.bitangent.map(|bitangent| self.rotate_back(bitangent));
+
+      return Some(hit);
+    }
+    None
+  }
+
+  fn bounding_box(&self) -> Option<AABB> {
+    self.bbox
+  }
+
+  fn volume_emission(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Vec3 {
+    self.hitable.volume_emission(&self.rotate_ray(ray), t_range)
+  }
+
+  fn has_volume_emission(&self) -> bool {
+    self.hitable.has_volume_emission()
+  }
+
+  fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f32 {
+    self.hitable.pdf_value(self.rotate(origin), self.rotate(direction))
+  }
+
+  fn random(&self, origin: Vec3) -> Vec3 {
+    self.rotate_back(self.hitable.random(self.rotate(origin)))
+  }
+}
+
+// places a hitable with an arbitrary affine transform. Rays are moved into
+// object space without normalizing the direction so that t stays the same.
+// Matrices without an inverse, e.g. with a zero scale, give None.
+pub struct Transform {
+  hitable: Arc<Hitable>,
+  matrix: Mat4,
+  inverse: Mat4,
+  inverse_determinant: f32,
+  bbox: Option<AABB>
+}
+
+impl Transform {
+  pub fn new(hitable: Arc<Hitable>, matrix: Mat4) -> Option<Self> {
+    let inverse = matrix.inverse()?;
+    let bbox = hitable.bounding_box().map(|bbox| transform_box(&matrix, &bbox));
+
+    let axis = |x, y, z| inverse.transform_vector(Vec3::new(x, y, z));
+    let inverse_determinant = axis(1.0, 0.0, 0.0).dot(axis(0.0, 1.0, 0.0).cross(axis(0.0, 0.0, 1.0)));
+
+    Some(Transform { hitable, matrix, inverse, inverse_determinant, bbox })
+  }
+
+  fn object_ray(&self, ray: &Ray) -> Ray {
+    Ray::new(self.inverse.transform_point(ray.origin), self.inverse.transform_vector(ray.direction), ray.time)
+      .with_wavelength(ray.wavelength)
+  }
+}
+
+impl Hitable for Transform {
+  fn hit(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
+    let mut hit = self.hitable.hit(&self.object_ray(ray), t_range)?;
+
+    hit.p = self.matrix.transform_point(hit.p);
+    hit.normal = self.inverse.transform_normal(hit.normal).normalize();
+    hit.shading_normal = self.inverse.transform_normal(hit.shading_normal).normalize();
+    hit.tangent = hit.tangent.map(|tangent| self.matrix.transform_vector(tangent));
+    hit.bitangent = hit.bitangent.map(|bitangent| self.matrix.transform_vector(bitangent));
+
+    Some(hit)
+  }
+
+  fn bounding_box(&self) -> Option<AABB> {
+    self.bbox
+  }
+
+  fn volume_emission(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Vec3 {
+    self.hitable.volume_emission(&self.object_ray(ray), t_range)
+  }
+
+  fn has_volume_emission(&self) -> bool {
+    self.hitable.has_volume_emission()
+  }
+
+  // solid angles are stretched by the linear part of the transform, by
+  // |det A| / |A d|^3 for the unit direction d and A the inverse
+  fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f32 {
+    let object_direction = self.inverse.transform_vector(direction.normalize());
+    let pdf = self.hitable.pdf_value(self.inverse.transform_point(origin), object_direction);
+
+    pdf * self.inverse_determinant.abs() / object_direction.len().powi(3)
+  }
+
+  fn random(&self, origin: Vec3) -> Vec3 {
+    self.matrix.transform_vector(self.hitable.random(self.inverse.transform_point(origin)))
+  }
+}
+
+pub fn transform_box(matrix: &Mat4, bbox: &AABB) -> AABB {
+  let mut min = Vec3::max();
+  let mut max = Vec3::min();
+
+  for i in 0..2 {
+    for j in 0..2 {
+      for k in 0..2 {
+        let corner = Vec3::new(
+          if i == 0 { bbox.min.x } else { bbox.max.x },
+          if j == 0 { bbox.min.y } else { bbox.max.y },
+          if k == 0 { bbox.min.z } else { bbox.max.z }
+        );
+
+        set_min_max(&mut min, &mut max, &matrix.transform_point(corner));
+      }
+    }
+  }
+
+  AABB::new(min, max)
+}
+
+pub struct ConstantMedium {
+  boundary: Arc<Hitable>,
+  density: f32,
+  phase_function: Isotropic
+}
+
+impl ConstantMedium {
+  pub fn new(boundary: Arc<Hitable>, density: f32, texture: Arc<Texture>) -> Self {
+    ConstantMedium { boundary, density, phase_function: Isotropic::new(texture) }
+  }
+}
+
+impl Hitable for ConstantMedium {
+  fn hit(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
+    let length = ray.direction.len();
+    let mut start = std::f32::MIN;
+
+    // non convex boundaries such as csg shells are entered several times,
+    // each pair of crossings is one stretch of medium
+    while let Some(record1) = self.boundary.hit(ray, start..std::f32::MAX) {
+      let record2 = self.boundary.hit(ray, step_past(record1.t)..std::f32::MAX)?;
+
+      let tmin = record1.t.max(t_range.start).max(0.0);
+      let tmax = record2.t.min(t_range.end);
+
+      if tmin < tmax {
+        let distance_inside_boundary = (tmax - tmin) * length;
+        let hit_distance = -(1.0 / self.density) * random::<f32>().log2();
+
+        if hit_distance < distance_inside_boundary {
+          let t = tmin + hit_distance / length;
+          let p = ray.point_at_parameter(t);
+          let normal = Vec3::new(1.0, 0.0, 0.0);
+
+          return Some(HitRecord::new(
+            t,
+            p,
+            normal,
+            &self.phase_function,
+            0.0,
+            0.0
+          ));
+        }
+      }
+
+      if record2.t >= t_range.end {
+        return None;
+      }
+
+      let next = step_past(record2.t);
+      if next <= start {
+        return None;
+      }
+      start = next;
+    }
+
+    None
+  }
+
+  fn bounding_box(&self) -> Option<AABB> {
+    self.boundary.bounding_box()
+  }
+}
+
+pub struct HeterogeneousMedium {
+  grid: Arc<VoxelGrid>,
+  density_scale: f32,
+  phase_function: Isotropic
+}
+
+impl HeterogeneousMedium {
+  pub fn new(grid: Arc<VoxelGrid>, density_scale: f32, texture: Arc<Texture>) -> Self {
+    HeterogeneousMedium { grid, density_scale, phase_function: Isotropic::new(texture) }
+  }
+}
+
+impl Hitable for HeterogeneousMedium {
+  fn hit(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
+    let majorant = self.grid.max_value() * self.density_scale;
+    if majorant <= 0.0 {
+      return None;
+    }
+
+    let (tmin, tmax) = self.grid.bounds().intersect(ray, t_range.start, t_range.end)?;
+    let ray_length = ray.direction.len();
+    let mut t = tmin;
+
+    // delta tracking: sample against the majorant and reject null collisions
+    loop {
+      t -= (1.0 - random::<f32>()).ln() / (majorant * ray_length);
+
+      if t >= tmax {
+        return None;
+      }
+
+      let p = ray.point_at_parameter(t);
+      if random::<f32>() * majorant < self.grid.lookup(p) * self.density_scale {
+        return Some(HitRecord::new(
+          t,
+          p,
+          Vec3::new(1.0, 0.0, 0.0),
+          &self.phase_function,
+          0.0,
+          0.0
+        ));
+      }
+    }
+  }
+
+  fn bounding_box(&self) -> Option<AABB> {
+    Some(self.grid.bounds())
+  }
+}
+
+pub struct EmissiveMedium {
+  density: Arc<VoxelGrid>,
+  temperature: Arc<VoxelGrid>,
+  absorption: f32,
+  scattering: f32,
+  emission_scale: f32,
+  step_size: f32,
+  phase_function: Isotropic
+}
+
+impl EmissiveMedium {
+  pub fn new(density: Arc<VoxelGrid>, temperature: Arc<VoxelGrid>, absorption: f32, scattering: f32, emission_scale: f32) -> Self {
+    let bounds = density.bounds();
+    let (nx, ny, nz) = density.resolution();
+    let extent = bounds.max - bounds.min;
+    let step_size = 0.5 * (extent.x / nx as f32).min(extent.y / ny as f32).min(extent.z / nz as f32);
+
+    let albedo = scattering / (absorption + scattering);
+    let phase_function = Isotropic::new(Arc::new(ConstantTexture::new(Vec3::new(albedo, albedo, albedo))));
+
+    EmissiveMedium { density, temperature, absorption, scattering, emission_scale, step_size, phase_function }
+  }
+
+  fn emission(&self, p: Vec3) -> Vec3 {
+    spectrum::blackbody_rgb(self.temperature.lookup(p)) * self.emission_scale
+  }
+}
+
+impl Hitable for EmissiveMedium {
+  fn hit(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
+    let majorant = self.density.max_value() * (self.absorption + self.scattering);
+    if majorant <= 0.0 {
+      return None;
+    }
+
+    let (tmin, tmax) = self.density.bounds().intersect(ray, t_range.start, t_range.end)?;
+    let ray_length = ray.direction.len();
+    let mut t = tmin;
+
+    loop {
+      t -= (1.0 - random::<f32>()).ln() / (majorant * ray_length);
+
+      if t >= tmax {
+        return None;
+      }
+
+      let p = ray.point_at_parameter(t);
+      if random::<f32>() * majorant < self.density.lookup(p) * (self.absorption + self.scattering) {
+        return Some(HitRecord::new(
+          t,
+          p,
+          Vec3::new(1.0, 0.0, 0.0),
+          &self.phase_function,
+          0.0,
+          0.0
+        ));
+      }
+    }
+  }
+
+  fn bounding_box(&self) -> Option<AABB> {
+    Some(self.density.bounds())
+  }
+
+  // track length estimator: the segment ends at the next sampled collision,
+  // so the emission is integrated without weighting by transmittance
+  fn volume_emission(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Vec3 {
+    let (tmin, tmax) = match self.density.bounds().intersect(ray, t_range.start, t_range.end) {
+      Some(interval) => interval,
+      None => return Vec3::zero()
+    };
+
+    let ray_length = ray.direction.len();
+    let dt = self.step_size / ray_length;
+    let mut start = tmin;
+    let mut emission = Vec3::zero();
+
+    // one jittered sample per step, the last step ends at tmax
+    while start < tmax {
+      let step = dt.min(tmax - start);
+      let p = ray.point_at_parameter(start + random::<f32>() * step);
+      let sigma_a = self.density.lookup(p) * self.absorption;
+
+      if sigma_a > 0.0 {
+        emission += self.emission(p) * (sigma_a * step * ray_length);
+      }
+
+      start += dt;
+    }
+
+    emission
+  }
+
+  fn has_volume_emission(&self) -> bool {
+    true
+  }
+}
+
+// random walk subsurface scattering inside a closed boundary whose normals
+// point outwards. The surface is a dielectric interface and the interior a
+// homogeneous medium with per channel albedo and mean free path. Distances
+// are sampled against the densest channel and the remaining channels are
+// corrected with spectral tracking weights (Kutz et al. 2017), which are
+// constant in a homogeneous medium and therefore live in the event materials.
+pub struct Subsurface {
+  boundary: Arc<Hitable>,
+  interface: Dielectric,
+  majorant: f32,
+  absorb_prob: f32,
+  scatter_prob: f32,
+  absorb: PassThrough,
+  phase_function: Isotropic,
+  null_collision: PassThrough
+}
+
+impl Subsurface {
+  pub fn new(boundary: Arc<Hitable>, albedo: Vec3, mean_free_path: Vec3, ref_idx: f32) -> Self {
+    let sigma_t = Vec3::new(1.0 / mean_free_path.x, 1.0 / mean_free_path.y, 1.0 / mean_free_path.z);
+    let sigma_s = sigma_t * albedo;
+    let sigma_a = sigma_t - sigma_s;
+    let majorant = sigma_t.x.max(sigma_t.y).max(sigma_t.z);
+    let sigma_n = Vec3::new(majorant, majorant, majorant) - sigma_t;
+
+    let mean = |v: Vec3| (v.x + v.y + v.z) / 3.0;
+    let absorb_prob = mean(sigma_a) / majorant;
+    let scatter_prob = mean(sigma_s) / majorant;
+    let null_prob = mean(sigma_n) / majorant;
+
+    let scatter_weight = if scatter_prob > 0.0 { sigma_s / (majorant * scatter_prob) } else { Vec3::zero() };
+    let null_weight = if null_prob > 0.0 { sigma_n / (majorant * null_prob) } else { Vec3::zero() };
+
+    Subsurface {
+      boundary,
+      interface: Dielectric::new(ref_idx),
+      majorant,
+      absorb_prob,
+      scatter_prob,
+      absorb: PassThrough::new(Vec3::zero()),
+      phase_function: Isotropic::new(Arc::new(ConstantTexture::new(scatter_weight))),
+      null_collision: PassThrough::new(null_weight)
+    }
+  }
+}
+
+impl Hitable for Subsurface {
+  fn hit(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
+    let surface = self.boundary.hit(ray, t_range.clone())?;
+
+    if ray.direction.dot(surface.normal) <= 0.0 {
+      let mut entry = surface;
+      entry.material = &self.interface;
+      return Some(entry);
+    }
+
+    let distance = -(1.0 - random::<f32>()).ln() / self.majorant;
+    let t = t_range.start + distance / ray.direction.len();
+
+    if t >= surface.t {
+      let mut exit = surface;
+      exit.material = &self.interface;
+      return Some(exit);
+    }
+
+    let event = random::<f32>();
+    let material: &Material = if event < self.absorb_prob {
+      &self.absorb
+    } else if event < self.absorb_prob + self.scatter_prob {
+      &self.phase_function
+    } else {
+      &self.null_collision
+    };
+
+    Some(HitRecord::new(
+      t,
+      ray.point_at_parameter(t),
+      surface.normal,
+      material,
+      surface.u,
+      surface.v
+    ))
+  }
+
+  fn bounding_box(&self) -> Option<AABB> {
+    self.boundary.bounding_box()
+  }
+}
+
+fn set_min_max(min: &mut Vec3, max: &mut Vec3, minMax: &Vec3) {
+  if minMax.x > max.x {
+    max.x = minMax.x;
+  }
+
+  if minMax.y > max.y {
+    max.y = minMax.y;
+  }
+
+  if minMax.z > max.z {
+    max.z = minMax.z;
+  }
+
+  if minMax.x < min.x {
+    min.x = minMax.x;
+  }
+
+  if minMax.y < min.y {
+    min.y = minMax.y;
+  }
+
+  if minMax.z < min.z {
+    min.z = minMax.z;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::voxel_grid::DenseGrid;
+  use crate::sphere::Sphere;
+  use crate::quad::{Quad, Disk};
+  use crate::matrix::Mat4;
+  use crate::xy_rect::{XZRect, YZRect};
+
+  // fraction of rays along x through the unit cube that leave it without a collision
+  fn transmittance(hitable: &Hitable, samples: usize) -> f32 {
+    let ray = Ray::new(Vec3::new(-1.0, 0.5, 0.5), Vec3::new(2.0, 0.0, 0.0), 0.0);
+    let misses = (0..samples).filter(|_| hitable.hit(&ray, 0.001..std::f32::MAX).is_none()).count();
+    misses as f32 / samples as f32
+  }
+
+  #[test]
+  fn heterogeneous_medium_follows_beer_lambert() {
+    let bounds = AABB::new(Vec3::zero(), Vec3::new(1.0, 1.0, 1.0));
+    let grid = DenseGrid::from_fn(4, 4, 4, bounds, |p| if p.x < 0.5 { 1.0 } else { 3.0 });
+    let medium = HeterogeneousMedium::new(Arc::new(grid), 0.5, Arc::new(ConstantTexture::new(Vec3::zero())));
+
+    // the optical depth is the integral of the trilinear density along x
+    let optical_depth: f32 = (0..1000).map(|i| grid_density(i as f32 / 1000.0 + 0.0005) * 0.5 / 1000.0).sum();
+    let expected = (-optical_depth).exp();
+
+    assert!((transmittance(&medium, 40000) - expected).abs() < 0.01);
+  }
+
+  #[test]
+  fn emissive_medium_integrates_emission_along_the_ray() {
+    let bounds = AABB::new(Vec3::zero(), Vec3::new(1.0, 1.0, 1.0));
+    let density = DenseGrid::from_fn(8, 8, 8, bounds, |_| 2.0);
+    let temperature = DenseGrid::from_fn(8, 8, 8, bounds, |_| 3000.0);
+    let medium = EmissiveMedium::new(Arc::new(density), Arc::new(temperature), 0.25, 0.0, 10.0);
+
+    // a segment of the cube of length 0.7, absorption 0.25 * 2
+    let ray = Ray::new(Vec3::new(-1.0, 0.5, 0.5), Vec3::new(2.0, 0.0, 0.0), 0.0);
+    let emission = medium.volume_emission(&ray, 0.6..0.95);
+    let expected = spectrum::blackbody_rgb(3000.0) * (10.0 * 0.5 * 0.7);
+
+    assert!((emission - expected).len() < 1e-4 * expected.len());
+    assert!(medium.has_volume_emission());
+  }
+
+  fn grid_density(x: f32) -> f32 {
+    // voxel centers at 0.125, 0.375 (density 1) and 0.625, 0.875 (density 3)
+    let g = (x * 4.0 - 0.5).max(0.0).min(3.0);
+    if g <= 1.0 { 1.0 } else if g >= 2.0 { 3.0 } else { 1.0 + 2.0 * (g - 1.0) }
+  }
+
+  fn is_material(record: &HitRecord, material: &Material) -> bool {
+    record.material as *const Material as *const u8 == material as *const Material as *const u8
+  }
+
+  fn skin() -> Subsurface {
+    let boundary = Arc::new(Sphere::new(Vec3::zero(), 1.0, Arc::new(PassThrough::new(Vec3::zero()))));
+    Subsurface::new(boundary, Vec3::new(0.9, 0.6, 0.3), Vec3::new(1.0, 0.5, 0.25), 1.4)
+  }
+
+  #[test]
+  fn subsurface_rays_refract_at_the_boundary() {
+    let medium = skin();
+    let ray = Ray::new(Vec3::new(0.0, 0.0, 3.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+    let record = medium.hit(&ray, 0.001..std::f32::MAX).unwrap();
+
+    assert!((record.t - 2.0).abs() < 1e-4);
+    assert!(is_material(&record, &medium.interface));
+  }
+
+  #[test]
+  fn subsurface_collisions_follow_the_majorant() {
+    let medium = skin();
+    let ray = Ray::new(Vec3::zero(), Vec3::new(1.0, 0.0, 0.0), 0.0);
+
+    let samples = 40000;
+    let exits = (0..samples)
+      .filter(|_| is_material(&medium.hit(&ray, 0.0..std::f32::MAX).unwrap(), &medium.interface))
+      .count();
+
+    // the densest channel has a mean free path of 0.25 and the exit is 1 away
+    let expected = (-4.0f32).exp();
+    assert!((exits as f32 / samples as f32 - expected).abs() < 0.005);
+  }
+
+  #[test]
+  fn subsurface_tracking_weights_reproduce_the_scattering_coefficients() {
+    let medium = skin();
+    let ray = Ray::new(Vec3::zero(), Vec3::new(1.0, 0.0, 0.0), 0.0);
+
+    // the expected weight of real scattering per collision is sigma_s / majorant
+    let samples = 40000;
+    let mut scattered = Vec3::zero();
+    let mut collisions = 0;
+    for _ in 0..samples {
+      let record = medium.hit(&ray, 0.0..std::f32::MAX).unwrap();
+      if is_material(&record, &medium.interface) {
+        continue;
+      }
+
+      collisions += 1;
+      if is_material(&record, &medium.phase_function) {
+        scattered += medium.phase_function.scatter(&ray, &record).attenuation;
+      }
+    }
+
+    let scattered = scattered / collisions as f32;
+    let expected = Vec3::new(0.9 * 1.0, 0.6 * 2.0, 0.3 * 4.0) / 4.0;
+    assert!((scattered - expected).len() < 0.02, "{:?}", scattered);
+  }
+
+  fn unit_sphere() -> Arc<Hitable> {
+    Arc::new(Sphere::new(Vec3::zero(), 1.0, Arc::new(PassThrough::new(Vec3::zero()))))
+  }
+
+  fn opacity(alpha: f32) -> Arc<Texture> {
+    Arc::new(ConstantTexture::new(Vec3::new(alpha, alpha, alpha)))
+  }
+
+  #[test]
+  fn alpha_mask_lets_rays_through_transparent_parts() {
+    let ray = Ray::new(Vec3::new(0.0, 0.0, 3.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+
+    let opaque = AlphaMask::new(unit_sphere(), opacity(1.0));
+    assert!((opaque.hit(&ray, 0.001..std::f32::MAX).unwrap().t - 2.0).abs() < 1e-4);
+
+    let transparent = AlphaMask::new(unit_sphere(), opacity(0.0));
+    assert!(transparent.hit(&ray, 0.001..std::f32::MAX).is_none());
+  }
+
+  #[test]
+  fn alpha_mask_resolves_partial_opacity_stochastically() {
+    let ray = Ray::new(Vec3::new(0.0, 0.0, 3.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+    let mask = AlphaMask::new(unit_sphere(), opacity(0.25));
+
+    let samples = 40000;
+    let (mut front, mut back) = (0, 0);
+    for _ in 0..samples {
+      match mask.hit(&ray, 0.001..std::f32::MAX) {
+        Some(record) if record.t < 3.0 => front += 1,
+        Some(_) => back += 1,
+        None => {}
+      }
+    }
+
+    // the back of the sphere is only reached through the front
+    assert!((front as f32 / samples as f32 - 0.25).abs() < 0.01);
+    assert!((back as f32 / samples as f32 - 0.75 * 0.25).abs() < 0.01);
+  }
+
+  #[test]
+  fn area_light_spreads_its_power_over_the_shape() {
+    let white = Vec3::new(1.0, 1.0, 1.0);
+    let panel = || XZRect::new(0.0, 2.0, 0.0, 3.0, 1.0, Arc::new(PassThrough::new(Vec3::zero())));
+    let down = Ray::new(Vec3::new(1.0, 2.0, 1.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+    let up = Ray::new(Vec3::new(1.0, 0.0, 1.0), Vec3::new(0.0, 1.0, 0.0), 0.0);
+
+    // the radiance times pi and the area of the emitting sides is the power
+    let one_sided = AreaLight::from_power(panel(), white, 60.0, false);
+    let record = one_sided.hit(&up, 0.001..std::f32::MAX).unwrap();
+    assert_eq!(record.material.emitted(&up, &record), Vec3::zero());
+    let record = one_sided.hit(&down, 0.001..std::f32::MAX).unwrap();
+    let radiance = record.material.emitted(&down, &record).x;
+    assert!((radiance * ::std::f32::consts::PI * 6.0 - 60.0).abs() < 1e-3);
+
+    let two_sided = AreaLight::from_lumens(panel(), white, 683.0 * 60.0, true);
+    let record = two_sided.hit(&up, 0.001..std::f32::MAX).unwrap();
+    assert!((record.material.emitted(&up, &record).x - 0.5 * radiance).abs() < 1e-5);
+  }
+
+  #[test]
+  fn shapes_know_their_area() {
+    let material: Arc<Material> = Arc::new(PassThrough::new(Vec3::zero()));
+    let pi = ::std::f32::consts::PI;
+
+    let quad = Quad::new(Vec3::zero(), Vec3::new(2.0, 0.0, 0.0), Vec3::new(1.0, 3.0, 0.0), Arc::clone(&material));
+    assert!((quad.area() - 6.0).abs() < 1e-5);
+
+    let annulus = Disk::annulus(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), 1.0, 2.0, Arc::clone(&material));
+    assert!((annulus.area() - 3.0 * pi).abs() < 1e-5);
+
+    assert!((Sphere::new(Vec3::zero(), 2.0, Arc::clone(&material)).area() - 16.0 * pi).abs() < 1e-4);
+    assert!((YZRect::new(0.0, 2.0, 1.0, 4.0, 0.0, material).area() - 6.0).abs() < 1e-5);
+  }
+
+  fn solid_angle_integral(hitable: &Hitable, origin: Vec3) -> f32 {
+    let samples = 200000;
+    let sum: f32 = (0..samples)
+      .map(|_| hitable.pdf_value(origin, crate::sphere::random_in_unit_sphere().normalize()))
+      .sum();
+
+    4.0 * ::std::f32::consts::PI * sum / samples as f32
+  }
+
+  fn light_quad() -> Arc<Hitable> {
+    let unused: Arc<Material> = Arc::new(PassThrough::new(Vec3::zero()));
+    Arc::new(Quad::new(Vec3::new(-1.0, 1.0, -0.5), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0), unused))
+  }
+
+  #[test]
+  fn transformed_lights_keep_normalized_densities() {
+    let origin = Vec3::new(0.2, -0.5, 0.1);
+    let rotated = RotateY::new(light_quad(), 30.0);
+    let stretched = Transform::new(light_quad(), Mat4::rotation_axis(Vec3::new(1.0, 0.0, 0.0), 20.0) * Mat4::scale(Vec3::new(0.5, 1.5, 2.0))).unwrap();
+
+    for light in &[&rotated as &Hitable, &stretched] {
+      assert!((solid_angle_integral(*light, origin) - 1.0).abs() < 0.03);
+
+      for _ in 0..1000 {
+        let direction = light.random(origin);
+        assert!(light.hit(&Ray::new(origin, direction, 0.0), 0.001..std::f32::MAX).is_some());
+      }
+    }
+  }
+
+  #[test]
+  fn singular_transforms_are_rejected() {
+    assert!(Transform::new(light_quad(), Mat4::scale(Vec3::new(1.0, 0.0, 1.0))).is_none());
+  }
+
+  #[test]
+  fn empty_light_lists_are_never_sampled() {
+    let lights = HitableList::new();
+    assert_eq!(lights.pdf_value(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0)), 0.0);
+    lights.random(Vec3::zero());
+
+    let lights = HitableList::from_list(vec![Box::new(Translate::new(light_quad(), Vec3::new(0.0, 1.0, 0.0)))]);
+    let direction = lights.random(Vec3::zero());
+    assert!(lights.pdf_value(Vec3::zero(), direction) > 0.0);
+  }
+
+  #[test]
+  fn far_boundaries_are_stepped_past() {
+    assert_eq!(step_past(0.5), 0.5001);
+    assert!(step_past(3000.0) > 3000.0);
+    assert!(step_past(-3e7) > -3e7);
+
+    // at this distance a fixed step of 1e-4 does not change t at all
+    let far_sphere = || -> Arc<Hitable> {
+      Arc::new(Sphere::new(Vec3::new(5000.0, 0.0, 0.0), 100.0, Arc::new(PassThrough::new(Vec3::zero()))))
+    };
+    let ray = Ray::new(Vec3::zero(), Vec3::new(1.0, 0.0, 0.0), 0.0);
+
+    let thin = ConstantMedium::new(far_sphere(), 1e-9, opacity(1.0));
+    assert!(thin.hit(&ray, 0.001..std::f32::MAX).is_none());
+
+    let dense = ConstantMedium::new(far_sphere(), 10.0, opacity(1.0));
+    let record = dense.hit(&ray, 0.001..std::f32::MAX).unwrap();
+    assert!(record.t >= 4900.0 && record.t <= 5100.0);
+
+    let mask = AlphaMask::new(far_sphere(), opacity(0.0));
+    assert!(mask.hit(&ray, 0.001..std::f32::MAX).is_none());
+  }
+}
@@ -0,0 +1,144 @@
+//! Instances of shared geometry for two level acceleration.
+//!
+//! The geometry of an object, e.g. a mesh or a group of primitives, goes into
+//! one bottom level `Bvh` behind an `Arc`. Every placement of it is an
+//! `Instance` holding only that reference, its transform and optionally a
+//! material replacing the one of the geometry. Instances are ordinary
+//! hitables, so the scene `BvhTree` (or another `Bvh`) over them forms the
+//! top level: rays are culled against the transformed instance boxes there
+//! and only enter the shared bottom level tree in object space.
+
+use crate::hitable::{Hitable, HitRecord, Transform};
+use crate::bvh_node::Bvh;
+use crate::material::Material;
+use crate::matrix::Mat4;
+use crate::aabb::AABB;
+use crate::ray::Ray;
+use crate::vec::Vec3;
+
+use std::sync::Arc;
+
+pub struct Instance {
+    transform: Transform,
+    material: Option<Arc<Material>>
+}
+
+impl Instance {
+    // None if the matrix is not invertible, see `Transform`
+    pub fn new(geometry: Arc<Hitable>, matrix: Mat4) -> Option<Self> {
+        Some(Instance { transform: Transform::new(geometry, matrix)?, material: None })
+    }
+
+    // shades every surface of this instance with the material instead of
+    // the ones of the shared geometry
+    pub fn with_material(mut self, material: Arc<Material>) -> Self {
+        self.material = Some(material);
+        self
+    }
+}
+
+impl Hitable for Instance {
+    fn hit(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
+        let mut hit = self.transform.hit(ray, t_range)?;
+
+        if let Some(ref material) = self.material {
+            hit.material = &**material;
+        }
+
+        Some(hit)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.transform.bounding_box()
+    }
+
+    fn volume_emission(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Vec3 {
+        self.transform.volume_emission(ray, t_range)
+    }
+
+    fn has_volume_emission(&self) -> bool {
+        self.transform.has_volume_emission()
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f32 {
+        self.transform.pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: Vec3) -> Vec3 {
+        self.transform.random(origin)
+    }
+}
+
+// bottom level tree over a group of hitables, ready to be instanced
+pub fn shared(hitables: Vec<Box<Hitable>>) -> Arc<Hitable> {
+    Arc::new(Bvh::new(hitables))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Diffuse, PassThrough};
+    use crate::texture::ConstantTexture;
+    use crate::quad::Quad;
+    use crate::sphere::{self, Sphere};
+
+    fn unused() -> Arc<Material> {
+        Arc::new(PassThrough::new(Vec3::zero()))
+    }
+
+    fn same_material(a: &Material, b: &Material) -> bool {
+        a as *const Material as *const u8 == b as *const Material as *const u8
+    }
+
+    #[test]
+    fn instances_share_geometry_and_replace_materials() {
+        let own = unused();
+        let geometry = shared(vec![
+            Box::new(Sphere::new(Vec3::zero(), 1.0, Arc::clone(&own))),
+            Box::new(Sphere::new(Vec3::new(3.0, 0.0, 0.0), 1.0, Arc::clone(&own)))
+        ]);
+
+        let red: Arc<Material> = Arc::new(Diffuse::new(Arc::new(ConstantTexture::new(Vec3::new(0.8, 0.1, 0.1)))));
+        let plain = Instance::new(Arc::clone(&geometry), Mat4::translation(Vec3::new(0.0, 10.0, 0.0))).unwrap();
+        let painted = Instance::new(geometry, Mat4::translation(Vec3::new(0.0, -10.0, 0.0)) * Mat4::scale(Vec3::new(2.0, 2.0, 2.0)))
+            .unwrap()
+            .with_material(Arc::clone(&red));
+
+        let down = |x: f32, y: f32| Ray::new(Vec3::new(x, y, 10.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+
+        let record = plain.hit(&down(3.0, 10.0), 0.001..std::f32::MAX).unwrap();
+        assert!((record.t - 9.0).abs() < 1e-4);
+        assert!(same_material(record.material, &*own));
+
+        // the scaled copy is twice as large and painted red
+        let record = painted.hit(&down(6.0, -10.0), 0.001..std::f32::MAX).unwrap();
+        assert!((record.t - 8.0).abs() < 1e-4);
+        assert!((record.normal - Vec3::new(0.0, 0.0, 1.0)).len() < 1e-5);
+        assert!(same_material(record.material, &*red));
+
+        let bbox = painted.bounding_box().unwrap();
+        assert!((bbox.min - Vec3::new(-2.0, -12.0, -2.0)).len() < 1e-3 && (bbox.max - Vec3::new(8.0, -8.0, 2.0)).len() < 1e-3);
+    }
+
+    #[test]
+    fn instanced_lights_can_be_sampled() {
+        let panel = shared(vec![Box::new(Quad::new(Vec3::new(-0.5, 0.0, -0.5), Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 0.0), unused()))]);
+        let light = Instance::new(panel, Mat4::translation(Vec3::new(0.0, 3.0, 0.0)) * Mat4::scale(Vec3::new(2.0, 1.0, 1.0))).unwrap();
+        let origin = Vec3::zero();
+
+        for _ in 0..100 {
+            let direction = light.random(origin);
+            assert!(light.hit(&Ray::new(origin, direction, 0.0), 0.001..std::f32::MAX).is_some());
+            assert!(light.pdf_value(origin, direction) > 0.0);
+        }
+
+        // the density over all directions integrates to one
+        let samples = 200000;
+        let sum: f32 = (0..samples)
+            .map(|_| light.pdf_value(origin, sphere::random_in_unit_sphere().normalize()))
+            .sum();
+        let integral = 4.0 * std::f32::consts::PI * sum / samples as f32;
+
+        assert!((integral - 1.0).abs() < 0.05, "{}", integral);
+    }
+}
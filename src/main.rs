@@ -16,9 +16,26 @@ mod bvh_node;
 mod perlin;
 mod texture;
 mod xy_rect;
+mod quad;
+mod quadric;
+mod csg;
+mod sdf;
+mod heightfield;
+mod curve;
+mod instance;
+mod animation;
 mod box_model;
 mod scene;
 mod renderer;
+mod voxel_grid;
+mod spectrum;
+mod onb;
+mod microfacet;
+mod principled;
+mod thin_film;
+mod mesh;
+mod matrix;
+mod gltf_import;
 
 use crate::hitable::*;
 use crate::sphere::Sphere;
@@ -29,12 +46,34 @@ use crate::box_model::*;
 use crate::scene::*;
 use crate::vec::Vec3;
 use crate::camera::Camera;
+use crate::gltf_import::GltfScene;
+use crate::mesh::TriangleMesh;
+use crate::voxel_grid::{DenseGrid, SparseGrid};
+use crate::aabb::AABB;
+use crate::quad::{Quad, Disk};
+use crate::quadric::{Cylinder, Cone, Torus};
+use crate::csg::Csg;
+use crate::sdf::*;
+use crate::heightfield::Heightfield;
+use crate::curve::{CurveSet, CurveType, Curve};
+use crate::instance::Instance;
+use crate::animation::{AnimatedTransform, Keyframe};
+use crate::matrix::Mat4;
+use crate::spectrum::Dispersion;
+use crate::principled::Principled;
+use crate::thin_film::{ThinFilm, FilmBase};
 
 use rand::prelude::*;
 use clap::{App, Arg};
+use std::io;
 use std::sync::Arc;
 use std::time::Instant;
 
+const SCENES: &[&str] = &[
+    "random_spheres", "simple_light", "light_and_sphere", "cornell_box", "cornell_smoke", "next_week",
+    "smoke", "fire", "metals", "glass", "tinted_glass", "dispersion", "principled", "layered", "cloth", "thin_films", "subsurface", "cutouts", "lamps", "area_lights", "quadrics", "csg", "distance_fields", "terrain", "hair", "instancing", "motion_blur", "bump_maps", "displacement"
+];
+
 fn create_scene() -> Vec<Box<Hitable>> {
     let mut world: Vec<Box<Hitable>> = Vec::new();
 
@@ -514,6 +553,733 @@ fn the_next_week() -> Vec<Box<Hitable>> {
     return world;
 }
 
+// walls and ceiling light of the cornell box, open towards the camera
+fn cornell_room() -> Vec<Box<Hitable>> {
+    let mut world: Vec<Box<Hitable>> = Vec::new();
+
+    let red: Arc<Material> = Arc::new(Diffuse::new(
+        Arc::new(ConstantTexture::new(Vec3::new(0.65, 0.05, 0.05)))
+    ));
+
+    let white: Arc<Material> = Arc::new(Diffuse::new(
+        Arc::new(ConstantTexture::new(Vec3::new(0.73, 0.73, 0.73)))
+    ));
+
+    let green: Arc<Material> = Arc::new(Diffuse::new(
+        Arc::new(ConstantTexture::new(Vec3::new(0.12, 0.45, 0.15)))
+    ));
+
+    let light: Arc<Material> = Arc::new(DiffuseLight::new(
+        Arc::new(ConstantTexture::new(Vec3::new(15.0, 15.0, 15.0)))
+    ));
+
+    world.push(Box::new(FlipNormal::new(Box::new(YZRect::new(0.0, 555.0, 0.0, 555.0, 555.0, Arc::clone(&green))))));
+    world.push(Box::new(YZRect::new(0.0, 555.0, 0.0, 555.0, 0.0, Arc::clone(&red))));
+    world.push(Box::new(XZRect::new(213.0, 343.0, 227.0, 332.0, 554.0, Arc::clone(&light))));
+    world.push(Box::new(FlipNormal::new(Box::new(XZRect::new(0.0, 555.0, 0.0, 555.0, 555.0, Arc::clone(&white))))));
+    world.push(Box::new(XZRect::new(0.0, 555.0, 0.0, 555.0, 0.0, Arc::clone(&white))));
+    world.push(Box::new(FlipNormal::new(Box::new(XYRect::new(0.0, 555.0, 0.0, 555.0, 555.0, Arc::clone(&white))))));
+
+    world
+}
+
+// a puff of smoke from a sparse voxel grid, dense in the middle and frayed
+// by noise towards the edges
+fn smoke() -> Vec<Box<Hitable>> {
+    let mut world = cornell_room();
+
+    let bounds = AABB::new(Vec3::new(130.0, 0.0, 130.0), Vec3::new(430.0, 400.0, 430.0));
+    let center = Vec3::new(280.0, 180.0, 280.0);
+    let grid = DenseGrid::from_fn(64, 64, 64, bounds, |p| {
+        let falloff = 1.0 - (p - center).len() / 170.0;
+        (falloff + 0.6 * perlin::turb(p * 0.02, 5) - 0.4).max(0.0)
+    });
+
+    world.push(Box::new(HeterogeneousMedium::new(
+        Arc::new(SparseGrid::from_dense(&grid, 0.0)),
+        0.15,
+        Arc::new(ConstantTexture::new(Vec3::new(0.8, 0.8, 0.8)))
+    )));
+
+    world
+}
+
+// a fireball: the temperature drops from the core outwards, so the glow
+// turns from yellow to deep red where the smoke thins out
+fn fire() -> Vec<Box<Hitable>> {
+    let mut world = cornell_room();
+
+    let bounds = AABB::new(Vec3::new(155.0, 30.0, 155.0), Vec3::new(405.0, 330.0, 405.0));
+    let center = Vec3::new(280.0, 150.0, 280.0);
+    let falloff = move |p: Vec3| (1.0 - (p - center).len() / 125.0).max(0.0);
+
+    let density = DenseGrid::from_fn(48, 48, 48, bounds, |p| {
+        (falloff(p) + 0.5 * perlin::turb(p * 0.03, 5) - 0.3).max(0.0)
+    });
+    let temperature = DenseGrid::from_fn(48, 48, 48, bounds, |p| 1200.0 + 2200.0 * falloff(p));
+
+    world.push(Box::new(EmissiveMedium::new(Arc::new(density), Arc::new(temperature), 0.02, 0.01, 300.0)));
+
+    world
+}
+
+// one sphere per material in a row across the cornell box
+fn material_showcase(materials: Vec<Arc<Material>>) -> Vec<Box<Hitable>> {
+    let mut world = cornell_room();
+    let spacing = 555.0 / materials.len() as f32;
+    let radius = (0.4 * spacing).min(90.0);
+
+    for (i, material) in materials.into_iter().enumerate() {
+        let center = Vec3::new(555.0 - (i as f32 + 0.5) * spacing, radius, 300.0);
+        world.push(Box::new(Sphere::new(center, radius, material)));
+    }
+
+    world
+}
+
+// polished and brushed metals, the anisotropic highlights run along the
+// parameterization of the spheres
+fn metals() -> Vec<Box<Hitable>> {
+    material_showcase(vec![
+        Arc::new(Conductor::gold(0.2)),
+        Arc::new(Conductor::copper(0.4)),
+        Arc::new(Conductor::aluminium(0.3).with_anisotropy(0.9)),
+        Arc::new(Conductor::silver(0.05))
+    ])
+}
+
+// clear glass next to increasingly frosted glass and roughened water
+fn glass() -> Vec<Box<Hitable>> {
+    material_showcase(vec![
+        Arc::new(Dielectric::new(1.5)),
+        Arc::new(RoughDielectric::new(1.5, 0.1)),
+        Arc::new(RoughDielectric::new(1.5, 0.4)),
+        Arc::new(RoughDielectric::new(1.33, 0.2))
+    ])
+}
+
+// thick colored glass and a frosted amber, tinted by the distance the light
+// travels inside them
+fn tinted_glass() -> Vec<Box<Hitable>> {
+    material_showcase(vec![
+        Arc::new(Dielectric::new(1.5).with_absorption(Vec3::new(0.02, 0.004, 0.015))),
+        Arc::new(Dielectric::new(1.33).with_absorption(Vec3::new(0.015, 0.006, 0.001))),
+        Arc::new(RoughDielectric::new(1.5, 0.15).with_absorption(Vec3::new(0.002, 0.008, 0.03)))
+    ])
+}
+
+// glasses of increasing dispersion, the caustics below them split into
+// rainbows
+fn dispersion() -> Vec<Box<Hitable>> {
+    material_showcase(vec![
+        Arc::new(Dielectric::new(1.5).with_dispersion(Dispersion::fused_silica())),
+        Arc::new(Dielectric::new(1.5).with_dispersion(Dispersion::bk7())),
+        Arc::new(Dielectric::new(1.5).with_dispersion(Dispersion::dense_flint())),
+        Arc::new(Dielectric::new(1.5).with_dispersion(Dispersion::diamond())),
+        Arc::new(RoughDielectric::new(1.5, 0.1).with_dispersion(Dispersion::Cauchy { a: 1.6, b: 0.05 }))
+    ])
+}
+
+// the principled material from rough plastic over a clearcoated paint and
+// brushed metal to sheened cloth
+fn principled() -> Vec<Box<Hitable>> {
+    let color = |r, g, b| -> Arc<Texture> { Arc::new(ConstantTexture::new(Vec3::new(r, g, b))) };
+
+    material_showcase(vec![
+        Arc::new(Principled::new(color(0.8, 0.2, 0.1)).with_roughness(0.6).with_specular(0.5, 0.3)),
+        Arc::new(Principled::new(color(0.1, 0.2, 0.6)).with_roughness(0.4).with_clearcoat(1.0, 0.9)),
+        Arc::new(Principled::new(color(0.9, 0.8, 0.7)).with_metallic(1.0).with_roughness(0.4).with_anisotropy(0.8)),
+        Arc::new(Principled::new(color(0.3, 0.5, 0.2)).with_roughness(0.9).with_sheen(1.0, 0.5)),
+        Arc::new(Principled::new(color(0.9, 0.9, 0.9)).with_roughness(0.1).with_transmission(1.0, 1.5))
+    ])
+}
+
+// layered materials: lacquered paint, varnish that yellows the wood below
+// it, a rough coat over copper and a checker blend of gold and white plaster
+fn layered() -> Vec<Box<Hitable>> {
+    let color = |r, g, b| -> Arc<Texture> { Arc::new(ConstantTexture::new(Vec3::new(r, g, b))) };
+    let plaster: Arc<Material> = Arc::new(Diffuse::new(color(0.8, 0.8, 0.8)));
+    let checker = Arc::new(CheckerTexture::new(
+        Box::new(ConstantTexture::new(Vec3::zero())),
+        Box::new(ConstantTexture::new(Vec3::new(1.0, 1.0, 1.0)))
+    ));
+
+    material_showcase(vec![
+        Arc::new(Coated::new(Arc::new(Diffuse::new(color(0.7, 0.05, 0.05))), 1.5)),
+        Arc::new(Coated::new(Arc::new(Diffuse::new(color(0.6, 0.4, 0.25))), 1.5).with_absorption(Vec3::new(0.02, 0.08, 0.3))),
+        Arc::new(Coated::new(Arc::new(Conductor::copper(0.1)), 1.5).with_roughness(0.3)),
+        Arc::new(MixMaterial::new(Arc::clone(&plaster), Arc::new(Conductor::gold(0.2)), checker)),
+        Arc::new(MixMaterial::with_factor(plaster, Arc::new(Dielectric::new(1.5)), 0.5))
+    ])
+}
+
+// matte and fabric surfaces: lambertian, moon dust like Oren-Nayar clay with
+// a noisy roughness, and velvets whose rims light up under the sheen
+fn cloth() -> Vec<Box<Hitable>> {
+    let color = |r, g, b| -> Arc<Texture> { Arc::new(ConstantTexture::new(Vec3::new(r, g, b))) };
+
+    material_showcase(vec![
+        Arc::new(Diffuse::new(color(0.7, 0.6, 0.5))),
+        Arc::new(OrenNayar::new(color(0.7, 0.6, 0.5), color(0.5, 0.5, 0.5))),
+        Arc::new(OrenNayar::new(color(0.7, 0.6, 0.5), Arc::new(NoiseTexture::new(0.05)))),
+        Arc::new(Sheen::new(color(0.3, 0.02, 0.05), color(0.9, 0.5, 0.6), color(0.4, 0.4, 0.4))),
+        Arc::new(Sheen::new(color(0.05, 0.05, 0.2), color(0.6, 0.6, 1.0), color(0.8, 0.8, 0.8)))
+    ])
+}
+
+// interference colors: soap bubbles with swirling thickness, an
+// antireflection coated lens and oil on steel
+fn thin_films() -> Vec<Box<Hitable>> {
+    let swirl: Arc<Texture> = Arc::new(NoiseTexture::new(0.02));
+    let steel = FilmBase::Conductor { eta: Vec3::new(2.9, 2.9, 2.5), k: Vec3::new(3.1, 3.0, 2.8) };
+
+    material_showcase(vec![
+        Arc::new(ThinFilm::soap_bubble(Arc::clone(&swirl))),
+        Arc::new(ThinFilm::new(FilmBase::Dielectric(1.5), 1.38, Arc::new(ConstantTexture::new(Vec3::new(0.5, 0.5, 0.5))), 0.0, 200.0)),
+        Arc::new(ThinFilm::new(steel, 1.47, Arc::clone(&swirl), 200.0, 700.0)),
+        Arc::new(ThinFilm::new(steel, 1.47, swirl, 200.0, 700.0).with_roughness(0.3))
+    ])
+}
+
+// translucent solids lit from above: skin, jade and milk, the light bleeds
+// deeper into the channels with the longer mean free path
+fn subsurface() -> Vec<Box<Hitable>> {
+    let mut world = cornell_room();
+    let boundary = |center: Vec3| -> Arc<Hitable> {
+        Arc::new(Sphere::new(center, 80.0, Arc::new(PassThrough::new(Vec3::zero()))))
+    };
+
+    world.push(Box::new(Subsurface::new(
+        boundary(Vec3::new(420.0, 80.0, 300.0)), Vec3::new(0.95, 0.7, 0.55), Vec3::new(60.0, 20.0, 10.0), 1.4
+    )));
+    world.push(Box::new(Subsurface::new(
+        boundary(Vec3::new(278.0, 80.0, 300.0)), Vec3::new(0.6, 0.95, 0.7), Vec3::new(15.0, 50.0, 25.0), 1.6
+    )));
+    world.push(Box::new(Subsurface::new(
+        boundary(Vec3::new(136.0, 80.0, 300.0)), Vec3::new(0.99, 0.99, 0.98), Vec3::new(30.0, 25.0, 15.0), 1.35
+    )));
+
+    world
+}
+
+// a sphere with a checker pattern cut out of it and a half transparent
+// screen, lit by a one sided panel that only shines down onto them
+fn cutouts() -> Vec<Box<Hitable>> {
+    let mut world = cornell_room();
+    world.remove(2);
+
+    let light: Arc<Material> = Arc::new(DiffuseLight::one_sided(Arc::new(ConstantTexture::new(Vec3::new(15.0, 15.0, 15.0)))));
+    world.push(Box::new(FlipNormal::new(Box::new(XZRect::new(163.0, 393.0, 177.0, 382.0, 554.0, light)))));
+
+    let orange: Arc<Material> = Arc::new(Diffuse::new(Arc::new(ConstantTexture::new(Vec3::new(0.8, 0.4, 0.1)))));
+    let holes = Arc::new(CheckerTexture::new(
+        Box::new(ConstantTexture::new(Vec3::zero())),
+        Box::new(ConstantTexture::new(Vec3::new(1.0, 1.0, 1.0)))
+    ));
+    let sphere: Arc<Hitable> = Arc::new(Sphere::new(Vec3::new(370.0, 120.0, 300.0), 120.0, orange));
+    world.push(Box::new(AlphaMask::new(sphere, holes)));
+
+    let blue: Arc<Material> = Arc::new(Diffuse::new(Arc::new(ConstantTexture::new(Vec3::new(0.1, 0.2, 0.7)))));
+    let screen: Arc<Hitable> = Arc::new(XYRect::new(60.0, 240.0, 0.0, 300.0, 200.0, blue));
+    world.push(Box::new(AlphaMask::new(screen, Arc::new(ConstantTexture::new(Vec3::new(0.4, 0.4, 0.4))))));
+
+    world
+}
+
+// lights given by their power instead of their radiance: a ceiling panel, a
+// small but equally strong downlight and a warm bulb given in lumens, the
+// smaller lights are brighter per area for the same power
+fn lamps() -> Vec<Box<Hitable>> {
+    let mut world = cornell_room();
+    world.remove(2);
+
+    let black: Arc<Material> = Arc::new(PassThrough::new(Vec3::zero()));
+    let white = Vec3::new(1.0, 1.0, 1.0);
+
+    let panel = XZRect::new(60.0, 200.0, 200.0, 340.0, 554.0, Arc::clone(&black));
+    world.push(Box::new(FlipNormal::new(Box::new(AreaLight::from_power(panel, white, 3.0e5, false)))));
+
+    let downlight = Disk::new(Vec3::new(420.0, 554.0, 270.0), Vec3::new(0.0, -1.0, 0.0), 30.0, Arc::clone(&black));
+    world.push(Box::new(AreaLight::from_power(downlight, white, 3.0e5, false)));
+
+    let bulb = Sphere::new(Vec3::new(278.0, 120.0, 300.0), 25.0, black);
+    world.push(Box::new(AreaLight::from_lumens(bulb, spectrum::blackbody_color(2700.0), 1.0e8, false)));
+
+    world
+}
+
+// a ply or stl mesh standing on the floor of the cornell box, scaled to
+// fit. Vertex colors are used where the file has them.
+fn mesh_scene(path: &str) -> io::Result<Vec<Box<Hitable>>> {
+    let mut mesh = if path.to_lowercase().ends_with(".stl") {
+        TriangleMesh::from_stl(path)?
+    } else {
+        TriangleMesh::from_ply(path)?
+    };
+
+    let (scale, offset) = fit_into_room(&mesh.positions);
+    for p in &mut mesh.positions {
+        *p = *p * scale + offset;
+    }
+
+    let mut world = cornell_room();
+    world.extend(mesh.colored_triangles(|texture| Arc::new(Diffuse::new(texture))));
+    Ok(world)
+}
+
+// a curve list file as dark brown hair in the cornell box, fitted like a mesh
+fn curves_scene(path: &str) -> io::Result<Vec<Box<Hitable>>> {
+    let mut set = CurveSet::from_file(path)?;
+
+    let points: Vec<Vec3> = set.curves.iter().flat_map(|curve| curve.0.iter().cloned()).collect();
+    let (scale, offset) = fit_into_room(&points);
+    for curve in &mut set.curves {
+        for p in curve.0.iter_mut() {
+            *p = *p * scale + offset;
+        }
+        curve.1 *= scale;
+        curve.2 *= scale;
+    }
+
+    let hair: Arc<Material> = Arc::new(Hair::new(Arc::new(ConstantTexture::new(Vec3::new(0.3, 0.15, 0.05))), Vec3::new(0.1, 0.1, 0.1), 0.15));
+    let mut world = cornell_room();
+    world.extend(set.hitables(CurveType::Cylinder, hair));
+    Ok(world)
+}
+
+// scale and offset that stand points on the floor in the middle of the
+// cornell box, 350 units across at the widest
+fn fit_into_room(points: &[Vec3]) -> (f32, Vec3) {
+    let mut min = Vec3::new(std::f32::MAX, std::f32::MAX, std::f32::MAX);
+    let mut max = Vec3::new(std::f32::MIN, std::f32::MIN, std::f32::MIN);
+    for p in points {
+        min = Vec3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+        max = Vec3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+    }
+
+    let size = max - min;
+    let scale = 350.0 / size.x.max(size.y).max(size.z).max(1e-6);
+    let offset = Vec3::new(278.0, 0.0, 278.0) - Vec3::new(min.x + 0.5 * size.x, min.y, min.z + 0.5 * size.z) * scale;
+
+    (scale, offset)
+}
+
+// a rectangular light in the ceiling of the cornell box, as a shape for the
+// renderer to sample, its material is never used
+fn ceiling_light(x0: f32, x1: f32, z0: f32, z1: f32) -> Box<Hitable> {
+    let unused: Arc<Material> = Arc::new(PassThrough::new(Vec3::zero()));
+    Box::new(Quad::new(Vec3::new(x0, 554.0, z0), Vec3::new(x1 - x0, 0.0, 0.0), Vec3::new(0.0, 0.0, z1 - z0), unused))
+}
+
+// small lights of different shapes that are sampled directly: a slanted
+// quad, a ring on the back wall and an elliptical spot from a stretched disk
+fn area_lights() -> (Vec<Box<Hitable>>, Vec<Box<Hitable>>) {
+    let mut world = cornell_room();
+    world.remove(2);
+
+    let light: Arc<Material> = Arc::new(DiffuseLight::new(Arc::new(ConstantTexture::new(Vec3::new(20.0, 18.0, 15.0)))));
+    let unused: Arc<Material> = Arc::new(PassThrough::new(Vec3::zero()));
+
+    let quad = |material| Quad::new(Vec3::new(80.0, 500.0, 200.0), Vec3::new(120.0, 40.0, 0.0), Vec3::new(0.0, 0.0, 150.0), material);
+    let ring = |material| Disk::annulus(Vec3::new(380.0, 380.0, 554.0), Vec3::new(0.0, 0.0, -1.0), 40.0, 60.0, material);
+    let spot = |material: Arc<Material>| -> Box<Hitable> {
+        let disk: Arc<Hitable> = Arc::new(Disk::new(Vec3::zero(), Vec3::new(0.0, -1.0, 0.0), 30.0, material));
+        let matrix = Mat4::translation(Vec3::new(400.0, 554.0, 200.0)) * Mat4::scale(Vec3::new(2.5, 1.0, 1.0));
+        Box::new(Transform::new(disk, matrix).unwrap())
+    };
+
+    world.push(Box::new(quad(Arc::clone(&light))));
+    world.push(Box::new(ring(Arc::clone(&light))));
+    world.push(spot(Arc::clone(&light)));
+
+    let white: Arc<Material> = Arc::new(Diffuse::new(Arc::new(ConstantTexture::new(Vec3::new(0.73, 0.73, 0.73)))));
+    world.push(Box::new(Sphere::new(Vec3::new(190.0, 90.0, 190.0), 90.0, Arc::clone(&white))));
+    let block = Arc::new(BoxModel::new(Vec3::zero(), Vec3::new(165.0, 330.0, 165.0), white));
+    world.push(Box::new(Translate::new(Arc::new(RotateY::new(block, 15.0)), Vec3::new(265.0, 0.0, 295.0))));
+
+    let lights: Vec<Box<Hitable>> = vec![Box::new(quad(Arc::clone(&unused))), Box::new(ring(Arc::clone(&unused))), spot(unused)];
+    (world, lights)
+}
+
+// the analytic primitives in the cornell room: a capped marble cylinder, a
+// glass cone frustum, a tilted metal torus and an annulus on the back wall
+fn quadrics() -> Vec<Box<Hitable>> {
+    let mut world = cornell_room();
+
+    world.push(Box::new(Cylinder::new(
+        Vec3::new(130.0, 0.0, 300.0), Vec3::new(0.0, 200.0, 0.0), 60.0, Arc::new(Diffuse::new(Arc::new(NoiseTexture::new(0.05))))
+    ).with_caps()));
+    world.push(Box::new(Cone::new(
+        Vec3::new(280.0, 0.0, 380.0), Vec3::new(0.0, 160.0, 0.0), 70.0, 20.0, Arc::new(Dielectric::new(1.5))
+    ).with_caps()));
+    world.push(Box::new(Torus::new(
+        Vec3::new(420.0, 110.0, 250.0), Vec3::new(1.0, 1.0, -0.5), 80.0, 25.0, Arc::new(Metal::new(Vec3::new(0.8, 0.6, 0.3), 0.1))
+    )));
+    world.push(Box::new(Disk::annulus(
+        Vec3::new(278.0, 330.0, 554.0), Vec3::new(0.0, 0.0, -1.0), 50.0, 120.0, Arc::new(Diffuse::new(Arc::new(ConstantTexture::new(Vec3::new(0.2, 0.3, 0.7)))))
+    )));
+
+    world
+}
+
+// constructive solids: a rounded die from a box and a sphere, a glass ball
+// with a bite taken out of it and a cloud filling the union of two spheres
+fn csg() -> Vec<Box<Hitable>> {
+    let mut world = cornell_room();
+    let red: Arc<Material> = Arc::new(Diffuse::new(Arc::new(ConstantTexture::new(Vec3::new(0.7, 0.1, 0.1)))));
+    let glass: Arc<Material> = Arc::new(Dielectric::new(1.5));
+    let unused: Arc<Material> = Arc::new(PassThrough::new(Vec3::zero()));
+
+    world.push(Box::new(Csg::intersection(
+        Arc::new(BoxModel::new(Vec3::new(340.0, 0.0, 200.0), Vec3::new(480.0, 140.0, 340.0), Arc::clone(&red))),
+        Arc::new(Sphere::new(Vec3::new(410.0, 70.0, 270.0), 95.0, red))
+    )));
+    world.push(Box::new(Csg::difference(
+        Arc::new(Sphere::new(Vec3::new(150.0, 90.0, 250.0), 90.0, Arc::clone(&glass))),
+        Arc::new(Sphere::new(Vec3::new(90.0, 150.0, 190.0), 60.0, glass))
+    )));
+
+    let cloud = Csg::union(
+        Arc::new(Sphere::new(Vec3::new(250.0, 330.0, 420.0), 70.0, Arc::clone(&unused))),
+        Arc::new(Sphere::new(Vec3::new(340.0, 360.0, 420.0), 60.0, unused))
+    );
+    world.push(Box::new(ConstantMedium::new(Arc::new(cloud), 0.02, Arc::new(ConstantTexture::new(Vec3::new(0.9, 0.9, 0.9))))));
+
+    world
+}
+
+// sphere traced distance fields: two blobs melted together, a rounded box
+// with a smooth dent, a twisted column and a torus pierced by a capsule
+fn distance_fields() -> Vec<Box<Hitable>> {
+    let mut world = cornell_room();
+    let bounds = |center: Vec3, half_size: f32| AABB::new(center - half_size, center + half_size);
+    let place = |sdf: Arc<Sdf>, center: Vec3| -> Arc<Sdf> { Arc::new(Translated::new(sdf, center)) };
+
+    let blobs: Arc<Sdf> = Arc::new(SmoothUnion::new(
+        place(Arc::new(SphereSdf::new(50.0)), Vec3::new(110.0, 60.0, 200.0)),
+        place(Arc::new(SphereSdf::new(40.0)), Vec3::new(170.0, 70.0, 220.0)),
+        30.0
+    ));
+    world.push(Box::new(DistanceField::new(blobs, bounds(Vec3::new(140.0, 70.0, 210.0), 100.0), Arc::new(Dielectric::new(1.5)))));
+
+    let dented: Arc<Sdf> = Arc::new(SmoothSubtraction::new(
+        place(Arc::new(RoundedBoxSdf::new(Vec3::new(60.0, 60.0, 60.0), 15.0)), Vec3::new(400.0, 60.0, 180.0)),
+        place(Arc::new(SphereSdf::new(45.0)), Vec3::new(400.0, 120.0, 140.0)),
+        15.0
+    ));
+    world.push(Box::new(DistanceField::new(
+        dented, bounds(Vec3::new(400.0, 60.0, 180.0), 61.0), Arc::new(Diffuse::new(Arc::new(ConstantTexture::new(Vec3::new(0.2, 0.4, 0.7)))))
+    )));
+
+    // full steps overshoot the strongly twisted corners
+    let column = place(Arc::new(Twist::new(Arc::new(BoxSdf::new(Vec3::new(35.0, 130.0, 35.0))), 0.015)), Vec3::new(280.0, 130.0, 400.0));
+    world.push(Box::new(DistanceField::new(
+        column, bounds(Vec3::new(280.0, 130.0, 400.0), 131.0), Arc::new(Metal::new(Vec3::new(0.8, 0.8, 0.85), 0.05))
+    ).with_step_scale(0.6)));
+
+    let ring: Arc<Sdf> = Arc::new(SmoothUnion::new(
+        Arc::new(TorusSdf::new(50.0, 12.0)),
+        Arc::new(CapsuleSdf::new(Vec3::new(-70.0, -30.0, 0.0), Vec3::new(70.0, 30.0, 0.0), 8.0)),
+        0.0
+    ));
+    world.push(Box::new(DistanceField::new(
+        place(ring, Vec3::new(280.0, 380.0, 300.0)), bounds(Vec3::new(280.0, 380.0, 300.0), 80.0),
+        Arc::new(Diffuse::new(Arc::new(ConstantTexture::new(Vec3::new(0.8, 0.6, 0.2)))))
+    )));
+
+    // endless rows of beads, cut to two rows of nine by the bounds
+    let beads = place(Arc::new(Repeat::new(Arc::new(SphereSdf::new(12.0)), Vec3::new(40.0, 0.0, 40.0))), Vec3::new(280.0, 12.0, 80.0));
+    world.push(Box::new(DistanceField::new(
+        beads, AABB::new(Vec3::new(100.0, 0.0, 58.0), Vec3::new(460.0, 24.0, 132.0)), Arc::new(Metal::new(Vec3::new(0.9, 0.7, 0.3), 0.2))
+    )));
+
+    world
+}
+
+// rolling hills over the floor of the room, a heightfield sampled from
+// turbulence with a glass lake filling the lowest valleys
+fn terrain() -> Vec<Box<Hitable>> {
+    let mut world = cornell_room();
+    let ground: Arc<Material> = Arc::new(Diffuse::new(Arc::new(ConstantTexture::new(Vec3::new(0.45, 0.55, 0.3)))));
+
+    world.push(Box::new(Heightfield::from_function(
+        129, 129, Vec3::zero(), Vec3::new(555.0, 250.0, 555.0),
+        |x, z| {
+            let hills = 0.6 + 0.4 * (x * 0.012).sin() * (z * 0.009).cos();
+            (1.4 * perlin::turb(Vec3::new(x, 0.0, z) * 0.004, 3) * hills).min(1.0)
+        },
+        ground
+    )));
+    world.push(Box::new(Quad::new(
+        Vec3::new(0.0, 60.0, 0.0), Vec3::new(0.0, 0.0, 555.0), Vec3::new(555.0, 0.0, 0.0), Arc::new(Dielectric::new(1.33))
+    )));
+
+    world
+}
+
+// a fur ball next to a tuft of grass: curved strands grown out of a sphere
+// and bent down by gravity, and flat blades rising from the floor
+fn hair() -> Vec<Box<Hitable>> {
+    let mut world = cornell_room();
+    let fur: Arc<Material> = Arc::new(Hair::new(Arc::new(ConstantTexture::new(Vec3::new(0.55, 0.3, 0.12))), Vec3::new(0.08, 0.08, 0.08), 0.2));
+    let grass: Arc<Material> = Arc::new(Diffuse::new(Arc::new(ConstantTexture::new(Vec3::new(0.2, 0.5, 0.1)))));
+
+    let center = Vec3::new(360.0, 90.0, 280.0);
+    world.push(Box::new(Sphere::new(center, 70.0, Arc::new(Diffuse::new(Arc::new(ConstantTexture::new(Vec3::new(0.35, 0.2, 0.08))))))));
+    for _ in 0..4000 {
+        let normal = sphere::random_in_unit_sphere().normalize();
+        let root = center + normal * 69.0;
+        let length = 30.0 + 15.0 * random::<f32>();
+        let sag = Vec3::new(0.0, -0.3 * length, 0.0);
+
+        world.push(Box::new(Curve::new(
+            [root, root + normal * (0.4 * length), root + normal * (0.8 * length) + sag * 0.5, root + normal * length + sag],
+            (1.2, 0.2), CurveType::Cylinder, Arc::clone(&fur)
+        )));
+    }
+
+    for _ in 0..600 {
+        let root = Vec3::new(90.0 + 120.0 * random::<f32>(), 0.0, 200.0 + 150.0 * random::<f32>());
+        let lean = Vec3::new(random::<f32>() - 0.5, 0.0, random::<f32>() - 0.5) * 60.0;
+        let height = 80.0 + 80.0 * random::<f32>();
+
+        world.push(Box::new(Curve::new(
+            [root, root + Vec3::new(0.0, 0.5 * height, 0.0), root + Vec3::new(0.0, height, 0.0) + lean * 0.5, root + Vec3::new(0.0, 0.9 * height, 0.0) + lean],
+            (4.0, 0.5), CurveType::Flat, Arc::clone(&grass)
+        )));
+    }
+
+    world
+}
+
+// a grove of one shared tree, trunk and crown built once and placed with
+// different rotations, sizes and foliage materials, under two instanced
+// ceiling lamps that the renderer samples through the instances
+fn instancing() -> (Vec<Box<Hitable>>, Vec<Box<Hitable>>) {
+    let mut world = cornell_room();
+    world.remove(2);
+
+    let bark: Arc<Material> = Arc::new(Diffuse::new(Arc::new(ConstantTexture::new(Vec3::new(0.4, 0.25, 0.1)))));
+    let leaves: Arc<Material> = Arc::new(Diffuse::new(Arc::new(ConstantTexture::new(Vec3::new(0.2, 0.5, 0.15)))));
+    let tree = instance::shared(vec![
+        Box::new(Cylinder::new(Vec3::zero(), Vec3::new(0.0, 40.0, 0.0), 6.0, bark).with_caps()),
+        Box::new(Cone::new(Vec3::new(0.0, 30.0, 0.0), Vec3::new(0.0, 60.0, 0.0), 30.0, 0.0, Arc::clone(&leaves)).with_caps()),
+        Box::new(Sphere::new(Vec3::new(0.0, 95.0, 0.0), 6.0, leaves))
+    ]);
+
+    let autumn: Vec<Arc<Material>> = vec![
+        Arc::new(Diffuse::new(Arc::new(ConstantTexture::new(Vec3::new(0.7, 0.35, 0.05))))),
+        Arc::new(Diffuse::new(Arc::new(ConstantTexture::new(Vec3::new(0.6, 0.1, 0.05))))),
+        Arc::new(Metal::new(Vec3::new(0.9, 0.8, 0.4), 0.3))
+    ];
+
+    for i in 0..5 {
+        for j in 0..4 {
+            let position = Vec3::new(70.0 + 100.0 * i as f32 + 20.0 * (j % 2) as f32, 0.0, 120.0 + 110.0 * j as f32);
+            let size = 0.8 + 0.15 * ((i * 7 + j * 3) % 5) as f32;
+            let matrix = Mat4::translation(position)
+                * Mat4::rotation_axis(Vec3::new(0.0, 1.0, 0.0), 37.0 * (i * 4 + j) as f32)
+                * Mat4::scale(Vec3::new(size, size, size));
+
+            let instance = Instance::new(Arc::clone(&tree), matrix).unwrap();
+            let index = i * 4 + j;
+            if index % 4 == 0 {
+                world.push(Box::new(instance));
+            } else {
+                world.push(Box::new(instance.with_material(Arc::clone(&autumn[index % 3]))));
+            }
+        }
+    }
+
+    // one lamp shape shared by both lamps and the light list
+    let unused: Arc<Material> = Arc::new(PassThrough::new(Vec3::zero()));
+    let light: Arc<Material> = Arc::new(DiffuseLight::new(Arc::new(ConstantTexture::new(Vec3::new(15.0, 15.0, 15.0)))));
+    let lamp = instance::shared(vec![Box::new(Disk::new(Vec3::zero(), Vec3::new(0.0, -1.0, 0.0), 60.0, unused))]);
+    let lamps = || vec![
+        Instance::new(Arc::clone(&lamp), Mat4::translation(Vec3::new(160.0, 554.0, 278.0))).unwrap(),
+        Instance::new(Arc::clone(&lamp), Mat4::translation(Vec3::new(395.0, 554.0, 278.0))).unwrap()
+    ];
+
+    world.extend(lamps().into_iter().map(|lamp| Box::new(lamp.with_material(Arc::clone(&light))) as Box<Hitable>));
+    let lights = lamps().into_iter().map(|lamp| Box::new(lamp) as Box<Hitable>).collect();
+
+    (world, lights)
+}
+
+// keyframed motion: a ball hopping across the room, a box tumbling about
+// its own centre and a glass ball that swells, all blurred over the shutter
+fn motion_blur() -> Vec<Box<Hitable>> {
+    let mut world = cornell_room();
+
+    let orange: Arc<Material> = Arc::new(Diffuse::new(Arc::new(ConstantTexture::new(Vec3::new(0.8, 0.4, 0.1)))));
+    let ball: Arc<Hitable> = Arc::new(Sphere::new(Vec3::zero(), 70.0, orange));
+    let hop = (0..=2).map(|i| {
+        let t = i as f32 / 2.0;
+        let height = if i == 1 { 70.0 } else { 130.0 };
+        Keyframe::translation(t, Vec3::new(340.0 - 60.0 * t, height, 380.0))
+    }).collect();
+    world.push(Box::new(AnimatedTransform::new(ball, hop).unwrap()));
+
+    let white: Arc<Material> = Arc::new(Metal::new(Vec3::new(0.8, 0.85, 0.9), 0.1));
+    let block: Arc<Hitable> = Arc::new(BoxModel::new(Vec3::new(-60.0, -60.0, -60.0), Vec3::new(60.0, 60.0, 60.0), white));
+    let axis = Vec3::new(1.0, 1.0, 0.0).normalize();
+    let tumble = (0..=3).map(|i| {
+        let t = i as f32 / 3.0;
+        Keyframe::new(t, Vec3::new(380.0, 100.0, 160.0), matrix::quaternion_axis(axis, 10.0 * i as f32), Vec3::new(1.0, 1.0, 1.0))
+    }).collect();
+    world.push(Box::new(AnimatedTransform::new(block, tumble).unwrap()));
+
+    let glass: Arc<Material> = Arc::new(Dielectric::new(1.5));
+    let bubble: Arc<Hitable> = Arc::new(Sphere::new(Vec3::zero(), 1.0, glass));
+    world.push(Box::new(AnimatedTransform::new(bubble, vec![
+        Keyframe::new(0.0, Vec3::new(150.0, 80.0, 150.0), [0.0, 0.0, 0.0, 1.0], Vec3::new(40.0, 40.0, 40.0)),
+        Keyframe::new(1.0, Vec3::new(150.0, 80.0, 150.0), [0.0, 0.0, 0.0, 1.0], Vec3::new(80.0, 80.0, 80.0))
+    ]).unwrap()));
+
+    world
+}
+
+// shapes modelled at unit size and scaled into the room, so the solid noise
+// and checker textures that drive their normals come out at a visible size:
+// a bumpy clay ball, a ball of rippled glass and a metal floor of tilted tiles
+fn bump_maps() -> Vec<Box<Hitable>> {
+    let mut world = cornell_room();
+    let noise: Arc<Texture> = Arc::new(NoiseTexture::new(1.0));
+    let scaled = |hitable: Arc<Hitable>, position: Vec3, size: f32| {
+        Box::new(Transform::new(hitable, Mat4::translation(position) * Mat4::scale(Vec3::new(size, size, size))).unwrap()) as Box<Hitable>
+    };
+
+    let clay: Arc<Material> = Arc::new(Diffuse::new(Arc::new(ConstantTexture::new(Vec3::new(0.8, 0.5, 0.3)))));
+    let bumpy: Arc<Material> = Arc::new(BumpMapped::new(clay, Arc::clone(&noise), 0.01));
+    world.push(scaled(Arc::new(Sphere::new(Vec3::zero(), 0.3, bumpy)), Vec3::new(400.0, 90.0, 300.0), 300.0));
+
+    let rippled: Arc<Material> = Arc::new(BumpMapped::new(Arc::new(Dielectric::new(1.5)), noise, 0.005));
+    world.push(scaled(Arc::new(Sphere::new(Vec3::zero(), 0.3, rippled)), Vec3::new(170.0, 90.0, 250.0), 300.0));
+
+    let tiles: Arc<Texture> = Arc::new(CheckerTexture::new(
+        Box::new(ConstantTexture::new(Vec3::new(0.8, 0.8, 0.9))),
+        Box::new(ConstantTexture::new(Vec3::new(0.2, 0.2, 0.9)))
+    ));
+    let floor: Arc<Material> = Arc::new(NormalMapped::new(Arc::new(Metal::new(Vec3::new(0.8, 0.8, 0.85), 0.05)), tiles));
+    world.push(scaled(Arc::new(Quad::new(Vec3::new(-1.0, 0.05, -1.0), Vec3::new(0.0, 0.0, 2.0), Vec3::new(2.0, 0.0, 0.0), floor)), Vec3::new(278.0, -4.0, 278.0), 200.0));
+
+    world
+}
+
+// subdivision with a warning when edges are left longer than asked for
+fn subdivided(mesh: TriangleMesh, max_edge_length: f32) -> TriangleMesh {
+    let (mesh, left) = mesh.subdivide(max_edge_length);
+    if left > 0 {
+        eprintln!("subdivision stopped with {} edges longer than {}", left, max_edge_length);
+    }
+    mesh
+}
+
+// meshes subdivided and displaced by solid noise: an octahedron rounded into
+// a rocky sphere, and a cube of separate facets, as an STL file would have
+// it, whose facets stay joined because displacement moves shared corners
+// together
+fn displacement() -> Vec<Box<Hitable>> {
+    let mut world = cornell_room();
+    let noise = NoiseTexture::new(1.0);
+    let placed = |mut mesh: TriangleMesh, size: f32, position: Vec3| {
+        for p in &mut mesh.positions {
+            *p = *p * size + position;
+        }
+        mesh
+    };
+
+    let corners = [
+        Vec3::new(1.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0),
+        Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0)
+    ];
+    let mut faces = Vec::new();
+    for &x in [0, 1].iter() {
+        for &y in [2, 3].iter() {
+            for &z in [4, 5].iter() {
+                faces.push(if (x + y + z) % 2 == 0 { [x, y, z] } else { [x, z, y] });
+            }
+        }
+    }
+    let mut rock = subdivided(TriangleMesh { positions: corners.to_vec(), normals: None, uvs: None, colors: None, faces }, 0.04);
+    for p in &mut rock.positions {
+        *p = p.normalize();
+    }
+    let rock = placed(rock.displace(&noise, 0.08), 100.0, Vec3::new(390.0, 110.0, 300.0));
+    world.extend(rock.triangles(Arc::new(Diffuse::new(Arc::new(ConstantTexture::new(Vec3::new(0.55, 0.5, 0.45)))))));
+
+    let mut positions = Vec::new();
+    for axis in 0..3 {
+        for &side in [-1.0f32, 1.0].iter() {
+            let corner = |a: f32, b: f32| {
+                let mut p = [0.0; 3];
+                p[axis] = side;
+                p[(axis + 1) % 3] = a * side;
+                p[(axis + 2) % 3] = b;
+                Vec3::new(p[0], p[1], p[2])
+            };
+            positions.extend_from_slice(&[corner(-1.0, -1.0), corner(1.0, -1.0), corner(1.0, 1.0)]);
+            positions.extend_from_slice(&[corner(-1.0, -1.0), corner(1.0, 1.0), corner(-1.0, 1.0)]);
+        }
+    }
+    let faces = (0..positions.len() / 3).map(|i| [3 * i, 3 * i + 1, 3 * i + 2]).collect();
+    let block = subdivided(TriangleMesh { positions, normals: None, uvs: None, colors: None, faces }, 0.05);
+    let block = placed(block.displace(&noise, 0.06), 70.0, Vec3::new(160.0, 80.0, 250.0));
+    world.extend(block.triangles(Arc::new(Metal::new(Vec3::new(0.8, 0.6, 0.3), 0.2))));
+
+    world
+}
+
+// the built in scene by name with the shapes of its sampled lights, and a
+// camera for the scenes that do not use the default view into the cornell box
+fn built_in_scene(name: &str, aspect: f32) -> (Vec<Box<Hitable>>, Vec<Box<Hitable>>, Option<Camera>) {
+    match name {
+        "random_spheres" => {
+            let lookfrom = Vec3::new(13.0, 2.0, 3.0);
+            let camera = Camera::new(lookfrom, Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), 20.0, aspect, 0.1, 10.0, 0.0, 1.0);
+            (create_scene(), Vec::new(), Some(camera))
+        },
+        "simple_light" => {
+            let lookfrom = Vec3::new(26.0, 3.0, 6.0);
+            let camera = Camera::new(lookfrom, Vec3::new(0.0, 2.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 20.0, aspect, 0.0, 10.0, 0.0, 1.0);
+            (simple_light(), Vec::new(), Some(camera))
+        },
+        "light_and_sphere" => (light_and_sphere(), Vec::new(), None),
+        "cornell_box" => (cornell_box(), vec![ceiling_light(213.0, 343.0, 227.0, 332.0)], None),
+        "cornell_smoke" => (cornell_smoke(), vec![ceiling_light(113.0, 443.0, 127.0, 432.0)], None),
+        "smoke" => (smoke(), vec![ceiling_light(213.0, 343.0, 227.0, 332.0)], None),
+        "fire" => (fire(), vec![ceiling_light(213.0, 343.0, 227.0, 332.0)], None),
+        "metals" => (metals(), vec![ceiling_light(213.0, 343.0, 227.0, 332.0)], None),
+        "glass" => (glass(), vec![ceiling_light(213.0, 343.0, 227.0, 332.0)], None),
+        "tinted_glass" => (tinted_glass(), vec![ceiling_light(213.0, 343.0, 227.0, 332.0)], None),
+        "dispersion" => (dispersion(), vec![ceiling_light(213.0, 343.0, 227.0, 332.0)], None),
+        "principled" => (principled(), vec![ceiling_light(213.0, 343.0, 227.0, 332.0)], None),
+        "layered" => (layered(), vec![ceiling_light(213.0, 343.0, 227.0, 332.0)], None),
+        "cloth" => (cloth(), vec![ceiling_light(213.0, 343.0, 227.0, 332.0)], None),
+        "thin_films" => (thin_films(), vec![ceiling_light(213.0, 343.0, 227.0, 332.0)], None),
+        "subsurface" => (subsurface(), vec![ceiling_light(213.0, 343.0, 227.0, 332.0)], None),
+        "cutouts" => (cutouts(), vec![ceiling_light(163.0, 393.0, 177.0, 382.0)], None),
+        "lamps" => (lamps(), vec![ceiling_light(60.0, 200.0, 200.0, 340.0)], None),
+        "area_lights" => {
+            let (world, lights) = area_lights();
+            (world, lights, None)
+        },
+        "quadrics" => (quadrics(), vec![ceiling_light(213.0, 343.0, 227.0, 332.0)], None),
+        "csg" => (csg(), vec![ceiling_light(213.0, 343.0, 227.0, 332.0)], None),
+        "distance_fields" => (distance_fields(), vec![ceiling_light(213.0, 343.0, 227.0, 332.0)], None),
+        "terrain" => (terrain(), vec![ceiling_light(213.0, 343.0, 227.0, 332.0)], None),
+        "hair" => (hair(), vec![ceiling_light(213.0, 343.0, 227.0, 332.0)], None),
+        "instancing" => {
+            let (world, lights) = instancing();
+            (world, lights, None)
+        },
+        "motion_blur" => (motion_blur(), vec![ceiling_light(213.0, 343.0, 227.0, 332.0)], None),
+        "bump_maps" => (bump_maps(), vec![ceiling_light(213.0, 343.0, 227.0, 332.0)], None),
+        "displacement" => (displacement(), vec![ceiling_light(213.0, 343.0, 227.0, 332.0)], None),
+        _ => (the_next_week(), Vec::new(), None)
+    }
+}
+
 fn main() {
     let matches = App::new("rttnw")
         .author("Soeren Vullriede")
@@ -547,6 +1313,29 @@ fn main() {
             .value_name("DEPTH")
             .help("maximum ray depth")
             .takes_value(true))
+        .arg(Arg::with_name("gltf")
+            .long("gltf")
+            .value_name("FILE")
+            .help("render a glTF 2.0 scene instead of the built in one")
+            .takes_value(true))
+        .arg(Arg::with_name("mesh")
+            .long("mesh")
+            .value_name("FILE")
+            .help("render a PLY or STL mesh in the cornell box")
+            .conflicts_with("gltf")
+            .takes_value(true))
+        .arg(Arg::with_name("curves")
+            .long("curves")
+            .value_name("FILE")
+            .help("render a curve list file as hair in the cornell box")
+            .conflicts_with_all(&["gltf", "mesh"])
+            .takes_value(true))
+        .arg(Arg::with_name("scene")
+            .long("scene")
+            .value_name("NAME")
+            .help("built in scene to render")
+            .possible_values(SCENES)
+            .takes_value(true))
         .get_matches();
 
     let start = Instant::now();
@@ -557,15 +1346,49 @@ fn main() {
     let max_ray_depth = matches.value_of("max_ray_depth").unwrap_or("10").parse::<u32>().unwrap();
 
     //scene
-    let mut world = the_next_week();
-    let scene = Scene::new(&mut world, max_ray_depth);
+    let aspect = width as f32 / height as f32;
+    let (mut world, lights, gltf_camera) = match matches.value_of("gltf") {
+        Some(path) => match GltfScene::load(path, aspect) {
+            Ok(ref gltf) if gltf.hitables.is_empty() => {
+                println!("glTF file \"{}\" contains no meshes", path);
+                return;
+            },
+            Ok(gltf) => (gltf.hitables, Vec::new(), gltf.cameras.into_iter().next()),
+            Err(err) => {
+                println!("Error loading glTF file \"{}\": {}", path, err);
+                return;
+            }
+        },
+        None => match matches.value_of("mesh") {
+            Some(path) => match mesh_scene(path) {
+                Ok(world) => (world, vec![ceiling_light(213.0, 343.0, 227.0, 332.0)], None),
+                Err(err) => {
+                    println!("Error loading mesh \"{}\": {}", path, err);
+                    return;
+                }
+            },
+            None => match matches.value_of("curves") {
+                Some(path) => match curves_scene(path) {
+                    Ok(world) => (world, vec![ceiling_light(213.0, 343.0, 227.0, 332.0)], None),
+                    Err(err) => {
+                        println!("Error loading curves \"{}\": {}", path, err);
+                        return;
+                    }
+                },
+                None => built_in_scene(matches.value_of("scene").unwrap_or("next_week"), aspect)
+            }
+        }
+    };
+    let scene = Scene::new(&mut world, max_ray_depth).with_lights(lights);
 
     //camera
     let lookfrom = Vec3::new(278.0, 278.0, -800.0);
     let lookat = Vec3::new(278.0, 278.0, 0.0);
     let dist_to_focus = 10.0;
     let aperture = 0.0;
-    let camera = Camera::new(lookfrom, lookat, Vec3::new(0.0, 1.0, 0.0), 40.0, width as f32 / height as f32, aperture, dist_to_focus, 0.0, 1.0);
+    let camera = gltf_camera.unwrap_or_else(|| {
+        Camera::new(lookfrom, lookat, Vec3::new(0.0, 1.0, 0.0), 40.0, aspect, aperture, dist_to_focus, 0.0, 1.0)
+    });
 
     //render
     let pixels = scene.render(&camera, width, height, rays);
@@ -579,6 +1402,11 @@ fn main() {
         time_secs as f32 + time_millis as f32 / 1000.0
     );
 
+    let truncated = csg::truncated_rays();
+    if truncated > 0 {
+        eprintln!("csg crossings were cut off for {} rays, surfaces behind them may be missing", truncated);
+    }
+
     let filename = "output.png";
     match lodepng::encode24_file(filename, &pixels, width, height) {
         Ok(()) => {}
@@ -1,160 +1,1178 @@
-use crate::ray::Ray;
-use crate::vec::Vec3;
-use crate::hitable::HitRecord;
-use crate::sphere;
-use crate::math;
-use crate::texture::Texture;
-
-use std::sync::Arc;
-use rand::random;
-
-pub struct Scatter {
-    pub attenuation: Vec3,
-    pub ray: Option<Ray>,
-}
-
-impl Scatter {
-    pub fn new(attenuation: Vec3, ray: Option<Ray>) -> Self {
-        Scatter {attenuation, ray}
-    }
-}
-
-pub trait Material: Send + Sync {
-    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Scatter;
-    fn emitted(&self, u: f32, v: f32, p: Vec3) -> Vec3 {
-        Vec3::zero()
-    }
-}
-
-pub struct Diffuse {
-    albedo: Arc<Texture>
-}
-
-impl Diffuse {
-    pub fn new(a: Arc<Texture>) -> Self {
-        Diffuse { albedo: a }
-    }
-}
-
-impl Material for Diffuse {
-    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Scatter {
-        let target = record.p + record.normal + sphere::random_in_unit_sphere();
-        let scattered = Ray::new(record.p, target - record.p, ray.time);
-
-        return Scatter::new(self.albedo.value(record.u, record.v, &record.p), Some(scattered));
-    }
-}
-
-pub struct Metal {
-    albedo: Vec3,
-    fuzz: f32
-}
-
-impl Metal {
-    pub fn new(a: Vec3, fuzz: f32) -> Self {
-        let mut f = 1.0;
-        if fuzz < 1.0 {
-            f = fuzz;
-        }
-        Metal { albedo: a, fuzz: f }
-    }
-}
-
-impl Material for Metal {
-    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Scatter {
-        let reflected = math::reflect(ray.direction.normalize(), record.normal);
-        let scattered = Ray::new(record.p, reflected + sphere::random_in_unit_sphere() * self.fuzz, ray.time);
-
-        if scattered.direction.dot(record.normal) > 0.0 {
-            return Scatter::new(self.albedo, Some(scattered));
-        }
-
-        return Scatter::new(self.albedo, None);
-    }
-}
-
-pub struct Dielectric {
-    pub ref_idx: f32
-}
-
-impl Dielectric {
-    pub fn new(ref_idx: f32) -> Self {
-        Dielectric { ref_idx }
-    }
-}
-
-impl Material for Dielectric {
-    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Scatter {
-        let reflected = math::reflect(ray.direction, record.normal);
-        let attenuation = Vec3::new(1.0, 1.0, 1.0);
-
-        let outward_normal: Vec3;
-        let reflect_prob: f32;
-        let ni_over_nt: f32;
-        let cosine: f32;
-
-
-        if ray.direction.dot(record.normal) > 0.0 {
-            outward_normal = -record.normal;
-            ni_over_nt = self.ref_idx;
-            cosine = self.ref_idx * ray.direction.dot(record.normal) / ray.direction.len();
-        } else {
-            outward_normal = record.normal;
-            ni_over_nt = 1.0 / self.ref_idx;
-            cosine = -ray.direction.dot(record.normal) / ray.direction.len();
-        }
-
-        let refracted = math::refract(ray.direction, outward_normal, ni_over_nt);
-
-        if refracted.is_some() {
-            reflect_prob = math::schlick(cosine, self.ref_idx);
-        } else {
-            reflect_prob = 1.0;
-        }
-
-        if random::<f32>() < reflect_prob {
-            return Scatter::new(attenuation, Some(Ray::new(record.p, reflected, ray.time)));
-        } else {
-            return Scatter::new(attenuation, Some(Ray::new(record.p, refracted.unwrap(), ray.time)))
-        }
-    }
-}
-
-pub struct DiffuseLight {
-    emit: Arc<Texture>
-}
-
-impl DiffuseLight {
-    pub fn new(emit: Arc<Texture>) -> Self {
-        DiffuseLight { emit }
-    }
-}
-
-impl Material for DiffuseLight {
-    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Scatter {
-        Scatter::new(Vec3::zero(), None)
-    }
-
-    fn emitted(&self, u: f32, v: f32, p: Vec3) -> Vec3 {
-        self.emit.value(u, v, &p)
-    }
-}
-
-pub struct Isotropic {
-    albedo: Arc<Texture>
-}
-
-impl Isotropic {
-    pub fn new(albedo: Arc<Texture>) -> Self {
-        Isotropic { albedo }
-    }
-}
-
-impl Material for Isotropic {
-    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Scatter {
-        let scattered = Ray::new(record.p, sphere::random_in_unit_sphere(), ray.time);
-        let attenuation = self.albedo.value(record.u, record.v, &record.p);
-
-        Scatter::new(attenuation, Some(scattered))
-    }
-}
\ No newline at end of file
+use crate::ray::Ray;
+use crate::vec::Vec3;
+use crate::hitable::HitRecord;
+use crate::sphere;
+use crate::math;
+use crate::texture::{Texture, ConstantTexture};
+use crate::onb::{self, Onb};
+use crate::microfacet::Ggx;
+use crate::spectrum::{self, Dispersion};
+
+use std::f32::consts::PI;
+use std::sync::Arc;
+use rand::random;
+
+// step in u and v, or over the surface, for bump map differences
+const BUMP_DELTA: f32 = 0.0005;
+
+pub struct Scatter {
+    pub attenuation: Vec3,
+    pub ray: Option<Ray>,
+}
+
+impl Scatter {
+    pub fn new(attenuation: Vec3, ray: Option<Ray>) -> Self {
+        Scatter {attenuation, ray}
+    }
+}
+
+pub trait Material: Send + Sync {
+    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Scatter;
+    fn emitted(&self, ray: &Ray, record: &HitRecord) -> Vec3 {
+        Vec3::zero()
+    }
+
+    // solid angle density of scattering towards direction for materials
+    // whose rays the renderer may send towards the lights instead. Their
+    // scatter attenuation has to be the same for every direction.
+    fn scattering_pdf(&self, _ray: &Ray, _record: &HitRecord, _direction: Vec3) -> Option<f32> {
+        None
+    }
+}
+
+pub struct Diffuse {
+    albedo: Arc<Texture>
+}
+
+impl Diffuse {
+    pub fn new(a: Arc<Texture>) -> Self {
+        Diffuse { albedo: a }
+    }
+}
+
+impl Material for Diffuse {
+    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Scatter {
+        let frame = Onb::from_w(record.shading_normal);
+        let scattered = Ray::new(record.p, frame.local(onb::random_cosine_direction()), ray.time);
+
+        return Scatter::new(self.albedo.value(record.u, record.v, &record.p), Some(scattered));
+    }
+
+    fn scattering_pdf(&self, _ray: &Ray, record: &HitRecord, direction: Vec3) -> Option<f32> {
+        Some((record.shading_normal.dot(direction.normalize()) / PI).max(0.0))
+    }
+}
+
+// rough diffuse reflection after Oren and Nayar 1994, the roughness texture
+// gives the standard deviation of the facet slopes in radians
+pub struct OrenNayar {
+    albedo: Arc<Texture>,
+    roughness: Arc<Texture>
+}
+
+impl OrenNayar {
+    pub fn new(albedo: Arc<Texture>, roughness: Arc<Texture>) -> Self {
+        OrenNayar { albedo, roughness }
+    }
+}
+
+impl Material for OrenNayar {
+    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Scatter {
+        let normal = if ray.direction.dot(record.normal) > 0.0 { -record.shading_normal } else { record.shading_normal };
+        let frame = Onb::from_w(normal);
+        let wo = frame.to_local(-ray.direction.normalize());
+        let wi = onb::random_cosine_direction();
+
+        let sigma = self.roughness.scalar(record.u, record.v, &record.p);
+        let sigma2 = sigma * sigma;
+        let a = 1.0 - 0.5 * sigma2 / (sigma2 + 0.33);
+        let b = 0.45 * sigma2 / (sigma2 + 0.09);
+
+        let sin_o = (1.0 - wo.z * wo.z).max(0.0).sqrt();
+        let sin_i = (1.0 - wi.z * wi.z).max(0.0).sqrt();
+
+        let mut max_cos = 0.0;
+        if sin_o > 1e-4 && sin_i > 1e-4 {
+            max_cos = ((wi.x * wo.x + wi.y * wo.y) / (sin_i * sin_o)).max(0.0);
+        }
+
+        let (sin_alpha, tan_beta) = if wi.z.abs() > wo.z.abs() {
+            (sin_o, sin_i / wi.z.abs())
+        } else {
+            (sin_i, sin_o / wo.z.abs().max(1e-4))
+        };
+
+        // cosine sampling cancels the cosine and the 1 / pi of the brdf
+        let albedo = self.albedo.value(record.u, record.v, &record.p);
+        let attenuation = albedo * (a + b * max_cos * sin_alpha * tan_beta);
+
+        Scatter::new(attenuation, Some(Ray::new(record.p, frame.local(wi), ray.time)))
+    }
+}
+
+// cloth: a lambertian base with the "Charlie" sheen lobe of Estevez and Kulla
+// 2017 and the visibility term of Neubelt and Pettineo 2013 on top
+pub struct Sheen {
+    albedo: Arc<Texture>,
+    sheen: Arc<Texture>,
+    roughness: Arc<Texture>
+}
+
+impl Sheen {
+    pub fn new(albedo: Arc<Texture>, sheen: Arc<Texture>, roughness: Arc<Texture>) -> Self {
+        Sheen { albedo, sheen, roughness }
+    }
+}
+
+impl Material for Sheen {
+    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Scatter {
+        let normal = if ray.direction.dot(record.normal) > 0.0 { -record.shading_normal } else { record.shading_normal };
+        let frame = Onb::from_w(normal);
+        let wo = frame.to_local(-ray.direction.normalize());
+
+        // sheen peaks at grazing angles where cosine sampling is sparse, so
+        // half of the samples are spread uniformly over the hemisphere
+        let wi = if random::<f32>() < 0.5 {
+            onb::random_cosine_direction()
+        } else {
+            let z = random::<f32>();
+            let r = (1.0 - z * z).max(0.0).sqrt();
+            let phi = 2.0 * PI * random::<f32>();
+            Vec3::new(r * phi.cos(), r * phi.sin(), z)
+        };
+
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Scatter::new(Vec3::zero(), None);
+        }
+
+        let pdf = 0.5 * wi.z / PI + 0.5 / (2.0 * PI);
+
+        let alpha = self.roughness.scalar(record.u, record.v, &record.p).max(0.07).powi(2);
+        let wh = (wo + wi).normalize();
+        let sin_h = (1.0 - wh.z * wh.z).max(0.0).sqrt();
+        let d = (2.0 + 1.0 / alpha) * sin_h.powf(1.0 / alpha) / (2.0 * PI);
+        let visibility = 1.0 / (4.0 * (wi.z + wo.z - wi.z * wo.z));
+
+        let albedo = self.albedo.value(record.u, record.v, &record.p);
+        let sheen = self.sheen.value(record.u, record.v, &record.p);
+        let f = albedo / PI + sheen * (d * visibility);
+
+        Scatter::new(f * (wi.z / pdf), Some(Ray::new(record.p, frame.local(wi), ray.time)))
+    }
+}
+
+// hair and fur after Kajiya and Kay 1989, shaded around the fiber tangent of
+// the hit. The diffuse lobe follows the sine to the fiber, the specular lobe
+// is the cone of mirror directions along the fiber, spread by the
+// longitudinal roughness in radians. Light not reflected specularly is
+// colored by the diffuse texture.
+pub struct Hair {
+    color: Arc<Texture>,
+    specular: Vec3,
+    roughness: f32
+}
+
+impl Hair {
+    pub fn new(color: Arc<Texture>, specular: Vec3, roughness: f32) -> Self {
+        Hair { color, specular, roughness }
+    }
+}
+
+impl Material for Hair {
+    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Scatter {
+        let wo = -ray.direction.normalize();
+        let tangent = record.tangent.map_or_else(|| Onb::from_w(record.shading_normal).u, |tangent| tangent.normalize());
+        let normal = if wo.dot(record.normal) < 0.0 { -record.shading_normal } else { record.shading_normal };
+        let normal = (normal - tangent * normal.dot(tangent)).normalize();
+        let bitangent = tangent.cross(normal);
+
+        let specular_weight = self.specular.x.max(self.specular.y).max(self.specular.z).min(1.0);
+
+        if random::<f32>() < specular_weight {
+            // reflection keeps the angle to the fiber with the opposite sign,
+            // the azimuth is spread over the lit half of the fiber
+            let offset = (-2.0 * (1.0 - random::<f32>()).ln()).sqrt() * (2.0 * PI * random::<f32>()).cos();
+            let theta = -wo.dot(tangent).max(-1.0).min(1.0).asin() + self.roughness * offset;
+            let phi = PI * (random::<f32>() - 0.5);
+            let wi = tangent * theta.sin() + (normal * phi.cos() + bitangent * phi.sin()) * theta.cos();
+
+            return Scatter::new(self.specular / specular_weight, Some(Ray::new(record.p, wi, ray.time)));
+        }
+
+        // uniform sphere sampling, the sine to the fiber averages to pi / 4
+        let wi = sphere::random_in_unit_sphere().normalize();
+        let sin_t = (1.0 - wi.dot(tangent).powi(2)).max(0.0).sqrt();
+        let color = self.color.value(record.u, record.v, &record.p);
+        let weight = sin_t * 4.0 / PI / (1.0 - specular_weight);
+
+        Scatter::new(color * (Vec3::new(1.0, 1.0, 1.0) - self.specular) * weight, Some(Ray::new(record.p, wi, ray.time)))
+    }
+}
+
+pub struct Metal {
+    albedo: Vec3,
+    fuzz: f32
+}
+
+impl Metal {
+    pub fn new(a: Vec3, fuzz: f32) -> Self {
+        let mut f = 1.0;
+        if fuzz < 1.0 {
+            f = fuzz;
+        }
+        Metal { albedo: a, fuzz: f }
+    }
+}
+
+impl Material for Metal {
+    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Scatter {
+        let reflected = math::reflect(ray.direction.normalize(), record.shading_normal);
+        let scattered = Ray::new(record.p, reflected + sphere::random_in_unit_sphere() * self.fuzz, ray.time);
+
+        if scattered.direction.dot(record.normal) > 0.0 {
+            return Scatter::new(self.albedo, Some(scattered));
+        }
+
+        return Scatter::new(self.albedo, None);
+    }
+}
+
+pub struct Conductor {
+    eta: Vec3,
+    k: Vec3,
+    roughness: f32,
+    anisotropy: f32
+}
+
+impl Conductor {
+    pub fn new(eta: Vec3, k: Vec3, roughness: f32, anisotropy: f32) -> Self {
+        Conductor { eta, k, roughness, anisotropy }
+    }
+
+    pub fn gold(roughness: f32) -> Self {
+        Conductor::new(Vec3::new(0.143, 0.374, 1.442), Vec3::new(3.983, 2.385, 1.603), roughness, 0.0)
+    }
+
+    pub fn copper(roughness: f32) -> Self {
+        Conductor::new(Vec3::new(0.200, 0.924, 1.102), Vec3::new(3.912, 2.452, 2.142), roughness, 0.0)
+    }
+
+    pub fn aluminium(roughness: f32) -> Self {
+        Conductor::new(Vec3::new(1.657, 0.880, 0.521), Vec3::new(9.224, 6.270, 4.837), roughness, 0.0)
+    }
+
+    pub fn silver(roughness: f32) -> Self {
+        Conductor::new(Vec3::new(0.155, 0.117, 0.138), Vec3::new(4.828, 3.122, 2.147), roughness, 0.0)
+    }
+
+    pub fn with_anisotropy(mut self, anisotropy: f32) -> Self {
+        self.anisotropy = anisotropy;
+        self
+    }
+
+    fn fresnel(&self, cos_i: f32) -> Vec3 {
+        Vec3::new(
+            math::fresnel_conductor(cos_i, self.eta.x, self.k.x),
+            math::fresnel_conductor(cos_i, self.eta.y, self.k.y),
+            math::fresnel_conductor(cos_i, self.eta.z, self.k.z)
+        )
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Scatter {
+        let frame = Onb::from_tangent(record.shading_normal, record.tangent);
+        let wo = frame.to_local(-ray.direction.normalize());
+
+        if wo.z <= 0.0 {
+            return Scatter::new(Vec3::zero(), None);
+        }
+
+        let ggx = Ggx::from_roughness(self.roughness, self.anisotropy);
+        let wm = ggx.sample_visible_normal(wo, random::<f32>(), random::<f32>());
+        let wi = math::reflect(-wo, wm);
+
+        if wi.z <= 0.0 {
+            return Scatter::new(Vec3::zero(), None);
+        }
+
+        // sampling visible normals leaves only fresnel and the shadowing term
+        let attenuation = self.fresnel(wo.dot(wm)) * (ggx.g2(wo, wi) / ggx.g1(wo));
+        Scatter::new(attenuation, Some(Ray::new(record.p, frame.local(wi), ray.time)))
+    }
+}
+
+pub struct Dielectric {
+    pub ref_idx: f32,
+    pub absorption: Vec3,
+    pub dispersion: Option<Dispersion>
+}
+
+impl Dielectric {
+    pub fn new(ref_idx: f32) -> Self {
+        Dielectric { ref_idx, absorption: Vec3::zero(), dispersion: None }
+    }
+
+    pub fn with_dispersion(mut self, dispersion: Dispersion) -> Self {
+        self.ref_idx = dispersion.ior(FRAUNHOFER_D);
+        self.dispersion = Some(dispersion);
+        self
+    }
+
+    // absorption coefficient per unit of distance travelled inside the object
+    pub fn with_absorption(mut self, absorption: Vec3) -> Self {
+        self.absorption = absorption;
+        self
+    }
+}
+
+impl Material for Dielectric {
+    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Scatter {
+        let normal = refracting_normal(ray, record);
+        let reflected = math::reflect(ray.direction, normal);
+        let (ref_idx, wavelength, tint) = dispersed_ior(self.ref_idx, self.dispersion, ray);
+        let attenuation = interior_transmittance(self.absorption, ray, record) * tint;
+
+        let outward_normal: Vec3;
+        let reflect_prob: f32;
+        let ni_over_nt: f32;
+        let cosine: f32;
+
+
+        if ray.direction.dot(record.normal) > 0.0 {
+            outward_normal = -normal;
+            ni_over_nt = ref_idx;
+            cosine = ray.direction.dot(normal) / ray.direction.len();
+        } else {
+            outward_normal = normal;
+            ni_over_nt = 1.0 / ref_idx;
+            cosine = -ray.direction.dot(normal) / ray.direction.len();
+        }
+
+        let refracted = math::refract(ray.direction, outward_normal, ni_over_nt);
+
+        if refracted.is_some() {
+            reflect_prob = math::fresnel_dielectric(cosine, 1.0 / ni_over_nt);
+        } else {
+            reflect_prob = 1.0;
+        }
+
+        if random::<f32>() < reflect_prob {
+            return Scatter::new(attenuation, Some(Ray::new(record.p, reflected, ray.time).with_wavelength(wavelength)));
+        } else {
+            return Scatter::new(attenuation, Some(Ray::new(record.p, refracted.unwrap(), ray.time).with_wavelength(wavelength)))
+        }
+    }
+}
+
+pub struct RoughDielectric {
+    ref_idx: f32,
+    roughness: f32,
+    absorption: Vec3,
+    dispersion: Option<Dispersion>
+}
+
+impl RoughDielectric {
+    pub fn new(ref_idx: f32, roughness: f32) -> Self {
+        RoughDielectric { ref_idx, roughness, absorption: Vec3::zero(), dispersion: None }
+    }
+
+    pub fn with_dispersion(mut self, dispersion: Dispersion) -> Self {
+        self.ref_idx = dispersion.ior(FRAUNHOFER_D);
+        self.dispersion = Some(dispersion);
+        self
+    }
+
+    pub fn with_absorption(mut self, absorption: Vec3) -> Self {
+        self.absorption = absorption;
+        self
+    }
+}
+
+impl Material for RoughDielectric {
+    // Walter et al. 2007, with the microfacet normal drawn from the visible normals
+    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Scatter {
+        let (ref_idx, wavelength, tint) = dispersed_ior(self.ref_idx, self.dispersion, ray);
+        let attenuation = interior_transmittance(self.absorption, ray, record) * tint;
+
+        let entering = ray.direction.dot(record.normal) < 0.0;
+        let (normal, eta) = if entering {
+            (refracting_normal(ray, record), ref_idx)
+        } else {
+            (-refracting_normal(ray, record), 1.0 / ref_idx)
+        };
+
+        let frame = Onb::from_w(normal);
+        let wo = frame.to_local(-ray.direction.normalize());
+        let ggx = Ggx::from_roughness(self.roughness, 0.0);
+        let wm = ggx.sample_visible_normal(wo, random::<f32>(), random::<f32>());
+
+        let reflect_prob = math::fresnel_dielectric(wo.dot(wm), eta);
+
+        let wi = if random::<f32>() < reflect_prob {
+            let reflected = math::reflect(-wo, wm);
+            if reflected.z <= 0.0 {
+                return Scatter::new(Vec3::zero(), None);
+            }
+            reflected
+        } else {
+            match math::refract(-wo, wm, 1.0 / eta) {
+                Some(refracted) if refracted.z < 0.0 => refracted,
+                _ => return Scatter::new(Vec3::zero(), None)
+            }
+        };
+
+        let shadowing = ggx.g2(wo, wi) / ggx.g1(wo);
+        Scatter::new(attenuation * shadowing, Some(Ray::new(record.p, frame.local(wi), ray.time).with_wavelength(wavelength)))
+    }
+}
+
+// the shading normal unless a map tilted it away from the side the ray
+// arrives on, where cosines turn negative, fresnel leaves [0, 1] and
+// reflections point into the surface. The geometric normal is used then.
+fn refracting_normal(ray: &Ray, record: &HitRecord) -> Vec3 {
+    if ray.direction.dot(record.shading_normal) * ray.direction.dot(record.normal) > 0.0 {
+        record.shading_normal
+    } else {
+        record.normal
+    }
+}
+
+// the catalogue reference wavelength in nm
+const FRAUNHOFER_D: f32 = 587.6;
+
+// white rays pick a wavelength at their first dispersive interface and carry
+// it from then on, the returned tint converts the monochromatic path to rgb
+fn dispersed_ior(ref_idx: f32, dispersion: Option<Dispersion>, ray: &Ray) -> (f32, Option<f32>, Vec3) {
+    match dispersion {
+        Some(dispersion) => {
+            let (wavelength, tint) = match ray.wavelength {
+                Some(wavelength) => (wavelength, Vec3::new(1.0, 1.0, 1.0)),
+                None => spectrum::sample_wavelength(random::<f32>())
+            };
+            (dispersion.ior(wavelength), Some(wavelength), tint)
+        },
+        None => (ref_idx, ray.wavelength, Vec3::new(1.0, 1.0, 1.0))
+    }
+}
+
+// Beer-Lambert falloff for rays that reach the surface from the inside,
+// they travelled from the previous interface to this hit
+fn interior_transmittance(absorption: Vec3, ray: &Ray, record: &HitRecord) -> Vec3 {
+    if ray.direction.dot(record.normal) <= 0.0 {
+        return Vec3::new(1.0, 1.0, 1.0);
+    }
+
+    let distance = record.t * ray.direction.len();
+    Vec3::new(
+        (-absorption.x * distance).exp(),
+        (-absorption.y * distance).exp(),
+        (-absorption.z * distance).exp()
+    )
+}
+
+pub struct MixMaterial {
+    first: Arc<Material>,
+    second: Arc<Material>,
+    mask: Arc<Texture>
+}
+
+impl MixMaterial {
+    // a mask of 0 gives the first material, 1 the second one
+    pub fn new(first: Arc<Material>, second: Arc<Material>, mask: Arc<Texture>) -> Self {
+        MixMaterial { first, second, mask }
+    }
+
+    pub fn with_factor(first: Arc<Material>, second: Arc<Material>, factor: f32) -> Self {
+        MixMaterial::new(first, second, Arc::new(ConstantTexture::new(Vec3::new(factor, factor, factor))))
+    }
+
+    fn factor(&self, u: f32, v: f32, p: &Vec3) -> f32 {
+        self.mask.scalar(u, v, p).max(0.0).min(1.0)
+    }
+}
+
+impl Material for MixMaterial {
+    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Scatter {
+        if random::<f32>() < self.factor(record.u, record.v, &record.p) {
+            self.second.scatter(ray, record)
+        } else {
+            self.first.scatter(ray, record)
+        }
+    }
+
+    fn emitted(&self, ray: &Ray, record: &HitRecord) -> Vec3 {
+        let factor = self.factor(record.u, record.v, &record.p);
+        self.first.emitted(ray, record) * (1.0 - factor) + self.second.emitted(ray, record) * factor
+    }
+}
+
+// tangent space normal map over another material. Colors from 0 to 1 map to
+// -1 to 1, x runs along increasing u, y along increasing v and z along the
+// surface normal, which is what common normal map images use. Only the
+// shading normal is tilted, the geometric normal still tells the material
+// which side of the surface a ray arrives from.
+pub struct NormalMapped {
+    material: Arc<Material>,
+    normal_map: Arc<Texture>
+}
+
+impl NormalMapped {
+    pub fn new(material: Arc<Material>, normal_map: Arc<Texture>) -> Self {
+        NormalMapped { material, normal_map }
+    }
+
+    fn perturb<'a>(&self, record: &HitRecord<'a>) -> HitRecord<'a> {
+        let normal = record.shading_normal;
+
+        // orthonormal frame following the surface derivatives
+        let tangent = record.tangent.unwrap_or_else(|| Onb::from_w(normal).u);
+        let tangent = (tangent - normal * normal.dot(tangent)).normalize();
+        let mut bitangent = normal.cross(tangent);
+        if record.bitangent.map_or(false, |b| b.dot(bitangent) < 0.0) {
+            bitangent = -bitangent;
+        }
+
+        let local = self.normal_map.value(record.u, record.v, &record.p) * 2.0 - 1.0;
+        let perturbed = tangent * local.x + bitangent * local.y + normal * local.z;
+
+        let mut record = *record;
+        if perturbed.len_squared() > 0.0 && perturbed.x.is_finite() {
+            record.shading_normal = perturbed.normalize();
+        }
+        record
+    }
+}
+
+impl Material for NormalMapped {
+    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Scatter {
+        self.material.scatter(ray, &self.perturb(record))
+    }
+
+    fn emitted(&self, ray: &Ray, record: &HitRecord) -> Vec3 {
+        self.material.emitted(ray, &self.perturb(record))
+    }
+}
+
+// bump map over another material: the surface is moved along the normal by
+// scale times the scalar height texture and shaded with the normal of the
+// moved surface. Surfaces with a parameterization step in u and v, so any
+// texture works, others step over the surface in an arbitrary frame, which
+// only suits solid textures such as `NoiseTexture`.
+pub struct BumpMapped {
+    material: Arc<Material>,
+    height: Arc<Texture>,
+    scale: f32
+}
+
+impl BumpMapped {
+    pub fn new(material: Arc<Material>, height: Arc<Texture>, scale: f32) -> Self {
+        BumpMapped { material, height, scale }
+    }
+
+    fn perturb<'a>(&self, record: &HitRecord<'a>) -> HitRecord<'a> {
+        let normal = record.shading_normal;
+        let (dpdu, dpdv, uv_step) = match (record.tangent, record.bitangent) {
+            (Some(tangent), Some(bitangent)) => (tangent, bitangent, BUMP_DELTA),
+            _ => {
+                let frame = Onb::from_w(normal);
+                (frame.u, frame.v, 0.0)
+            }
+        };
+
+        let (u, v, p) = (record.u, record.v, record.p);
+        let height = self.height.scalar(u, v, &p);
+        let height_u = self.height.scalar(u + uv_step, v, &(p + dpdu * BUMP_DELTA));
+        let height_v = self.height.scalar(u, v + uv_step, &(p + dpdv * BUMP_DELTA));
+
+        let displaced_u = dpdu + normal * ((height_u - height) / BUMP_DELTA * self.scale);
+        let displaced_v = dpdv + normal * ((height_v - height) / BUMP_DELTA * self.scale);
+        let perturbed = displaced_u.cross(displaced_v);
+
+        let mut record = *record;
+        if perturbed.len_squared() > 0.0 && perturbed.x.is_finite() {
+            let perturbed = perturbed.normalize();
+            record.shading_normal = if perturbed.dot(normal) < 0.0 { -perturbed } else { perturbed };
+        }
+        record
+    }
+}
+
+impl Material for BumpMapped {
+    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Scatter {
+        self.material.scatter(ray, &self.perturb(record))
+    }
+
+    fn emitted(&self, ray: &Ray, record: &HitRecord) -> Vec3 {
+        self.material.emitted(ray, &self.perturb(record))
+    }
+}
+
+// dielectric clear coat over an arbitrary base material, light either
+// reflects off the coat or passes through it, gets tinted by the coat's
+// absorption on the way in and out and is then handled by the base
+pub struct Coated {
+    base: Arc<Material>,
+    ref_idx: f32,
+    roughness: f32,
+    absorption: Vec3
+}
+
+impl Coated {
+    pub fn new(base: Arc<Material>, ref_idx: f32) -> Self {
+        Coated { base, ref_idx, roughness: 0.0, absorption: Vec3::zero() }
+    }
+
+    pub fn with_roughness(mut self, roughness: f32) -> Self {
+        self.roughness = roughness;
+        self
+    }
+
+    // optical depth of the coat at normal incidence
+    pub fn with_absorption(mut self, absorption: Vec3) -> Self {
+        self.absorption = absorption;
+        self
+    }
+}
+
+impl Material for Coated {
+    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Scatter {
+        if ray.direction.dot(record.normal) >= 0.0 {
+            return self.base.scatter(ray, record);
+        }
+
+        let frame = Onb::from_w(record.shading_normal);
+        let wo = frame.to_local(-ray.direction.normalize());
+        let ggx = Ggx::from_roughness(self.roughness, 0.0);
+        let wm = ggx.sample_visible_normal(wo, random::<f32>(), random::<f32>());
+
+        if random::<f32>() < math::fresnel_dielectric(wo.dot(wm), self.ref_idx) {
+            let wi = math::reflect(-wo, wm);
+            if wi.z <= 0.0 {
+                return Scatter::new(Vec3::zero(), None);
+            }
+
+            let attenuation = Vec3::new(1.0, 1.0, 1.0) * (ggx.g2(wo, wi) / ggx.g1(wo));
+            return Scatter::new(attenuation, Some(Ray::new(record.p, frame.local(wi), ray.time)));
+        }
+
+        let scattered = self.base.scatter(ray, record);
+        match scattered.ray {
+            Some(scattered_ray) => {
+                let cos_i = scattered_ray.direction.normalize().dot(record.shading_normal).abs().max(0.01);
+                let depth = 1.0 / wo.z.max(0.01) + 1.0 / cos_i;
+                let transmittance = Vec3::new(
+                    (-self.absorption.x * depth).exp(),
+                    (-self.absorption.y * depth).exp(),
+                    (-self.absorption.z * depth).exp()
+                );
+                Scatter::new(scattered.attenuation * transmittance, Some(scattered_ray))
+            },
+            None => scattered
+        }
+    }
+
+    fn emitted(&self, ray: &Ray, record: &HitRecord) -> Vec3 {
+        self.base.emitted(ray, record)
+    }
+}
+
+pub struct DiffuseLight {
+    emit: Arc<Texture>,
+    two_sided: bool
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Arc<Texture>) -> Self {
+        DiffuseLight { emit, two_sided: true }
+    }
+
+    // only emits on the side the surface normal points to
+    pub fn one_sided(emit: Arc<Texture>) -> Self {
+        DiffuseLight { emit, two_sided: false }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Scatter {
+        Scatter::new(Vec3::zero(), None)
+    }
+
+    fn emitted(&self, ray: &Ray, record: &HitRecord) -> Vec3 {
+        if !self.two_sided && ray.direction.dot(record.normal) > 0.0 {
+            return Vec3::zero();
+        }
+
+        self.emit.value(record.u, record.v, &record.p)
+    }
+}
+
+pub struct Isotropic {
+    albedo: Arc<Texture>
+}
+
+impl Isotropic {
+    pub fn new(albedo: Arc<Texture>) -> Self {
+        Isotropic { albedo }
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Scatter {
+        let scattered = Ray::new(record.p, sphere::random_in_unit_sphere(), ray.time);
+        let attenuation = self.albedo.value(record.u, record.v, &record.p);
+
+        Scatter::new(attenuation, Some(scattered))
+    }
+}
+
+// continues the ray unchanged, used for null collisions inside media where
+// the attenuation carries the collision weight. A zero attenuation absorbs.
+pub struct PassThrough {
+    attenuation: Vec3
+}
+
+impl PassThrough {
+    pub fn new(attenuation: Vec3) -> Self {
+        PassThrough { attenuation }
+    }
+}
+
+impl Material for PassThrough {
+    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Scatter {
+        if self.attenuation == Vec3::zero() {
+            return Scatter::new(Vec3::zero(), None);
+        }
+
+        Scatter::new(self.attenuation, Some(Ray::new(record.p, ray.direction, ray.time)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a hit at the origin of a surface facing +z, parameterized along x and y
+    fn flat_hit<'a>(material: &'a Material) -> HitRecord<'a> {
+        HitRecord::new(1.0, Vec3::zero(), Vec3::new(0.0, 0.0, 1.0), material, 0.5, 0.5)
+            .with_frame(Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0))
+    }
+
+    fn incoming(direction: Vec3) -> Ray {
+        Ray::new(-direction, direction, 0.0)
+    }
+
+    #[test]
+    fn anisotropic_conductor_follows_the_tangent() {
+        let conductor = Conductor::silver(0.5).with_anisotropy(1.0);
+        let ray = incoming(Vec3::new(0.0, 0.0, -1.0));
+
+        let spread = |record: &HitRecord| {
+            let (mut x, mut y) = (0.0, 0.0);
+            for _ in 0..4000 {
+                if let Some(scattered) = conductor.scatter(&ray, record).ray {
+                    let direction = scattered.direction.normalize();
+                    x += direction.x * direction.x;
+                    y += direction.y * direction.y;
+                }
+            }
+            (x, y)
+        };
+
+        let mut record = flat_hit(&conductor);
+        let (x, y) = spread(&record);
+        assert!(x > 2.0 * y);
+
+        record.tangent = Some(Vec3::new(0.0, 1.0, 0.0));
+        let (x, y) = spread(&record);
+        assert!(y > 2.0 * x);
+    }
+
+    #[test]
+    fn conductor_albedo_matches_the_microfacet_integral() {
+        // a perfect mirror, only the shadowing of the microfacets loses light
+        let conductor = Conductor::new(Vec3::zero(), Vec3::new(1e3, 1e3, 1e3), 0.8, 0.0);
+        let wo = Vec3::new(-0.5, 0.0, 1.0).normalize();
+        let ray = incoming(-wo);
+        let record = flat_hit(&conductor);
+
+        let samples = 40000;
+        let sampled = (0..samples).map(|_| conductor.scatter(&ray, &record).attenuation.x).sum::<f32>() / samples as f32;
+
+        // integral of the brdf times the cosine over the hemisphere
+        let ggx = Ggx::from_roughness(0.8, 0.0);
+        let n = 400;
+        let mut integral = 0.0;
+        for i in 0..n {
+            for j in 0..n {
+                let z = (i as f32 + 0.5) / n as f32;
+                let phi = 2.0 * PI * (j as f32 + 0.5) / n as f32;
+                let r = (1.0 - z * z).sqrt();
+                let wi = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+
+                let f = ggx.d((wo + wi).normalize()) * ggx.g2(wo, wi) / (4.0 * wo.z * wi.z);
+                integral += f * wi.z * 2.0 * PI / (n * n) as f32;
+            }
+        }
+
+        assert!(integral < 1.0);
+        assert!((sampled - integral).abs() < 0.02, "sampled {} integrated {}", sampled, integral);
+    }
+
+    // fraction of the scattered rays that stay on the side they came from
+    fn reflected_fraction(material: &Material, direction: Vec3, samples: usize) -> f32 {
+        let record = flat_hit(material);
+        let ray = incoming(direction);
+        let mut reflected = 0;
+
+        for _ in 0..samples {
+            let scatter = material.scatter(&ray, &record);
+            assert!(scatter.attenuation.x <= 1.0 + 1e-4);
+
+            if scatter.ray.map_or(false, |r| r.direction.z * direction.z < 0.0) {
+                reflected += 1;
+            }
+        }
+
+        reflected as f32 / samples as f32
+    }
+
+    #[test]
+    fn smooth_dielectric_reflects_by_exact_fresnel() {
+        let glass = Dielectric::new(1.5);
+        let direction = Vec3::new(1.0, 0.0, -1.0).normalize();
+
+        let expected = math::fresnel_dielectric(direction.z.abs(), 1.5);
+        assert!((reflected_fraction(&glass, direction, 40000) - expected).abs() < 0.01);
+
+        // from the inside past the critical angle everything is reflected
+        assert_eq!(reflected_fraction(&glass, Vec3::new(1.0, 0.0, 1.0).normalize(), 1000), 1.0);
+    }
+
+    #[test]
+    fn rough_dielectric_approaches_the_smooth_one() {
+        let frosted = RoughDielectric::new(1.5, 0.02);
+        assert!((reflected_fraction(&frosted, Vec3::new(0.0, 0.0, -1.0), 40000) - 0.04).abs() < 0.01);
+
+        // rougher glass spreads the transmitted rays
+        let rough = RoughDielectric::new(1.5, 0.6);
+        let record = flat_hit(&rough);
+        let ray = incoming(Vec3::new(0.0, 0.0, -1.0));
+        let spread = (0..2000)
+            .filter_map(|_| rough.scatter(&ray, &record).ray)
+            .filter(|r| r.direction.z < 0.0)
+            .map(|r| 1.0 - r.direction.normalize().z.abs())
+            .sum::<f32>();
+        assert!(spread > 10.0);
+    }
+
+    #[test]
+    fn absorption_follows_beer_lambert_inside_the_object() {
+        let glass = Dielectric::new(1.5).with_absorption(Vec3::new(0.1, 0.5, 2.0));
+
+        // reaching the surface from the inside after travelling 2 units
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -2.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let record = HitRecord::new(2.0, Vec3::zero(), Vec3::new(0.0, 0.0, 1.0), &glass, 0.0, 0.0);
+        let attenuation = glass.scatter(&ray, &record).attenuation;
+        let expected = Vec3::new((-0.2f32).exp(), (-1.0f32).exp(), (-4.0f32).exp());
+        assert!((attenuation - expected).len() < 1e-5);
+
+        // entering rays are not absorbed yet
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 2.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert_eq!(glass.scatter(&ray, &record).attenuation, Vec3::new(1.0, 1.0, 1.0));
+
+        // the distance is measured in world units, not in t
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -2.0), Vec3::new(0.0, 0.0, 0.5), 0.0);
+        let record = HitRecord::new(4.0, Vec3::zero(), Vec3::new(0.0, 0.0, 1.0), &glass, 0.0, 0.0);
+        assert!((glass.scatter(&ray, &record).attenuation - expected).len() < 1e-5);
+    }
+
+    #[test]
+    fn dispersive_glass_picks_and_keeps_a_wavelength() {
+        let prism = Dielectric::new(1.5).with_dispersion(Dispersion::dense_flint());
+        let record = flat_hit(&prism);
+        let white = incoming(Vec3::new(0.0, 0.0, -1.0));
+
+        let scatter = prism.scatter(&white, &record);
+        let lambda = scatter.ray.unwrap().wavelength.unwrap();
+        assert_eq!(scatter.attenuation, spectrum::wavelength_to_rgb(lambda));
+
+        let colored = incoming(Vec3::new(0.0, 0.0, -1.0)).with_wavelength(Some(480.0));
+        let scatter = prism.scatter(&colored, &record);
+        assert_eq!(scatter.ray.unwrap().wavelength, Some(480.0));
+        assert_eq!(scatter.attenuation, Vec3::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn dispersion_bends_blue_more_than_red() {
+        let prism = Dielectric::new(1.5).with_dispersion(Dispersion::dense_flint());
+        let record = flat_hit(&prism);
+        let direction = Vec3::new(1.0, 0.0, -1.0).normalize();
+
+        // sideways offset of the refracted direction, larger for stronger bending
+        let refracted_x = |lambda: f32| loop {
+            let ray = incoming(direction).with_wavelength(Some(lambda));
+            let scattered = prism.scatter(&ray, &record).ray.unwrap();
+            if scattered.direction.z < 0.0 {
+                break scattered.direction.normalize().x;
+            }
+        };
+
+        assert!(refracted_x(420.0) < refracted_x(680.0));
+    }
+
+    fn white_diffuse() -> Arc<Material> {
+        Arc::new(Diffuse::new(Arc::new(ConstantTexture::new(Vec3::new(1.0, 1.0, 1.0)))))
+    }
+
+    #[test]
+    fn mix_picks_the_second_material_by_the_factor() {
+        let mix = MixMaterial::with_factor(white_diffuse(), Arc::new(PassThrough::new(Vec3::zero())), 0.3);
+        let record = flat_hit(&mix);
+        let ray = incoming(Vec3::new(0.0, 0.0, -1.0));
+
+        let samples = 20000;
+        let absorbed = (0..samples).filter(|_| mix.scatter(&ray, &record).ray.is_none()).count();
+        assert!((absorbed as f32 / samples as f32 - 0.3).abs() < 0.02);
+    }
+
+    #[test]
+    fn mix_blends_the_emission() {
+        let light: Arc<Material> = Arc::new(DiffuseLight::new(Arc::new(ConstantTexture::new(Vec3::new(10.0, 10.0, 10.0)))));
+        let mix = MixMaterial::with_factor(white_diffuse(), light, 0.25);
+        let record = flat_hit(&mix);
+
+        let emitted = mix.emitted(&incoming(Vec3::new(0.0, 0.0, -1.0)), &record);
+        assert!((emitted.x - 2.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn smooth_coat_reflects_by_exact_fresnel() {
+        // a black base, all light that comes back was reflected by the coat
+        let coated = Coated::new(Arc::new(PassThrough::new(Vec3::zero())), 1.5);
+        let direction = Vec3::new(1.0, 0.0, -1.0).normalize();
+        let record = flat_hit(&coated);
+        let ray = incoming(direction);
+
+        let samples = 40000;
+        let reflected = (0..samples).filter(|_| coated.scatter(&ray, &record).ray.is_some()).count();
+        let expected = math::fresnel_dielectric(std::f32::consts::FRAC_1_SQRT_2, 1.5);
+        assert!((reflected as f32 / samples as f32 - expected).abs() < 0.01);
+    }
+
+    #[test]
+    fn coat_absorption_depends_on_the_path_through_the_coat() {
+        let absorption = Vec3::new(0.1, 0.2, 0.4);
+        let coated = Coated::new(white_diffuse(), 1.5).with_absorption(absorption);
+        let record = flat_hit(&coated);
+        let ray = incoming(Vec3::new(0.0, 0.0, -1.0));
+
+        for _ in 0..1000 {
+            let scatter = coated.scatter(&ray, &record);
+            let direction = scatter.ray.unwrap().direction.normalize();
+
+            // the mirror reflection off the coat is not tinted
+            if scatter.attenuation == Vec3::new(1.0, 1.0, 1.0) {
+                assert!(direction.z > 0.9999);
+                continue;
+            }
+
+            let depth = 1.0 + 1.0 / direction.z.max(0.01);
+            assert!((scatter.attenuation.x - (-absorption.x * depth).exp()).abs() < 1e-4);
+            assert!((scatter.attenuation.z - (-absorption.z * depth).exp()).abs() < 1e-4);
+        }
+    }
+
+    fn constant(value: f32) -> Arc<Texture> {
+        Arc::new(ConstantTexture::new(Vec3::new(value, value, value)))
+    }
+
+    fn mean_attenuation(material: &Material, direction: Vec3, samples: usize) -> f32 {
+        let record = flat_hit(material);
+        let ray = incoming(direction);
+
+        (0..samples).map(|_| material.scatter(&ray, &record).attenuation.x).sum::<f32>() / samples as f32
+    }
+
+    #[test]
+    fn smooth_oren_nayar_is_lambertian() {
+        let material = OrenNayar::new(constant(0.6), constant(0.0));
+        let record = flat_hit(&material);
+        let ray = incoming(Vec3::new(1.0, 0.0, -0.5).normalize());
+
+        for _ in 0..100 {
+            assert!((material.scatter(&ray, &record).attenuation.x - 0.6).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn rough_oren_nayar_scatters_back_towards_the_light() {
+        let material = OrenNayar::new(constant(0.8), constant(1.0));
+        let record = flat_hit(&material);
+        let direction = Vec3::new(1.0, 0.0, -0.3).normalize();
+        let ray = incoming(direction);
+
+        let (mut back, mut forward) = (0.0, 0.0);
+        for _ in 0..20000 {
+            let scatter = material.scatter(&ray, &record);
+            if scatter.ray.unwrap().direction.x < 0.0 {
+                back += scatter.attenuation.x;
+            } else {
+                forward += scatter.attenuation.x;
+            }
+        }
+
+        assert!(back > 1.2 * forward);
+    }
+
+    #[test]
+    fn sheen_without_a_sheen_color_is_lambertian() {
+        let material = Sheen::new(constant(0.5), constant(0.0), constant(0.5));
+        let albedo = mean_attenuation(&material, Vec3::new(0.5, 0.0, -1.0).normalize(), 40000);
+        assert!((albedo - 0.5).abs() < 0.02);
+    }
+
+    #[test]
+    fn sheen_grows_towards_grazing_angles() {
+        let material = Sheen::new(constant(0.0), constant(1.0), constant(0.5));
+        let normal = mean_attenuation(&material, Vec3::new(0.0, 0.0, -1.0), 40000);
+        let grazing = mean_attenuation(&material, Vec3::new(1.0, 0.0, -0.1).normalize(), 40000);
+        assert!(grazing > 1.5 * normal, "{} vs {}", grazing, normal);
+    }
+
+    #[test]
+    fn one_sided_light_only_emits_along_the_normal() {
+        let glow = Arc::new(ConstantTexture::new(Vec3::new(4.0, 4.0, 4.0)));
+        let one_sided = DiffuseLight::one_sided(Arc::clone(&glow) as Arc<Texture>);
+        let two_sided = DiffuseLight::new(glow);
+
+        let front = incoming(Vec3::new(0.0, 0.0, -1.0));
+        let back = incoming(Vec3::new(0.0, 0.0, 1.0));
+
+        assert_eq!(one_sided.emitted(&front, &flat_hit(&one_sided)), Vec3::new(4.0, 4.0, 4.0));
+        assert_eq!(one_sided.emitted(&back, &flat_hit(&one_sided)), Vec3::zero());
+        assert_eq!(two_sided.emitted(&back, &flat_hit(&two_sided)), Vec3::new(4.0, 4.0, 4.0));
+    }
+
+    #[test]
+    fn hair_reflects_into_the_mirrored_cone() {
+        // a smooth fully specular fiber along x
+        let hair = Hair::new(constant(0.5), Vec3::new(1.0, 1.0, 1.0), 0.0);
+        let record = flat_hit(&hair);
+        let direction = Vec3::new(0.6, 0.0, -0.8);
+
+        for _ in 0..200 {
+            let scattered = hair.scatter(&incoming(direction), &record).ray.unwrap().direction;
+
+            // the angle to the fiber is kept and the light stays on the lit side
+            assert!((scattered.dot(Vec3::new(1.0, 0.0, 0.0)) - 0.6).abs() < 1e-4);
+            assert!(scattered.z >= -1e-4);
+        }
+    }
+
+    #[test]
+    fn hair_splits_energy_between_the_lobes() {
+        let hair = Hair::new(constant(0.5), Vec3::new(0.2, 0.2, 0.2), 0.1);
+        let mean = mean_attenuation(&hair, Vec3::new(0.3, 0.2, -1.0).normalize(), 200000);
+
+        // specular plus the diffuse color of the rest
+        assert!((mean - (0.2 + 0.5 * 0.8)).abs() < 0.01, "{}", mean);
+    }
+
+    #[test]
+    fn hair_without_a_tangent_still_shades_around_a_fiber() {
+        let hair = Hair::new(constant(1.0), Vec3::zero(), 0.1);
+        let mut record = flat_hit(&hair);
+        record.tangent = None;
+        let ray = incoming(Vec3::new(0.0, 0.0, -1.0));
+
+        // some fiber in the surface plane, the weight follows the sine to it
+        let samples = 100000;
+        let mut sum = 0.0;
+        for _ in 0..samples {
+            let attenuation = hair.scatter(&ray, &record).attenuation.x;
+            assert!(attenuation <= 4.0 / PI + 1e-4);
+            sum += attenuation;
+        }
+
+        assert!((sum / samples as f32 - 1.0).abs() < 0.01);
+    }
+
+    // height rising along u
+    struct Ramp;
+
+    impl Texture for Ramp {
+        fn value(&self, u: f32, _v: f32, _p: &Vec3) -> Vec3 {
+            Vec3::new(u, u, u)
+        }
+    }
+
+    #[test]
+    fn maps_tilt_only_the_shading_normal() {
+        let glass: Arc<Material> = Arc::new(Dielectric::new(1.5));
+        let tilted = Arc::new(ConstantTexture::new(Vec3::new(0.5 + 0.5 * 0.8, 0.5, 0.5 + 0.5 * 0.6)));
+        let normal_mapped = NormalMapped::new(Arc::clone(&glass), tilted);
+        let bump_mapped = BumpMapped::new(glass, Arc::new(Ramp), 1.0);
+
+        let record = normal_mapped.perturb(&flat_hit(&normal_mapped));
+        assert_eq!(record.normal, Vec3::new(0.0, 0.0, 1.0));
+        assert!((record.shading_normal - Vec3::new(0.8, 0.0, 0.6)).len() < 1e-5);
+
+        let record = bump_mapped.perturb(&flat_hit(&bump_mapped));
+        assert_eq!(record.normal, Vec3::new(0.0, 0.0, 1.0));
+        assert!((record.shading_normal - Vec3::new(-1.0, 0.0, 1.0).normalize()).len() < 1e-3);
+    }
+
+    #[test]
+    fn tilted_shading_normals_do_not_flip_the_side() {
+        // the map leans the normal away from the ray, which still arrives
+        // from outside and must not be absorbed as if it left the glass
+        let glass: Arc<Material> = Arc::new(Dielectric::new(1.5).with_absorption(Vec3::new(1.0, 1.0, 1.0)));
+        let tilted = Arc::new(ConstantTexture::new(Vec3::new(0.5 + 0.5 * 0.95, 0.5, 0.5 + 0.5 * 0.31)));
+        let mapped = NormalMapped::new(glass, tilted);
+
+        let ray = incoming(Vec3::new(1.0, 0.0, -0.1).normalize());
+        let record = flat_hit(&mapped);
+        assert!(ray.direction.dot(mapped.perturb(&record).shading_normal) > 0.0);
+
+        // reflections stay above the surface as the mirror image, refracted
+        // rays go below it, bent towards the normal
+        let mirrored = Vec3::new(ray.direction.x, ray.direction.y, -ray.direction.z);
+        let (mut reflected, mut refracted) = (0, 0);
+        for _ in 0..1000 {
+            let scatter = mapped.scatter(&ray, &record);
+            assert_eq!(scatter.attenuation, Vec3::new(1.0, 1.0, 1.0));
+
+            let direction = scatter.ray.unwrap().direction.normalize();
+            if direction.z > 0.0 {
+                assert!((direction - mirrored).len() < 1e-5, "{:?}", direction);
+                reflected += 1;
+            } else {
+                assert!(direction.x > 0.0 && direction.x < ray.direction.x, "{:?}", direction);
+                refracted += 1;
+            }
+        }
+        assert!(reflected > 0 && refracted > 0);
+
+        let rough = NormalMapped::new(Arc::new(RoughDielectric::new(1.5, 0.1)), Arc::new(ConstantTexture::new(Vec3::new(0.975, 0.5, 0.655))));
+        let record = flat_hit(&rough);
+        for _ in 0..1000 {
+            let attenuation = rough.scatter(&ray, &record).attenuation;
+            assert!(attenuation.x >= 0.0 && attenuation.x <= 1.0, "{:?}", attenuation);
+        }
+    }
+
+    #[test]
+    fn diffuse_shades_with_the_shading_normal() {
+        let diffuse = Diffuse::new(Arc::new(ConstantTexture::new(Vec3::new(0.5, 0.5, 0.5))));
+        let mut record = flat_hit(&diffuse);
+        record.shading_normal = Vec3::new(1.0, 0.0, 0.0);
+
+        let pdf = diffuse.scattering_pdf(&incoming(Vec3::new(0.0, 0.0, -1.0)), &record, Vec3::new(1.0, 0.0, 0.0)).unwrap();
+        assert!((pdf - 1.0 / PI).abs() < 1e-6);
+        for _ in 0..100 {
+            let direction = diffuse.scatter(&incoming(Vec3::new(0.0, 0.0, -1.0)), &record).ray.unwrap().direction;
+            assert!(direction.x >= 0.0);
+        }
+    }
+}
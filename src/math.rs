@@ -1,24 +1,279 @@
-use crate::vec::Vec3;
-
-pub fn reflect(v: Vec3, n: Vec3) -> Vec3 {
-    return v - n * (2.0 * v.dot(n));
-}
-
-pub fn refract(v: Vec3, n: Vec3, ni_over_nt: f32) -> Option<Vec3> {
-    let uv = v.normalize();
-    let dt = uv.dot(n);
-    let discriminant = 1.0 - ni_over_nt * ni_over_nt * (1.0 - dt * dt);
-
-    if discriminant > 0.0 {
-        let refracted = (uv - n * dt) * ni_over_nt - n * discriminant.sqrt();
-        return Some(refracted);
-    }
-
-    return None;
-}
-
-pub fn schlick(cosine: f32, ref_idx: f32) -> f32 {
-    let mut r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
-    r0 = r0 * r0;
-    return r0 + (1.0 - r0) * (1.0 - cosine).powi(5);
-}
\ No newline at end of file
+use crate::vec::Vec3;
+
+pub fn reflect(v: Vec3, n: Vec3) -> Vec3 {
+    return v - n * (2.0 * v.dot(n));
+}
+
+pub fn refract(v: Vec3, n: Vec3, ni_over_nt: f32) -> Option<Vec3> {
+    let uv = v.normalize();
+    let dt = uv.dot(n);
+    let discriminant = 1.0 - ni_over_nt * ni_over_nt * (1.0 - dt * dt);
+
+    if discriminant > 0.0 {
+        let refracted = (uv - n * dt) * ni_over_nt - n * discriminant.sqrt();
+        return Some(refracted);
+    }
+
+    return None;
+}
+
+// unpolarized fresnel reflectance of a dielectric interface, eta is the
+// index of the side being entered over the index of the incident side
+pub fn fresnel_dielectric(cos_i: f32, eta: f32) -> f32 {
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let rs = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let rp = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+
+    0.5 * (rs * rs + rp * rp)
+}
+
+// unpolarized fresnel reflectance of a conductor with complex index eta + ik
+pub fn fresnel_conductor(cos_i: f32, eta: f32, k: f32) -> f32 {
+    let cos2 = cos_i * cos_i;
+    let sin2 = 1.0 - cos2;
+    let eta2 = eta * eta;
+    let k2 = k * k;
+
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos_i * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    0.5 * (rp + rs)
+}
+
+const SOLVER_EPSILON: f64 = 1e-9;
+
+// real roots of a x^2 + b x + c in ascending order, avoiding cancellation
+pub fn solve_quadratic(a: f32, b: f32, c: f32) -> Option<(f32, f32)> {
+    let roots = quadratic_roots(a as f64, b as f64, c as f64);
+
+    match roots.len() {
+        0 => None,
+        1 => Some((roots[0] as f32, roots[0] as f32)),
+        _ => Some((roots[0] as f32, roots[1] as f32))
+    }
+}
+
+// real roots of c4 x^4 + c3 x^3 + c2 x^2 + c1 x + c0 in ascending order.
+// Closed form from Schwarze, "Cubic and Quartic Roots" (Graphics Gems I),
+// polished with a few newton steps on the original polynomial.
+pub fn solve_quartic(c4: f64, c3: f64, c2: f64, c1: f64, c0: f64) -> Vec<f64> {
+    let mut roots = if c4.abs() < SOLVER_EPSILON {
+        solve_cubic(c3, c2, c1, c0)
+    } else {
+        quartic_roots(c4, c3, c2, c1, c0)
+    };
+
+    roots.sort_by(|x, y| x.partial_cmp(y).unwrap_or(std::cmp::Ordering::Equal));
+    roots
+}
+
+// unsorted roots of a quartic with a nonzero leading coefficient
+fn quartic_roots(c4: f64, c3: f64, c2: f64, c1: f64, c0: f64) -> Vec<f64> {
+    let a = c3 / c4;
+    let b = c2 / c4;
+    let c = c1 / c4;
+    let d = c0 / c4;
+
+    // substitute x = y - a / 4 to eliminate the cubic term
+    let sq_a = a * a;
+    let p = -3.0 / 8.0 * sq_a + b;
+    let q = 1.0 / 8.0 * sq_a * a - 0.5 * a * b + c;
+    let r = -3.0 / 256.0 * sq_a * sq_a + 1.0 / 16.0 * sq_a * b - 0.25 * a * c + d;
+
+    let mut roots = if r.abs() < SOLVER_EPSILON {
+        let mut roots = solve_cubic(1.0, 0.0, p, q);
+        roots.push(0.0);
+        roots
+    } else {
+        let z = solve_cubic(1.0, -0.5 * p, -r, 0.5 * r * p - 0.125 * q * q)[0];
+
+        let u = z * z - r;
+        let v = 2.0 * z - p;
+        if u < -SOLVER_EPSILON || v < -SOLVER_EPSILON {
+            return Vec::new();
+        }
+
+        let u = u.max(0.0).sqrt();
+        let v = if q < 0.0 { -v.max(0.0).sqrt() } else { v.max(0.0).sqrt() };
+
+        let mut roots = quadratic_roots(1.0, v, z - u);
+        roots.extend(quadratic_roots(1.0, -v, z + u));
+        roots
+    };
+
+    let polynomial = |x: f64| (((c4 * x + c3) * x + c2) * x + c1) * x + c0;
+    let derivative = |x: f64| ((4.0 * c4 * x + 3.0 * c3) * x + 2.0 * c2) * x + c1;
+
+    for root in roots.iter_mut() {
+        *root -= 0.25 * a;
+
+        for _ in 0..3 {
+            let slope = derivative(*root);
+            if slope.abs() < SOLVER_EPSILON {
+                break;
+            }
+            *root -= polynomial(*root) / slope;
+        }
+    }
+
+    roots
+}
+
+// real roots of c3 x^3 + c2 x^2 + c1 x + c0, unsorted
+pub fn solve_cubic(c3: f64, c2: f64, c1: f64, c0: f64) -> Vec<f64> {
+    if c3.abs() < SOLVER_EPSILON {
+        return quadratic_roots(c2, c1, c0);
+    }
+
+    let a = c2 / c3;
+    let b = c1 / c3;
+    let c = c0 / c3;
+
+    // substitute x = y - a / 3 to eliminate the quadratic term
+    let sq_a = a * a;
+    let p = 1.0 / 3.0 * (-1.0 / 3.0 * sq_a + b);
+    let q = 0.5 * (2.0 / 27.0 * a * sq_a - 1.0 / 3.0 * a * b + c);
+
+    let cb_p = p * p * p;
+    let discriminant = q * q + cb_p;
+
+    let mut roots = if discriminant.abs() < SOLVER_EPSILON {
+        if q.abs() < SOLVER_EPSILON {
+            vec![0.0]
+        } else {
+            let u = (-q).cbrt();
+            vec![2.0 * u, -u]
+        }
+    } else if discriminant < 0.0 {
+        let phi = 1.0 / 3.0 * (-q / (-cb_p).sqrt()).max(-1.0).min(1.0).acos();
+        let t = 2.0 * (-p).sqrt();
+        vec![
+            t * phi.cos(),
+            -t * (phi + std::f64::consts::PI / 3.0).cos(),
+            -t * (phi - std::f64::consts::PI / 3.0).cos()
+        ]
+    } else {
+        let sqrt_d = discriminant.sqrt();
+        vec![(sqrt_d - q).cbrt() - (sqrt_d + q).cbrt()]
+    };
+
+    for root in roots.iter_mut() {
+        *root -= a / 3.0;
+    }
+
+    roots
+}
+
+fn quadratic_roots(a: f64, b: f64, c: f64) -> Vec<f64> {
+    if a.abs() < SOLVER_EPSILON {
+        if b.abs() < SOLVER_EPSILON {
+            return Vec::new();
+        }
+        return vec![-c / b];
+    }
+
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return Vec::new();
+    }
+
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    let (x0, x1) = if q == 0.0 { (0.0, 0.0) } else { (q / a, c / q) };
+
+    if x0 < x1 { vec![x0, x1] } else { vec![x1, x0] }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conductor_fresnel_at_normal_and_grazing_incidence() {
+        let (eta, k) = (0.2, 3.9);
+        let expected = ((eta - 1.0) * (eta - 1.0) + k * k) / ((eta + 1.0) * (eta + 1.0) + k * k);
+
+        assert!((fresnel_conductor(1.0, eta, k) - expected).abs() < 1e-5);
+        assert!(fresnel_conductor(1e-4, eta, k) > 0.999);
+    }
+
+    #[test]
+    fn dielectric_fresnel() {
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-6);
+        assert!((fresnel_dielectric(1.0, 1.0 / 1.5) - 0.04).abs() < 1e-6);
+
+        // a conductor without extinction is a dielectric
+        for &cos_i in &[0.1, 0.4, 0.9] {
+            assert!((fresnel_dielectric(cos_i, 1.5) - fresnel_conductor(cos_i, 1.5, 0.0)).abs() < 1e-5);
+        }
+
+        // total internal reflection past the critical angle of 41.8 degrees
+        assert_eq!(fresnel_dielectric(45.0f32.to_radians().cos(), 1.0 / 1.5), 1.0);
+        assert!(fresnel_dielectric(40.0f32.to_radians().cos(), 1.0 / 1.5) < 1.0);
+    }
+
+    fn assert_roots(roots: Vec<f64>, expected: &[f64]) {
+        assert_eq!(roots.len(), expected.len(), "{:?}", roots);
+        for (root, expected) in roots.iter().zip(expected) {
+            assert!((root - expected).abs() < 1e-6, "{:?} vs {:?}", roots, expected);
+        }
+    }
+
+    fn sorted(mut roots: Vec<f64>) -> Vec<f64> {
+        roots.sort_by(|x, y| x.partial_cmp(y).unwrap());
+        roots
+    }
+
+    #[test]
+    fn solves_quartics() {
+        // (x - 1)(x - 2)(x - 3)(x - 4)
+        assert_roots(solve_quartic(1.0, -10.0, 35.0, -50.0, 24.0), &[1.0, 2.0, 3.0, 4.0]);
+
+        // (x + 0.5)(x - 3)(x^2 + 1), a scaled leading coefficient
+        assert_roots(solve_quartic(2.0, -5.0, -1.0, -5.0, -3.0), &[-0.5, 3.0]);
+
+        // double roots at 1 and 2
+        let roots = solve_quartic(1.0, -6.0, 13.0, -12.0, 4.0);
+        assert!(!roots.is_empty());
+        assert!(roots.iter().all(|x| (x - 1.0).abs() < 1e-3 || (x - 2.0).abs() < 1e-3), "{:?}", roots);
+
+        // x^4 + 1 has no real roots
+        assert!(solve_quartic(1.0, 0.0, 0.0, 0.0, 1.0).is_empty());
+
+        // without the quartic term it is the cubic (x - 1)(x + 1)(x - 2)
+        assert_roots(solve_quartic(0.0, 1.0, -2.0, -1.0, 2.0), &[-1.0, 1.0, 2.0]);
+    }
+
+    #[test]
+    fn solves_cubics() {
+        assert_roots(sorted(solve_cubic(1.0, -6.0, 11.0, -6.0)), &[1.0, 2.0, 3.0]);
+        assert_roots(sorted(solve_cubic(3.0, -6.0, 3.0, -6.0)), &[2.0]);
+        assert_roots(solve_cubic(1.0, -3.0, 3.0, -1.0), &[1.0]);
+
+        // falls back to the quadratic and the linear case
+        assert_roots(sorted(solve_cubic(0.0, 1.0, -1.0, -2.0)), &[-1.0, 2.0]);
+        assert_roots(solve_cubic(0.0, 0.0, 2.0, -1.0), &[0.5]);
+    }
+
+    #[test]
+    fn solves_quadratics_without_cancellation() {
+        // roots near 1e4 and 1e-4 lose the small one to cancellation in the textbook formula
+        let (x0, x1) = solve_quadratic(1.0, -1e4, 1.0).unwrap();
+        assert!((x0 - 1e-4).abs() < 1e-9 && (x1 - 1e4).abs() < 1e-1);
+
+        assert_eq!(solve_quadratic(1.0, 0.0, -4.0), Some((-2.0, 2.0)));
+        assert_eq!(solve_quadratic(1.0, 0.0, 4.0), None);
+    }
+}
@@ -0,0 +1,321 @@
+use crate::vec::Vec3;
+
+use std::ops::Mul;
+
+// row major 4x4 affine transform, points are column vectors
+#[derive(Clone, Copy, Debug)]
+pub struct Mat4 {
+    pub m: [[f32; 4]; 4]
+}
+
+impl Mat4 {
+    pub fn new(m: [[f32; 4]; 4]) -> Self {
+        Mat4 { m }
+    }
+
+    pub fn identity() -> Self {
+        Mat4::new([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0]
+        ])
+    }
+
+    // column major storage as used by glTF and OpenGL
+    pub fn from_columns(columns: [[f32; 4]; 4]) -> Self {
+        Mat4::new(columns).transpose()
+    }
+
+    pub fn translation(offset: Vec3) -> Self {
+        let mut matrix = Mat4::identity();
+        matrix.m[0][3] = offset.x;
+        matrix.m[1][3] = offset.y;
+        matrix.m[2][3] = offset.z;
+        matrix
+    }
+
+    pub fn scale(scale: Vec3) -> Self {
+        let mut matrix = Mat4::identity();
+        matrix.m[0][0] = scale.x;
+        matrix.m[1][1] = scale.y;
+        matrix.m[2][2] = scale.z;
+        matrix
+    }
+
+    // rotation by a unit quaternion given as [x, y, z, w]
+    pub fn rotation(q: [f32; 4]) -> Self {
+        let [x, y, z, w] = q;
+
+        Mat4::new([
+            [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - z * w), 2.0 * (x * z + y * w), 0.0],
+            [2.0 * (x * y + z * w), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - x * w), 0.0],
+            [2.0 * (x * z - y * w), 2.0 * (y * z + x * w), 1.0 - 2.0 * (x * x + y * y), 0.0],
+            [0.0, 0.0, 0.0, 1.0]
+        ])
+    }
+
+    // rotation around a unit axis by an angle in degrees
+    pub fn rotation_axis(axis: Vec3, degrees: f32) -> Self {
+        Mat4::rotation(quaternion_axis(axis, degrees))
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut result = [[0.0; 4]; 4];
+
+        for i in 0..4 {
+            for j in 0..4 {
+                result[i][j] = self.m[j][i];
+            }
+        }
+
+        Mat4::new(result)
+    }
+
+    // general inverse by cofactor expansion, None for singular matrices
+    pub fn inverse(&self) -> Option<Self> {
+        let m = &self.m;
+
+        let s0 = m[0][0] * m[1][1] - m[1][0] * m[0][1];
+        let s1 = m[0][0] * m[1][2] - m[1][0] * m[0][2];
+        let s2 = m[0][0] * m[1][3] - m[1][0] * m[0][3];
+        let s3 = m[0][1] * m[1][2] - m[1][1] * m[0][2];
+        let s4 = m[0][1] * m[1][3] - m[1][1] * m[0][3];
+        let s5 = m[0][2] * m[1][3] - m[1][2] * m[0][3];
+
+        let c5 = m[2][2] * m[3][3] - m[3][2] * m[2][3];
+        let c4 = m[2][1] * m[3][3] - m[3][1] * m[2][3];
+        let c3 = m[2][1] * m[3][2] - m[3][1] * m[2][2];
+        let c2 = m[2][0] * m[3][3] - m[3][0] * m[2][3];
+        let c1 = m[2][0] * m[3][2] - m[3][0] * m[2][2];
+        let c0 = m[2][0] * m[3][1] - m[3][0] * m[2][1];
+
+        let det = s0 * c5 - s1 * c4 + s2 * c3 + s3 * c2 - s4 * c1 + s5 * c0;
+        if det.abs() < 1e-12 {
+            return None;
+        }
+
+        let inv = 1.0 / det;
+
+        Some(Mat4::new([
+            [
+                (m[1][1] * c5 - m[1][2] * c4 + m[1][3] * c3) * inv,
+                (-m[0][1] * c5 + m[0][2] * c4 - m[0][3] * c3) * inv,
+                (m[3][1] * s5 - m[3][2] * s4 + m[3][3] * s3) * inv,
+                (-m[2][1] * s5 + m[2][2] * s4 - m[2][3] * s3) * inv
+            ],
+            [
+                (-m[1][0] * c5 + m[1][2] * c2 - m[1][3] * c1) * inv,
+                (m[0][0] * c5 - m[0][2] * c2 + m[0][3] * c1) * inv,
+                (-m[3][0] * s5 + m[3][2] * s2 - m[3][3] * s1) * inv,
+                (m[2][0] * s5 - m[2][2] * s2 + m[2][3] * s1) * inv
+            ],
+            [
+                (m[1][0] * c4 - m[1][1] * c2 + m[1][3] * c0) * inv,
+                (-m[0][0] * c4 + m[0][1] * c2 - m[0][3] * c0) * inv,
+                (m[3][0] * s4 - m[3][1] * s2 + m[3][3] * s0) * inv,
+                (-m[2][0] * s4 + m[2][1] * s2 - m[2][3] * s0) * inv
+            ],
+            [
+                (-m[1][0] * c3 + m[1][1] * c1 - m[1][2] * c0) * inv,
+                (m[0][0] * c3 - m[0][1] * c1 + m[0][2] * c0) * inv,
+                (-m[3][0] * s3 + m[3][1] * s1 - m[3][2] * s0) * inv,
+                (m[2][0] * s3 - m[2][1] * s1 + m[2][2] * s0) * inv
+            ]
+        ]))
+    }
+
+    pub fn transform_point(&self, p: Vec3) -> Vec3 {
+        let m = &self.m;
+
+        Vec3::new(
+            m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3],
+            m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3],
+            m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3]
+        )
+    }
+
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.m;
+
+        Vec3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z
+        )
+    }
+
+    // normals transform with the inverse transpose, this is called on the
+    // inverse matrix and applies its transpose
+    pub fn transform_normal(&self, n: Vec3) -> Vec3 {
+        let m = &self.m;
+
+        Vec3::new(
+            m[0][0] * n.x + m[1][0] * n.y + m[2][0] * n.z,
+            m[0][1] * n.x + m[1][1] * n.y + m[2][1] * n.z,
+            m[0][2] * n.x + m[1][2] * n.y + m[2][2] * n.z
+        )
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, other: Mat4) -> Mat4 {
+        let mut result = [[0.0; 4]; 4];
+
+        for i in 0..4 {
+            for j in 0..4 {
+                result[i][j] = (0..4).map(|k| self.m[i][k] * other.m[k][j]).sum();
+            }
+        }
+
+        Mat4::new(result)
+    }
+}
+
+// unit quaternion [x, y, z, w] rotating around an axis by an angle in degrees
+pub fn quaternion_axis(axis: Vec3, degrees: f32) -> [f32; 4] {
+    let half = degrees.to_radians() / 2.0;
+    let axis = axis.normalize() * half.sin();
+
+    [axis.x, axis.y, axis.z, half.cos()]
+}
+
+// spherical interpolation of unit quaternions along the shorter arc
+pub fn slerp(a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+    let mut cos_theta = a[0] * b[0] + a[1] * b[1] + a[2] * b[2] + a[3] * b[3];
+    let b = if cos_theta < 0.0 {
+        cos_theta = -cos_theta;
+        [-b[0], -b[1], -b[2], -b[3]]
+    } else {
+        b
+    };
+
+    // nearly parallel, fall back to a normalized lerp
+    let (wa, wb) = if cos_theta > 0.9995 {
+        (1.0 - t, t)
+    } else {
+        let theta = cos_theta.acos();
+        let sin_theta = theta.sin();
+        (((1.0 - t) * theta).sin() / sin_theta, (t * theta).sin() / sin_theta)
+    };
+
+    let q = [wa * a[0] + wb * b[0], wa * a[1] + wb * b[1], wa * a[2] + wb * b[2], wa * a[3] + wb * b[3]];
+    let length = (q[0] * q[0] + q[1] * q[1] + q[2] * q[2] + q[3] * q[3]).sqrt();
+
+    [q[0] / length, q[1] / length, q[2] / length, q[3] / length]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_identity(m: Mat4) {
+        for i in 0..4 {
+            for j in 0..4 {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!((m.m[i][j] - expected).abs() < 1e-5, "{:?}", m);
+            }
+        }
+    }
+
+    #[test]
+    fn inverse_undoes_the_transform() {
+        let matrix = Mat4::translation(Vec3::new(3.0, -2.0, 5.0))
+            * Mat4::rotation_axis(Vec3::new(1.0, 2.0, -0.5), 37.0)
+            * Mat4::scale(Vec3::new(2.0, 0.5, 3.0));
+        let inverse = matrix.inverse().unwrap();
+
+        assert_identity(matrix * inverse);
+        assert_identity(inverse * matrix);
+
+        let p = Vec3::new(0.3, -1.2, 4.0);
+        assert!((inverse.transform_point(matrix.transform_point(p)) - p).len() < 1e-5);
+    }
+
+    #[test]
+    fn inverse_of_a_general_matrix() {
+        // not affine, every cofactor takes part
+        let matrix = Mat4::new([
+            [2.0, 1.0, 0.0, 3.0],
+            [0.0, 1.0, 4.0, 1.0],
+            [1.0, 0.0, 2.0, 0.0],
+            [0.5, 2.0, 1.0, 1.0]
+        ]);
+
+        assert_identity(matrix * matrix.inverse().unwrap());
+    }
+
+    #[test]
+    fn singular_matrices_have_no_inverse() {
+        assert!(Mat4::scale(Vec3::new(1.0, 0.0, 1.0)).inverse().is_none());
+
+        // the third row is the sum of the first two
+        let matrix = Mat4::new([
+            [1.0, 2.0, 3.0, 0.0],
+            [0.0, 1.0, 1.0, 0.0],
+            [1.0, 3.0, 4.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0]
+        ]);
+        assert!(matrix.inverse().is_none());
+    }
+
+    #[test]
+    fn normals_stay_perpendicular_under_scaling() {
+        let matrix = Mat4::rotation_axis(Vec3::new(0.0, 0.0, 1.0), 30.0) * Mat4::scale(Vec3::new(4.0, 1.0, 1.0));
+        let tangent = Vec3::new(1.0, -1.0, 0.0);
+        let normal = Vec3::new(1.0, 1.0, 0.0);
+
+        let transformed = matrix.inverse().unwrap().transform_normal(normal);
+        assert!(transformed.dot(matrix.transform_vector(tangent)).abs() < 1e-5);
+    }
+
+    fn angle_between(a: [f32; 4], b: [f32; 4]) -> f32 {
+        let dot = a[0] * b[0] + a[1] * b[1] + a[2] * b[2] + a[3] * b[3];
+        2.0 * dot.abs().min(1.0).acos().to_degrees()
+    }
+
+    #[test]
+    fn slerp_moves_at_a_constant_rate() {
+        let a = quaternion_axis(Vec3::new(0.0, 1.0, 0.0), 0.0);
+        let b = quaternion_axis(Vec3::new(0.0, 1.0, 0.0), 120.0);
+
+        assert!(angle_between(slerp(a, b, 0.0), a) < 0.05);
+        assert!(angle_between(slerp(a, b, 1.0), b) < 0.05);
+
+        for &t in [0.25, 0.5, 0.8].iter() {
+            let q = slerp(a, b, t);
+            assert!((angle_between(q, a) - 120.0 * t).abs() < 0.05);
+            assert!(((q[0] * q[0] + q[1] * q[1] + q[2] * q[2] + q[3] * q[3]) - 1.0).abs() < 1e-5);
+        }
+
+        // the rotation itself turns x towards -z around y
+        let x = Mat4::rotation(slerp(a, b, 0.75)).transform_vector(Vec3::new(1.0, 0.0, 0.0));
+        assert!((x - Vec3::new(0.0, 0.0, -1.0)).len() < 1e-4);
+    }
+
+    #[test]
+    fn slerp_takes_the_shorter_arc() {
+        // 350 degrees one way is 10 degrees the other
+        let a = quaternion_axis(Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let b = quaternion_axis(Vec3::new(0.0, 0.0, 1.0), 350.0);
+        assert!((angle_between(slerp(a, b, 0.5), a) - 5.0).abs() < 0.05);
+
+        // q and -q are the same rotation
+        let c = quaternion_axis(Vec3::new(1.0, 1.0, 0.0), 40.0);
+        let negated = [-c[0], -c[1], -c[2], -c[3]];
+        assert!(angle_between(slerp(c, negated, 0.5), c) < 0.05);
+    }
+
+    #[test]
+    fn slerp_of_nearly_equal_rotations_stays_normalized() {
+        let a = quaternion_axis(Vec3::new(1.0, 0.0, 0.0), 10.0);
+        let b = quaternion_axis(Vec3::new(1.0, 0.0, 0.0), 10.01);
+        let q = slerp(a, b, 0.5);
+
+        assert!(q.iter().all(|x| x.is_finite()));
+        assert!(((q[0] * q[0] + q[1] * q[1] + q[2] * q[2] + q[3] * q[3]) - 1.0).abs() < 1e-5);
+        assert!(angle_between(q, a) < 0.05);
+    }
+}
@@ -0,0 +1,1149 @@
+//! Triangle meshes and loaders for the PLY and STL formats.
+//!
+//! Triangles are ordinary hitables, a loaded mesh is turned into one
+//! `Triangle` per face and added to the scene so that the `BvhTree` handles
+//! them like any other primitive. Triangles report their barycentric
+//! coordinates as u and v, which is what `VertexColorTexture` interpolates,
+//! unless the mesh has texture coordinates.
+//!
+//! * PLY: ascii, binary little-endian and binary big-endian bodies. The
+//!   `vertex` element needs `x`, `y` and `z`; `nx`, `ny`, `nz` become smooth
+//!   shading normals (zero length ones are recomputed from the adjacent
+//!   faces) and `red`, `green`, `blue` vertex colors (integer types
+//!   are scaled from [0, 255], float types are taken as is). Faces come from
+//!   the `vertex_indices` (or `vertex_index`) list of the `face` element and
+//!   polygons are triangulated as fans. Other elements are skipped.
+//! * STL: ascii and binary. Facet normals are ignored and recomputed from
+//!   the winding, vertices are not shared between facets.
+//!
+//! Displacement is applied to the mesh before it is turned into triangles:
+//! `subdivide` splits the faces down to a target edge length and `displace`
+//! moves the vertices along their normals by a scalar texture, e.g.
+//! `mesh.subdivide(0.02).0.displace(&*rock, 0.05).triangles(material)`. Unlike
+//! `NormalMapped` or `BumpMapped` this changes silhouettes and shadows, at
+//! the cost of many more triangles in the `BvhTree`.
+
+use crate::ray::Ray;
+use crate::vec::Vec3;
+use crate::hitable::{Hitable, HitRecord};
+use crate::material::Material;
+use crate::texture::{Texture, ConstantTexture, VertexColorTexture};
+use crate::aabb::AABB;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, BufReader};
+use std::mem;
+use std::path::Path;
+use std::sync::Arc;
+
+const STL_HEADER_SIZE: usize = 84;
+const STL_FACET_SIZE: usize = 50;
+// subdivision passes, each one at least halves the edges it splits
+const MAX_SUBDIVISION_PASSES: usize = 32;
+
+pub struct Triangle {
+    vertices: [Vec3; 3],
+    normals: Option<[Vec3; 3]>,
+    uvs: Option<[(f32, f32); 3]>,
+    material: Arc<Material>
+}
+
+impl Triangle {
+    pub fn new(v0: Vec3, v1: Vec3, v2: Vec3, material: Arc<Material>) -> Self {
+        Triangle { vertices: [v0, v1, v2], normals: None, uvs: None, material }
+    }
+
+    // per vertex normals interpolated across the face for smooth shading
+    pub fn with_normals(mut self, n0: Vec3, n1: Vec3, n2: Vec3) -> Self {
+        self.normals = Some([n0, n1, n2]);
+        self
+    }
+
+    pub fn with_uvs(mut self, uv0: (f32, f32), uv1: (f32, f32), uv2: (f32, f32)) -> Self {
+        self.uvs = Some([uv0, uv1, uv2]);
+        self
+    }
+
+    // derivatives of the position by the texture coordinates, or by the
+    // barycentric coordinates when there are none or they are degenerate
+    fn frame(&self) -> (Vec3, Vec3) {
+        let [p0, p1, p2] = self.vertices;
+
+        if let Some([uv0, uv1, uv2]) = self.uvs {
+            let (du02, dv02) = (uv0.0 - uv2.0, uv0.1 - uv2.1);
+            let (du12, dv12) = (uv1.0 - uv2.0, uv1.1 - uv2.1);
+            let determinant = du02 * dv12 - dv02 * du12;
+
+            if determinant.abs() > 1e-12 {
+                let (dp02, dp12) = (p0 - p2, p1 - p2);
+                let dpdu = (dp02 * dv12 - dp12 * dv02) / determinant;
+                let dpdv = (dp12 * du02 - dp02 * du12) / determinant;
+                return (dpdu, dpdv);
+            }
+        }
+
+        (p1 - p0, p2 - p0)
+    }
+}
+
+impl Hitable for Triangle {
+    // Moeller-Trumbore
+    fn hit(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
+        let [v0, v1, v2] = self.vertices;
+        let edge1 = v1 - v0;
+        let edge2 = v2 - v0;
+
+        let pvec = ray.direction.cross(edge2);
+        let det = edge1.dot(pvec);
+        if det.abs() < 1e-12 {
+            return None;
+        }
+
+        let inv_det = 1.0 / det;
+        let tvec = ray.origin - v0;
+        let u = tvec.dot(pvec) * inv_det;
+        if u < 0.0 || u > 1.0 {
+            return None;
+        }
+
+        let qvec = tvec.cross(edge1);
+        let v = ray.direction.dot(qvec) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let t = edge2.dot(qvec) * inv_det;
+        if t < t_range.start || t > t_range.end {
+            return None;
+        }
+
+        // vertex normals only shade, the face normal is turned to their side
+        let face_normal = edge1.cross(edge2).normalize();
+        let (normal, shading_normal) = match self.normals {
+            Some([n0, n1, n2]) => {
+                let shading_normal = (n0 * (1.0 - u - v) + n1 * u + n2 * v).normalize();
+                (if shading_normal.dot(face_normal) < 0.0 { -face_normal } else { face_normal }, shading_normal)
+            },
+            None => (face_normal, face_normal)
+        };
+
+        let (u, v) = match self.uvs {
+            Some([uv0, uv1, uv2]) => {
+                let w = 1.0 - u - v;
+                (uv0.0 * w + uv1.0 * u + uv2.0 * v, uv0.1 * w + uv1.1 * u + uv2.1 * v)
+            },
+            None => (u, v)
+        };
+
+        let (dpdu, dpdv) = self.frame();
+        let mut record = HitRecord::new(t, ray.point_at_parameter(t), normal, &*self.material, u, v).with_frame(dpdu, dpdv);
+        record.shading_normal = shading_normal;
+        Some(record)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        let [v0, v1, v2] = self.vertices;
+        let min = Vec3::new(v0.x.min(v1.x).min(v2.x), v0.y.min(v1.y).min(v2.y), v0.z.min(v1.z).min(v2.z));
+        let max = Vec3::new(v0.x.max(v1.x).max(v2.x), v0.y.max(v1.y).max(v2.y), v0.z.max(v1.z).max(v2.z));
+
+        // axis aligned triangles would otherwise get a flat box
+        Some(AABB::new(min - 0.0001, max + 0.0001))
+    }
+}
+
+#[derive(Clone)]
+pub struct TriangleMesh {
+    pub positions: Vec<Vec3>,
+    pub normals: Option<Vec<Vec3>>,
+    pub uvs: Option<Vec<(f32, f32)>>,
+    pub colors: Option<Vec<Vec3>>,
+    pub faces: Vec<[usize; 3]>
+}
+
+impl TriangleMesh {
+    pub fn from_ply<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        TriangleMesh::parse_ply(&read_file(path)?)
+    }
+
+    pub fn from_stl<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        TriangleMesh::parse_stl(&read_file(path)?)
+    }
+
+    pub fn parse_ply(bytes: &[u8]) -> io::Result<Self> {
+        let (header, body) = PlyHeader::parse(bytes)?;
+
+        let mut mesh = TriangleMesh { positions: Vec::new(), normals: None, uvs: None, colors: None, faces: Vec::new() };
+        let mut reader: Box<PlyReader> = match header.format {
+            PlyFormat::Ascii => {
+                let text = std::str::from_utf8(body)
+                    .map_err(|_| invalid_data("ply ascii body is not valid utf-8".to_string()))?;
+                Box::new(AsciiReader { tokens: text.split_ascii_whitespace() })
+            },
+            PlyFormat::BinaryLittleEndian => Box::new(BinaryReader { bytes: body, position: 0, big_endian: false }),
+            PlyFormat::BinaryBigEndian => Box::new(BinaryReader { bytes: body, position: 0, big_endian: true })
+        };
+
+        for element in &header.elements {
+            match element.name.as_str() {
+                "vertex" => mesh.read_ply_vertices(element, &mut *reader)?,
+                "face" => mesh.read_ply_faces(element, &mut *reader)?,
+                _ => {
+                    for _ in 0..element.count {
+                        for property in &element.properties {
+                            read_property(property, &mut *reader)?;
+                        }
+                    }
+                }
+            }
+        }
+
+        mesh.validate()?;
+        mesh.repair_normals();
+        Ok(mesh)
+    }
+
+    pub fn parse_stl(bytes: &[u8]) -> io::Result<Self> {
+        let binary_size = if bytes.len() >= STL_HEADER_SIZE {
+            let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+            Some(STL_HEADER_SIZE + count * STL_FACET_SIZE)
+        } else {
+            None
+        };
+
+        // binary files may start with "solid" as well, the size is decisive
+        let positions = if binary_size == Some(bytes.len()) {
+            parse_stl_binary(&bytes[STL_HEADER_SIZE..])
+        } else if bytes.starts_with(b"solid") {
+            parse_stl_ascii(bytes)?
+        } else {
+            return Err(invalid_data(format!(
+                "stl file of {} bytes is neither ascii nor a binary file of the size its facet count implies",
+                bytes.len()
+            )));
+        };
+
+        let faces = (0..positions.len() / 3).map(|i| [3 * i, 3 * i + 1, 3 * i + 2]).collect();
+        let mesh = TriangleMesh { positions, normals: None, uvs: None, colors: None, faces };
+
+        mesh.validate()?;
+        Ok(mesh)
+    }
+
+    pub fn triangles(&self, material: Arc<Material>) -> Vec<Box<Hitable>> {
+        self.faces.iter().map(|face| -> Box<Hitable> {
+            Box::new(self.triangle(face, material.clone()))
+        }).collect()
+    }
+
+    // one material per triangle, built from a texture that interpolates the
+    // vertex colors, e.g. `|texture| Arc::new(Diffuse::new(texture))`. The
+    // triangles report barycentric coordinates even if the mesh has uvs.
+    // Meshes without colors get a single white material.
+    pub fn colored_triangles<F: Fn(Arc<Texture>) -> Arc<Material>>(&self, material: F) -> Vec<Box<Hitable>> {
+        let colors = match self.colors {
+            Some(ref colors) => colors,
+            None => return self.triangles(material(Arc::new(ConstantTexture::new(Vec3::new(1.0, 1.0, 1.0)))))
+        };
+
+        self.faces.iter().map(|face| -> Box<Hitable> {
+            let texture = VertexColorTexture::new(colors[face[0]], colors[face[1]], colors[face[2]]);
+            let mut triangle = self.triangle(face, material(Arc::new(texture)));
+            triangle.uvs = None;
+            Box::new(triangle)
+        }).collect()
+    }
+
+    // splits every edge longer than `max_edge_length` at its midpoint until
+    // none is left, interpolating the vertex attributes. An edge is split
+    // once for all faces sharing it, so the result has no cracks. Edges of
+    // infinite length are left alone. Returns the mesh and the number of
+    // edges still too long when MAX_SUBDIVISION_PASSES ran out, zero if the
+    // target was reached.
+    pub fn subdivide(&self, max_edge_length: f32) -> (TriangleMesh, usize) {
+        self.subdivide_passes(max_edge_length, MAX_SUBDIVISION_PASSES)
+    }
+
+    fn subdivide_passes(&self, max_edge_length: f32, passes: usize) -> (TriangleMesh, usize) {
+        assert!(max_edge_length > 0.0 && max_edge_length.is_finite(), "subdivision needs a positive finite edge length");
+
+        let mut mesh = self.clone();
+        let max_squared = max_edge_length * max_edge_length;
+
+        for pass in 0..=passes {
+            let mut edges = Vec::new();
+            for face in &mesh.faces {
+                for k in 0..3 {
+                    let (a, b) = (face[k], face[(k + 1) % 3]);
+                    let length = (mesh.positions[a] - mesh.positions[b]).len_squared();
+                    if length > max_squared && length.is_finite() {
+                        edges.push((a.min(b), a.max(b)));
+                    }
+                }
+            }
+
+            if edges.is_empty() {
+                break;
+            }
+
+            edges.sort();
+            edges.dedup();
+
+            if pass == passes {
+                return (mesh, edges.len());
+            }
+
+            let midpoints: HashMap<(usize, usize), usize> = edges.into_iter()
+                .map(|(a, b)| ((a, b), mesh.split_edge(a, b)))
+                .collect();
+
+            for face in mem::replace(&mut mesh.faces, Vec::new()) {
+                mesh.split_face(face, &midpoints);
+            }
+        }
+
+        (mesh, 0)
+    }
+
+    // moves every vertex along its normal by `scale` times the scalar value of
+    // the texture at its uv and position, then recomputes the normals.
+    // Vertices at the same position, e.g. along uv seams or of STL facets,
+    // move together so the surface stays closed.
+    pub fn displace(&self, texture: &Texture, scale: f32) -> TriangleMesh {
+        let mut mesh = self.clone();
+        if mesh.normals.is_none() {
+            mesh.compute_normals();
+        }
+
+        let welded = mesh.welded();
+        let mut directions = vec![Vec3::zero(); mesh.positions.len()];
+        if let Some(ref normals) = mesh.normals {
+            for (i, &normal) in normals.iter().enumerate() {
+                directions[welded[i]] += normal;
+            }
+        }
+
+        let offsets: Vec<Vec3> = (0..mesh.positions.len()).map(|i| {
+            if welded[i] != i || directions[i].len_squared() == 0.0 {
+                return Vec3::zero();
+            }
+
+            let (u, v) = mesh.uvs.as_ref().map_or((0.0, 0.0), |uvs| uvs[i]);
+            directions[i].normalize() * (scale * texture.scalar(u, v, &mesh.positions[i]))
+        }).collect();
+
+        for i in 0..mesh.positions.len() {
+            mesh.positions[i] += offsets[welded[i]];
+        }
+
+        mesh.compute_normals();
+        mesh
+    }
+
+    // smooth normals from the area weighted normals of the adjacent faces,
+    // shared by all vertices at the same position
+    pub fn compute_normals(&mut self) {
+        let welded = self.welded();
+        let mut sums = vec![Vec3::zero(); self.positions.len()];
+
+        for face in &self.faces {
+            let [p0, p1, p2] = [self.positions[face[0]], self.positions[face[1]], self.positions[face[2]]];
+            let normal = (p1 - p0).cross(p2 - p0);
+            for &i in face {
+                sums[welded[i]] += normal;
+            }
+        }
+
+        self.normals = Some((0..self.positions.len()).map(|i| {
+            let sum = sums[welded[i]];
+            if sum.len_squared() > 0.0 { sum.normalize() } else { sum }
+        }).collect());
+    }
+
+    // index of the first vertex at the same position for every vertex
+    fn welded(&self) -> Vec<usize> {
+        let mut first = HashMap::new();
+
+        self.positions.iter().enumerate().map(|(i, p)| {
+            // adding zero turns -0.0 into 0.0 so both get the same bits
+            let key = [(p.x + 0.0).to_bits(), (p.y + 0.0).to_bits(), (p.z + 0.0).to_bits()];
+            *first.entry(key).or_insert(i)
+        }).collect()
+    }
+
+    fn split_edge(&mut self, a: usize, b: usize) -> usize {
+        self.positions.push((self.positions[a] + self.positions[b]) * 0.5);
+
+        if let Some(ref mut normals) = self.normals {
+            let sum = normals[a] + normals[b];
+            normals.push(if sum.len_squared() > 0.0 { sum.normalize() } else { normals[a] });
+        }
+        if let Some(ref mut uvs) = self.uvs {
+            let ((u0, v0), (u1, v1)) = (uvs[a], uvs[b]);
+            uvs.push(((u0 + u1) * 0.5, (v0 + v1) * 0.5));
+        }
+        if let Some(ref mut colors) = self.colors {
+            colors.push((colors[a] + colors[b]) * 0.5);
+        }
+
+        self.positions.len() - 1
+    }
+
+    // replaces a face by the triangles between its corners and the midpoints
+    // of its split edges, keeping the winding
+    fn split_face(&mut self, face: [usize; 3], midpoints: &HashMap<(usize, usize), usize>) {
+        let midpoint = |k: usize| {
+            let (a, b) = (face[k % 3], face[(k + 1) % 3]);
+            midpoints.get(&(a.min(b), a.max(b))).cloned()
+        };
+        let corner = |k: usize| face[k % 3];
+        let split = [midpoint(0), midpoint(1), midpoint(2)];
+
+        match split.iter().filter(|m| m.is_some()).count() {
+            0 => self.faces.push(face),
+            1 => {
+                let k = split.iter().position(|m| m.is_some()).unwrap();
+                let m = split[k].unwrap();
+
+                self.faces.push([corner(k), m, corner(k + 2)]);
+                self.faces.push([m, corner(k + 1), corner(k + 2)]);
+            },
+            2 => {
+                // corners and midpoints in order around the face, starting
+                // after the edge that is not split
+                let k = split.iter().position(|m| m.is_none()).unwrap() + 1;
+                let (v0, v1, v2) = (corner(k), corner(k + 1), corner(k + 2));
+                let (m0, m1) = (midpoint(k).unwrap(), midpoint(k + 1).unwrap());
+
+                self.faces.push([m0, v1, m1]);
+
+                // the remaining quad is cut along its shorter diagonal
+                let diagonal = |a: usize, b: usize| (self.positions[a] - self.positions[b]).len_squared();
+                if diagonal(v0, m1) <= diagonal(m0, v2) {
+                    self.faces.push([v0, m0, m1]);
+                    self.faces.push([v0, m1, v2]);
+                } else {
+                    self.faces.push([v0, m0, v2]);
+                    self.faces.push([m0, m1, v2]);
+                }
+            },
+            _ => {
+                let (m0, m1, m2) = (split[0].unwrap(), split[1].unwrap(), split[2].unwrap());
+
+                self.faces.push([face[0], m0, m2]);
+                self.faces.push([m0, face[1], m1]);
+                self.faces.push([m2, m1, face[2]]);
+                self.faces.push([m0, m1, m2]);
+            }
+        }
+    }
+
+    fn triangle(&self, face: &[usize; 3], material: Arc<Material>) -> Triangle {
+        let mut triangle = Triangle::new(self.positions[face[0]], self.positions[face[1]], self.positions[face[2]], material);
+
+        if let Some(ref normals) = self.normals {
+            triangle = triangle.with_normals(normals[face[0]], normals[face[1]], normals[face[2]]);
+        }
+        if let Some(ref uvs) = self.uvs {
+            triangle = triangle.with_uvs(uvs[face[0]], uvs[face[1]], uvs[face[2]]);
+        }
+
+        triangle
+    }
+
+    fn read_ply_vertices(&mut self, element: &PlyElement, reader: &mut PlyReader) -> io::Result<()> {
+        let find = |name: &str| element.properties.iter().position(|p| p.name() == name);
+        let find_all = |names: [&str; 3]| -> Option<[usize; 3]> {
+            Some([find(names[0])?, find(names[1])?, find(names[2])?])
+        };
+
+        let position = find_all(["x", "y", "z"])
+            .ok_or_else(|| invalid_data("ply vertex element is missing x, y or z".to_string()))?;
+        let normal = find_all(["nx", "ny", "nz"]);
+        let color = find_all(["red", "green", "blue"]);
+
+        // integer colors are in [0, 255], floating point ones in [0, 1]
+        let color_scale = match color.map(|c| &element.properties[c[0]]) {
+            Some(PlyProperty::Scalar { ty: ScalarType::Float32, .. }) |
+            Some(PlyProperty::Scalar { ty: ScalarType::Float64, .. }) => 1.0,
+            _ => 1.0 / 255.0
+        };
+
+        let mut normals = Vec::new();
+        let mut colors = Vec::new();
+        let mut values = vec![0.0; element.properties.len()];
+
+        for _ in 0..element.count {
+            for (i, property) in element.properties.iter().enumerate() {
+                values[i] = read_property(property, reader)?;
+            }
+
+            let vector = |indices: [usize; 3]| {
+                Vec3::new(values[indices[0]] as f32, values[indices[1]] as f32, values[indices[2]] as f32)
+            };
+
+            self.positions.push(vector(position));
+            if let Some(indices) = normal {
+                normals.push(vector(indices));
+            }
+            if let Some(indices) = color {
+                colors.push(vector(indices) * color_scale);
+            }
+        }
+
+        if normal.is_some() {
+            self.normals = Some(normals);
+        }
+        if color.is_some() {
+            self.colors = Some(colors);
+        }
+
+        Ok(())
+    }
+
+    fn read_ply_faces(&mut self, element: &PlyElement, reader: &mut PlyReader) -> io::Result<()> {
+        let index_property = element.properties.iter()
+            .position(|p| p.name() == "vertex_indices" || p.name() == "vertex_index")
+            .ok_or_else(|| invalid_data("ply face element has no vertex_indices list".to_string()))?;
+
+        for face in 0..element.count {
+            for (i, property) in element.properties.iter().enumerate() {
+                if i != index_property {
+                    read_property(property, reader)?;
+                    continue;
+                }
+
+                let (count_type, item_type) = match *property {
+                    PlyProperty::List { count, item, .. } => (count, item),
+                    PlyProperty::Scalar { .. } => return Err(invalid_data("ply vertex_indices must be a list".to_string()))
+                };
+
+                let count = reader.read(count_type)? as usize;
+                if count < 3 {
+                    return Err(invalid_data(format!("ply face {} has only {} vertices", face, count)));
+                }
+
+                // the count comes from the file, so the list grows as it is read
+                let mut indices = Vec::new();
+                for _ in 0..count {
+                    let index = reader.read(item_type)?;
+                    if index < 0.0 {
+                        return Err(invalid_data(format!("ply face {} has negative vertex index {}", face, index)));
+                    }
+                    indices.push(index as usize);
+                }
+
+                for k in 1..count - 1 {
+                    self.faces.push([indices[0], indices[k], indices[k + 1]]);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // normalizes the normals read from a file and replaces the ones without
+    // a direction by the smooth normals of the adjacent faces
+    fn repair_normals(&mut self) {
+        let normals = match self.normals.take() {
+            Some(normals) => normals,
+            None => return
+        };
+
+        let valid = |n: &Vec3| n.len_squared() > 0.0 && n.len_squared().is_finite();
+        if normals.iter().all(valid) {
+            self.normals = Some(normals.into_iter().map(|normal| normal.normalize()).collect());
+            return;
+        }
+
+        self.compute_normals();
+        let computed = self.normals.take().unwrap();
+        self.normals = Some(normals.into_iter().zip(computed).map(|(normal, computed)| {
+            if valid(&normal) { normal.normalize() } else { computed }
+        }).collect());
+    }
+
+    pub fn validate(&self) -> io::Result<()> {
+        if self.faces.is_empty() {
+            return Err(invalid_data("mesh contains no faces".to_string()));
+        }
+
+        let vertices = self.positions.len();
+        let attributes = [
+            ("normals", self.normals.as_ref().map(|normals| normals.len())),
+            ("uvs", self.uvs.as_ref().map(|uvs| uvs.len())),
+            ("colors", self.colors.as_ref().map(|colors| colors.len()))
+        ];
+
+        for &(name, len) in &attributes {
+            if let Some(len) = len.filter(|&len| len != vertices) {
+                return Err(invalid_data(format!("mesh has {} vertices but {} {}", vertices, len, name)));
+            }
+        }
+
+        for (i, face) in self.faces.iter().enumerate() {
+            if let Some(&index) = face.iter().find(|&&index| index >= vertices) {
+                return Err(invalid_data(format!(
+                    "face {} references vertex {} but the mesh has only {} vertices",
+                    i, index, vertices
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy)]
+enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian
+}
+
+#[derive(Clone, Copy)]
+enum ScalarType {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> io::Result<Self> {
+        match name {
+            "char" | "int8" => Ok(ScalarType::Int8),
+            "uchar" | "uint8" => Ok(ScalarType::UInt8),
+            "short" | "int16" => Ok(ScalarType::Int16),
+            "ushort" | "uint16" => Ok(ScalarType::UInt16),
+            "int" | "int32" => Ok(ScalarType::Int32),
+            "uint" | "uint32" => Ok(ScalarType::UInt32),
+            "float" | "float32" => Ok(ScalarType::Float32),
+            "double" | "float64" => Ok(ScalarType::Float64),
+            _ => Err(invalid_data(format!("unknown ply property type '{}'", name)))
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            ScalarType::Int8 | ScalarType::UInt8 => 1,
+            ScalarType::Int16 | ScalarType::UInt16 => 2,
+            ScalarType::Int32 | ScalarType::UInt32 | ScalarType::Float32 => 4,
+            ScalarType::Float64 => 8
+        }
+    }
+}
+
+enum PlyProperty {
+    Scalar { name: String, ty: ScalarType },
+    List { name: String, count: ScalarType, item: ScalarType }
+}
+
+impl PlyProperty {
+    fn name(&self) -> &str {
+        match *self {
+            PlyProperty::Scalar { ref name, .. } | PlyProperty::List { ref name, .. } => name
+        }
+    }
+}
+
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>
+}
+
+struct PlyHeader {
+    format: PlyFormat,
+    elements: Vec<PlyElement>
+}
+
+impl PlyHeader {
+    // returns the header and the body following it
+    fn parse(bytes: &[u8]) -> io::Result<(PlyHeader, &[u8])> {
+        if !bytes.starts_with(b"ply") {
+            return Err(invalid_data("ply file does not start with 'ply'".to_string()));
+        }
+
+        let marker = b"end_header";
+        let end = bytes.windows(marker.len()).position(|w| w == marker)
+            .ok_or_else(|| invalid_data("ply header has no end_header".to_string()))?;
+        let body_start = bytes[end..].iter().position(|&b| b == b'\n')
+            .map_or(bytes.len(), |newline| end + newline + 1);
+
+        let text = String::from_utf8_lossy(&bytes[..end]);
+        let mut format = None;
+        let mut elements: Vec<PlyElement> = Vec::new();
+
+        for (number, line) in text.lines().enumerate().skip(1) {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let line_error = || invalid_data(format!("malformed ply header line {}: '{}'", number + 1, line.trim()));
+
+            match words.first() {
+                None | Some(&"comment") | Some(&"obj_info") => {},
+                Some(&"format") => {
+                    format = Some(match words.get(1) {
+                        Some(&"ascii") => PlyFormat::Ascii,
+                        Some(&"binary_little_endian") => PlyFormat::BinaryLittleEndian,
+                        Some(&"binary_big_endian") => PlyFormat::BinaryBigEndian,
+                        _ => return Err(line_error())
+                    });
+                },
+                Some(&"element") => {
+                    if words.len() != 3 {
+                        return Err(line_error());
+                    }
+                    let count = words[2].parse().map_err(|_| line_error())?;
+                    elements.push(PlyElement { name: words[1].to_string(), count, properties: Vec::new() });
+                },
+                Some(&"property") => {
+                    let element = elements.last_mut().ok_or_else(line_error)?;
+                    let property = match words.len() {
+                        3 => PlyProperty::Scalar { name: words[2].to_string(), ty: ScalarType::parse(words[1])? },
+                        5 if words[1] == "list" => PlyProperty::List {
+                            name: words[4].to_string(),
+                            count: ScalarType::parse(words[2])?,
+                            item: ScalarType::parse(words[3])?
+                        },
+                        _ => return Err(line_error())
+                    };
+                    element.properties.push(property);
+                },
+                Some(_) => return Err(line_error())
+            }
+        }
+
+        let format = format.ok_or_else(|| invalid_data("ply header has no format line".to_string()))?;
+        Ok((PlyHeader { format, elements }, &bytes[body_start..]))
+    }
+}
+
+trait PlyReader {
+    fn read(&mut self, ty: ScalarType) -> io::Result<f64>;
+}
+
+struct AsciiReader<'a> {
+    tokens: std::str::SplitAsciiWhitespace<'a>
+}
+
+impl<'a> PlyReader for AsciiReader<'a> {
+    fn read(&mut self, _ty: ScalarType) -> io::Result<f64> {
+        let token = self.tokens.next()
+            .ok_or_else(|| invalid_data("ply body ends before all elements were read".to_string()))?;
+
+        token.parse().map_err(|_| invalid_data(format!("invalid number '{}' in ply body", token)))
+    }
+}
+
+struct BinaryReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    big_endian: bool
+}
+
+impl<'a> PlyReader for BinaryReader<'a> {
+    fn read(&mut self, ty: ScalarType) -> io::Result<f64> {
+        let size = ty.size();
+        if self.position + size > self.bytes.len() {
+            return Err(invalid_data("ply body ends before all elements were read".to_string()));
+        }
+
+        let mut raw = [0u8; 8];
+        raw[..size].copy_from_slice(&self.bytes[self.position..self.position + size]);
+        if self.big_endian {
+            raw[..size].reverse();
+        }
+        self.position += size;
+
+        Ok(match ty {
+            ScalarType::Int8 => raw[0] as i8 as f64,
+            ScalarType::UInt8 => raw[0] as f64,
+            ScalarType::Int16 => i16::from_le_bytes([raw[0], raw[1]]) as f64,
+            ScalarType::UInt16 => u16::from_le_bytes([raw[0], raw[1]]) as f64,
+            ScalarType::Int32 => i32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64,
+            ScalarType::UInt32 => u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64,
+            ScalarType::Float32 => f32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64,
+            ScalarType::Float64 => f64::from_le_bytes(raw)
+        })
+    }
+}
+
+// lists are skipped and read as zero
+fn read_property(property: &PlyProperty, reader: &mut PlyReader) -> io::Result<f64> {
+    match *property {
+        PlyProperty::Scalar { ty, .. } => reader.read(ty),
+        PlyProperty::List { count, item, .. } => {
+            let count = reader.read(count)? as usize;
+            for _ in 0..count {
+                reader.read(item)?;
+            }
+            Ok(0.0)
+        }
+    }
+}
+
+fn parse_stl_binary(facets: &[u8]) -> Vec<Vec3> {
+    let float = |bytes: &[u8], offset: usize| {
+        f32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+    };
+
+    let mut positions = Vec::with_capacity(facets.len() / STL_FACET_SIZE * 3);
+    for facet in facets.chunks(STL_FACET_SIZE) {
+        // the facet normal comes first, then three vertices
+        for vertex in 1..4 {
+            let offset = vertex * 12;
+            positions.push(Vec3::new(float(facet, offset), float(facet, offset + 4), float(facet, offset + 8)));
+        }
+    }
+
+    positions
+}
+
+fn parse_stl_ascii(bytes: &[u8]) -> io::Result<Vec<Vec3>> {
+    let text = std::str::from_utf8(bytes).map_err(|_| invalid_data("ascii stl file is not valid utf-8".to_string()))?;
+    let mut tokens = text.split_ascii_whitespace();
+    let mut positions = Vec::new();
+
+    while let Some(token) = tokens.next() {
+        if token != "vertex" {
+            continue;
+        }
+
+        let mut coordinate = || -> io::Result<f32> {
+            let value = tokens.next()
+                .ok_or_else(|| invalid_data(format!("stl vertex {} is missing coordinates", positions.len())))?;
+            value.parse().map_err(|_| invalid_data(format!("invalid stl coordinate '{}'", value)))
+        };
+        let vertex = Vec3::new(coordinate()?, coordinate()?, coordinate()?);
+        positions.push(vertex);
+    }
+
+    if positions.len() % 3 != 0 {
+        return Err(invalid_data(format!("stl file has {} vertices, not a multiple of three", positions.len())));
+    }
+
+    Ok(positions)
+}
+
+fn read_file<P: AsRef<Path>>(path: P) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    BufReader::new(File::open(path)?).read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::PassThrough;
+    use crate::texture::NoiseTexture;
+
+    fn ply(body: &str) -> io::Result<TriangleMesh> {
+        TriangleMesh::parse_ply(body.as_bytes())
+    }
+
+    fn error_message<T>(result: io::Result<T>) -> String {
+        match result {
+            Ok(_) => panic!("malformed file was accepted"),
+            Err(err) => {
+                assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+                err.to_string()
+            }
+        }
+    }
+
+    const QUAD: &str = "ply\nformat ascii 1.0\nelement vertex 4\nproperty float x\nproperty float y\nproperty float z\n\
+        element face 1\nproperty list uchar int vertex_indices\nend_header\n\
+        0 0 0\n1 0 0\n1 1 0\n0 1 0\n4 0 1 2 3\n";
+
+    #[test]
+    fn ascii_ply_polygons_become_triangle_fans() {
+        let mesh = ply(QUAD).unwrap();
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.faces, vec![[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn binary_ply_reads_both_byte_orders() {
+        let header = |format: &str| format!(
+            "ply\nformat {} 1.0\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\n\
+             element face 1\nproperty list uchar uint vertex_indices\nend_header\n", format
+        );
+
+        for &(format, big_endian) in &[("binary_little_endian", false), ("binary_big_endian", true)] {
+            let mut bytes = header(format).into_bytes();
+            for &value in &[0.0f32, 0.0, 0.0, 2.0, 0.0, 0.0, 0.0, 3.0, 0.0] {
+                bytes.extend_from_slice(&if big_endian { value.to_be_bytes() } else { value.to_le_bytes() });
+            }
+            bytes.push(3);
+            for &index in &[0u32, 1, 2] {
+                bytes.extend_from_slice(&if big_endian { index.to_be_bytes() } else { index.to_le_bytes() });
+            }
+
+            let mesh = TriangleMesh::parse_ply(&bytes).unwrap();
+            assert_eq!(mesh.positions[2], Vec3::new(0.0, 3.0, 0.0));
+            assert_eq!(mesh.faces, vec![[0, 1, 2]]);
+        }
+    }
+
+    #[test]
+    fn malformed_ply_files_are_rejected() {
+        let cases = vec![
+            ("ply\nelement vertex 0\nend_header\n".to_string(), "format"),
+            (QUAD.replace("property float z\n", ""), "x, y or z"),
+            (QUAD.replace("4 0 1 2 3", "4 0 1 2 7"), "references vertex 7"),
+            (QUAD.replace("4 0 1 2 3", "2 0 1"), "only 2 vertices"),
+            (QUAD.replace("4 0 1 2 3", "3 0 -1 2"), "negative vertex index"),
+            (QUAD.replace("4 0 1 2 3", "4 0 1"), "ends before"),
+            (QUAD.replace("1 1 0", "1 one 0"), "invalid number")
+        ];
+
+        for (file, expected) in cases {
+            let message = error_message(ply(&file));
+            assert!(message.contains(expected), "'{}' does not mention '{}'", message, expected);
+        }
+    }
+
+    #[test]
+    fn huge_list_counts_fail_without_allocating() {
+        let mut bytes = b"ply\nformat binary_little_endian 1.0\nelement vertex 3\nproperty float x\n\
+            property float y\nproperty float z\nelement face 1\nproperty list uint uint vertex_indices\nend_header\n".to_vec();
+        bytes.extend_from_slice(&[0u8; 36]);
+        bytes.extend_from_slice(&u32::max_value().to_le_bytes());
+        bytes.extend_from_slice(&[0u8; 12]);
+
+        assert!(error_message(TriangleMesh::parse_ply(&bytes)).contains("ends before"));
+    }
+
+    #[test]
+    fn zero_length_ply_normals_are_recomputed() {
+        let file = QUAD
+            .replace("property float z\n", "property float z\nproperty float nx\nproperty float ny\nproperty float nz\n")
+            .replace("0 0 0\n1 0 0\n", "0 0 0 0 0 0\n1 0 0 0 0 2\n")
+            .replace("1 1 0\n0 1 0\n", "1 1 0 0 0 1\n0 1 0 1 0 0\n");
+        let normals = ply(&file).unwrap().normals.unwrap();
+
+        // the zero normal comes from the faces, the others are normalized
+        assert!((normals[0] - Vec3::new(0.0, 0.0, 1.0)).len() < 1e-6);
+        assert!((normals[1] - Vec3::new(0.0, 0.0, 1.0)).len() < 1e-6);
+        assert!((normals[3] - Vec3::new(1.0, 0.0, 0.0)).len() < 1e-6);
+    }
+
+    #[test]
+    fn stl_files_in_both_encodings() {
+        let ascii = "solid t\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\nvertex 0 1 0\n\
+            endloop\nendfacet\nendsolid t\n";
+        let mesh = TriangleMesh::parse_stl(ascii.as_bytes()).unwrap();
+        assert_eq!(mesh.positions[1], Vec3::new(1.0, 0.0, 0.0));
+
+        let mut binary = vec![0u8; 80];
+        binary.extend_from_slice(&1u32.to_le_bytes());
+        for &value in &[0.0f32, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            binary.extend_from_slice(&value.to_le_bytes());
+        }
+        binary.extend_from_slice(&[0, 0]);
+        let mesh = TriangleMesh::parse_stl(&binary).unwrap();
+        assert_eq!(mesh.faces, vec![[0, 1, 2]]);
+        assert_eq!(mesh.positions[2], Vec3::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn malformed_stl_files_are_rejected() {
+        let mut truncated = vec![0u8; 80];
+        truncated.extend_from_slice(&2u32.to_le_bytes());
+        truncated.extend_from_slice(&[0u8; 50]);
+        assert!(error_message(TriangleMesh::parse_stl(&truncated)).contains("neither ascii nor"));
+
+        let partial = "solid t\nvertex 0 0 0\nvertex 1 0 0\nendsolid t\n";
+        assert!(error_message(TriangleMesh::parse_stl(partial.as_bytes())).contains("not a multiple of three"));
+
+        let missing = "solid t\nvertex 0 0\n";
+        assert!(error_message(TriangleMesh::parse_stl(missing.as_bytes())).contains("missing coordinates"));
+
+        let invalid = "solid t\nvertex 0 x 0\n";
+        assert!(error_message(TriangleMesh::parse_stl(invalid.as_bytes())).contains("invalid stl coordinate"));
+
+        assert!(error_message(TriangleMesh::parse_stl(b"solid empty\nendsolid empty\n")).contains("no faces"));
+    }
+
+    #[test]
+    fn attributes_must_match_the_vertex_count() {
+        let mut mesh = ply(QUAD).unwrap();
+        mesh.uvs = Some(vec![(0.0, 0.0); 3]);
+        assert!(error_message(mesh.validate()).contains("4 vertices but 3 uvs"));
+
+        mesh.uvs = None;
+        mesh.colors = Some(vec![Vec3::zero(); 5]);
+        assert!(error_message(mesh.validate()).contains("4 vertices but 5 colors"));
+    }
+
+    fn unused() -> Arc<Material> {
+        Arc::new(PassThrough::new(Vec3::zero()))
+    }
+
+    fn hit_at(triangle: &Triangle, x: f32, y: f32) -> HitRecord {
+        triangle.hit(&Ray::new(Vec3::new(x, y, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0), 0.001..std::f32::MAX).unwrap()
+    }
+
+    #[test]
+    fn triangle_frame_is_the_derivative_of_the_position_by_uv() {
+        let (v0, v1, v2) = (Vec3::new(0.0, 0.0, 0.0), Vec3::new(2.0, 0.5, 0.0), Vec3::new(0.5, 3.0, 0.0));
+        let mapped = Triangle::new(v0, v1, v2, unused()).with_uvs((0.2, 0.1), (0.9, 0.3), (0.4, 0.8));
+        let plain = Triangle::new(v0, v1, v2, unused());
+
+        // positions are affine in uv, so the derivatives connect any two hits
+        for triangle in [mapped, plain].iter() {
+            let a = hit_at(triangle, 0.6, 0.5);
+            let b = hit_at(triangle, 1.1, 1.4);
+            let (dpdu, dpdv) = (a.tangent.unwrap(), a.bitangent.unwrap());
+            let predicted = a.p + dpdu * (b.u - a.u) + dpdv * (b.v - a.v);
+
+            assert!((predicted - b.p).len() < 1e-4, "{:?} vs {:?}", predicted, b.p);
+        }
+    }
+
+    #[test]
+    fn vertex_normals_only_shade() {
+        let tilted = Vec3::new(0.3, 0.0, -1.0).normalize();
+        // wound clockwise seen from +z, the vertex normals point down as well
+        let triangle = Triangle::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0), unused())
+            .with_normals(tilted, tilted, Vec3::new(0.0, 0.0, -1.0));
+        let record = hit_at(&triangle, 0.25, 0.25);
+
+        assert_eq!(record.normal, Vec3::new(0.0, 0.0, -1.0));
+        assert!(record.shading_normal.x > 0.1 && (record.shading_normal.len() - 1.0).abs() < 1e-5);
+
+        let flat = Triangle::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), unused());
+        let record = hit_at(&flat, 0.25, 0.25);
+        assert_eq!(record.normal, record.shading_normal);
+    }
+
+    // an octahedron stretched along x, wound outwards, with its vertices
+    // shared between faces or, like STL facets, repeated for every face
+    fn octahedron(shared: bool) -> TriangleMesh {
+        let corners = [
+            Vec3::new(3.0, 0.0, 0.0), Vec3::new(-3.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0)
+        ];
+        let mut faces = Vec::new();
+        for &x in [0, 1].iter() {
+            for &y in [2, 3].iter() {
+                for &z in [4, 5].iter() {
+                    faces.push(if (x + y + z) % 2 == 0 { [x, y, z] } else { [x, z, y] });
+                }
+            }
+        }
+
+        if shared {
+            TriangleMesh { positions: corners.to_vec(), normals: None, uvs: None, colors: None, faces }
+        } else {
+            let positions = faces.iter().flat_map(|face| face.iter().map(|&i| corners[i])).collect();
+            let faces = (0..8).map(|i| [3 * i, 3 * i + 1, 3 * i + 2]).collect();
+            TriangleMesh { positions, normals: None, uvs: None, colors: None, faces }
+        }
+    }
+
+    // every edge between welded vertices is crossed once in each direction
+    fn assert_closed(mesh: &TriangleMesh) {
+        let welded = mesh.welded();
+        let mut edges = HashMap::new();
+        for face in &mesh.faces {
+            for k in 0..3 {
+                *edges.entry((welded[face[k]], welded[face[(k + 1) % 3]])).or_insert(0) += 1;
+            }
+        }
+
+        for (&(a, b), &count) in &edges {
+            assert_eq!(count, 1, "edge {:?} is used {} times", (a, b), count);
+            assert_eq!(edges.get(&(b, a)), Some(&1), "edge {:?} has no twin", (a, b));
+        }
+    }
+
+    fn area(mesh: &TriangleMesh) -> f32 {
+        mesh.faces.iter().map(|face| {
+            let [p0, p1, p2] = [mesh.positions[face[0]], mesh.positions[face[1]], mesh.positions[face[2]]];
+            (p1 - p0).cross(p2 - p0).len() * 0.5
+        }).sum()
+    }
+
+    #[test]
+    fn subdivision_leaves_no_cracks() {
+        for &shared in [true, false].iter() {
+            let mesh = octahedron(shared);
+            let (subdivided, left) = mesh.subdivide(0.4);
+
+            assert_eq!(left, 0);
+            assert_closed(&subdivided);
+            assert!(subdivided.faces.len() > 50 * mesh.faces.len());
+            assert!((area(&subdivided) - area(&mesh)).abs() < 1e-3 * area(&mesh));
+            for face in &subdivided.faces {
+                for k in 0..3 {
+                    assert!((subdivided.positions[face[k]] - subdivided.positions[face[(k + 1) % 3]]).len() <= 0.4);
+                }
+            }
+
+            // displaced facets move together and stay closed
+            let displaced = subdivided.displace(&NoiseTexture::new(1.0), 0.2);
+            assert_closed(&displaced);
+        }
+    }
+
+    #[test]
+    fn subdivision_interpolates_the_attributes() {
+        let mut mesh = ply(QUAD).unwrap();
+        mesh.uvs = Some(mesh.positions.iter().map(|p| (p.x, p.y)).collect());
+        mesh.colors = Some(mesh.positions.clone());
+        let (subdivided, _) = mesh.subdivide(0.3);
+
+        assert_eq!(subdivided.uvs.as_ref().unwrap().len(), subdivided.positions.len());
+        for (i, p) in subdivided.positions.iter().enumerate() {
+            assert_eq!(subdivided.uvs.as_ref().unwrap()[i], (p.x, p.y));
+            assert_eq!(subdivided.colors.as_ref().unwrap()[i], *p);
+        }
+    }
+
+    #[test]
+    fn infinite_edges_are_not_split() {
+        let mut mesh = octahedron(true);
+        mesh.positions[0] = Vec3::new(std::f32::INFINITY, 0.0, 0.0);
+        let (subdivided, left) = mesh.subdivide(0.4);
+
+        assert!(subdivided.faces.len() < 1000);
+        assert_eq!(left, 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn infinite_edge_lengths_are_rejected() {
+        octahedron(true).subdivide(std::f32::INFINITY);
+    }
+
+    #[test]
+    fn unfinished_subdivision_reports_the_edges_left() {
+        let mesh = octahedron(true);
+        let (once, left) = mesh.subdivide_passes(0.4, 1);
+        let (done, _) = mesh.subdivide(0.4);
+
+        assert_eq!(once.faces.len(), 4 * mesh.faces.len());
+        let mut long_edges: Vec<(usize, usize)> = once.faces.iter()
+            .flat_map(|face| (0..3).map(move |k| (face[k].min(face[(k + 1) % 3]), face[k].max(face[(k + 1) % 3]))))
+            .filter(|&(a, b)| (once.positions[a] - once.positions[b]).len() > 0.4)
+            .collect();
+        long_edges.sort();
+        long_edges.dedup();
+        assert_eq!(left, long_edges.len());
+        assert!(left > 0);
+
+        // more passes than needed stop early with nothing left
+        let (enough, left) = mesh.subdivide_passes(0.4, 10);
+        assert_eq!(left, 0);
+        assert_eq!(enough.faces.len(), done.faces.len());
+    }
+}
@@ -0,0 +1,168 @@
+use crate::vec::Vec3;
+
+use std::f32::consts::PI;
+
+// keeps perfectly smooth surfaces from producing infinite densities
+const MIN_ALPHA: f32 = 1e-4;
+
+// anisotropic GGX / Trowbridge-Reitz distribution, all directions are in the
+// local shading frame with the normal along z
+pub struct Ggx {
+    pub alpha_x: f32,
+    pub alpha_y: f32
+}
+
+impl Ggx {
+    pub fn new(alpha_x: f32, alpha_y: f32) -> Self {
+        Ggx { alpha_x: alpha_x.max(MIN_ALPHA), alpha_y: alpha_y.max(MIN_ALPHA) }
+    }
+
+    // perceptual roughness is squared, anisotropy in [0, 1] stretches the
+    // highlight along the tangent
+    pub fn from_roughness(roughness: f32, anisotropy: f32) -> Self {
+        let alpha = roughness * roughness;
+        let aspect = (1.0 - 0.9 * anisotropy).sqrt();
+
+        Ggx::new(alpha / aspect, alpha * aspect)
+    }
+
+    pub fn d(&self, wm: Vec3) -> f32 {
+        if wm.z <= 0.0 {
+            return 0.0;
+        }
+
+        let e = (wm.x / self.alpha_x).powi(2) + (wm.y / self.alpha_y).powi(2) + wm.z * wm.z;
+        1.0 / (PI * self.alpha_x * self.alpha_y * e * e)
+    }
+
+    pub fn lambda(&self, w: Vec3) -> f32 {
+        let cos2 = w.z * w.z;
+        if cos2 <= 0.0 {
+            return std::f32::INFINITY;
+        }
+
+        let a2_tan2 = ((w.x * self.alpha_x).powi(2) + (w.y * self.alpha_y).powi(2)) / cos2;
+        0.5 * ((1.0 + a2_tan2).sqrt() - 1.0)
+    }
+
+    pub fn g1(&self, w: Vec3) -> f32 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // height correlated masking-shadowing
+    pub fn g2(&self, wo: Vec3, wi: Vec3) -> f32 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // Heitz 2018, "Sampling the GGX Distribution of Visible Normals"
+    pub fn sample_visible_normal(&self, wo: Vec3, u1: f32, u2: f32) -> Vec3 {
+        let vh = Vec3::new(self.alpha_x * wo.x, self.alpha_y * wo.y, wo.z).normalize();
+
+        let len2 = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if len2 > 0.0 {
+            Vec3::new(-vh.y, vh.x, 0.0) / len2.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = vh.cross(t1);
+
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * r * phi.sin();
+        let p3 = (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+
+        let nh = t1 * p1 + t2 * p2 + vh * p3;
+        Vec3::new(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(0.0)).normalize()
+    }
+
+    // density of reflected directions when sampling visible normals
+    pub fn pdf_reflection(&self, wo: Vec3, wm: Vec3) -> f32 {
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+
+        self.g1(wo) * self.d(wm) / (4.0 * wo.z)
+    }
+}
+
+// Berry distribution used by the clear coat lobe of the principled material
+pub struct Gtr1 {
+    pub alpha: f32
+}
+
+impl Gtr1 {
+    pub fn new(alpha: f32) -> Self {
+        Gtr1 { alpha: alpha.max(MIN_ALPHA) }
+    }
+
+    pub fn d(&self, wm: Vec3) -> f32 {
+        if wm.z <= 0.0 {
+            return 0.0;
+        }
+
+        let a2 = self.alpha * self.alpha;
+        (a2 - 1.0) / (PI * a2.ln() * (1.0 + (a2 - 1.0) * wm.z * wm.z))
+    }
+
+    pub fn sample(&self, u1: f32, u2: f32) -> Vec3 {
+        let a2 = self.alpha * self.alpha;
+        let cos_theta = ((1.0 - a2.powf(1.0 - u1)) / (1.0 - a2)).max(0.0).sqrt();
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u2;
+
+        Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+    }
+
+    pub fn pdf_reflection(&self, wo: Vec3, wm: Vec3) -> f32 {
+        let cos_om = wo.dot(wm);
+        if cos_om <= 0.0 {
+            return 0.0;
+        }
+
+        self.d(wm) * wm.z / (4.0 * cos_om)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // uniformly distributed directions on the upper hemisphere, pdf 1 / 2pi
+    fn hemisphere(i: usize, n: usize) -> Vec3 {
+        let golden = 0.618_034 * i as f32;
+        let z = (i as f32 + 0.5) / n as f32;
+        let r = (1.0 - z * z).sqrt();
+        let phi = 2.0 * PI * (golden - golden.floor());
+        Vec3::new(r * phi.cos(), r * phi.sin(), z)
+    }
+
+    #[test]
+    fn ggx_projected_area_is_normalized() {
+        let n = 200_000;
+
+        for &(ax, ay) in &[(0.3, 0.3), (0.5, 0.2), (0.8, 0.8)] {
+            let ggx = Ggx::new(ax, ay);
+            let integral: f32 = (0..n).map(|i| {
+                let wm = hemisphere(i, n);
+                ggx.d(wm) * wm.z * 2.0 * PI / n as f32
+            }).sum();
+
+            assert!((integral - 1.0).abs() < 0.01, "{} for {}, {}", integral, ax, ay);
+        }
+    }
+
+    #[test]
+    fn visible_normals_face_the_viewer() {
+        let ggx = Ggx::from_roughness(0.7, 0.5);
+        let wo = Vec3::new(0.6, -0.3, 0.5).normalize();
+
+        for i in 0..1000 {
+            let wm = ggx.sample_visible_normal(wo, (i as f32 + 0.5) / 1000.0, (i * 37 % 1000) as f32 / 1000.0);
+
+            assert!(wm.z >= 0.0 && wo.dot(wm) >= 0.0);
+            assert!((wm.len() - 1.0).abs() < 1e-4);
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use crate::vec::Vec3;
+
+use rand::random;
+use std::f32::consts::PI;
+
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3
+}
+
+impl Onb {
+    // branchless construction from Duff et al. 2017
+    pub fn from_w(w: Vec3) -> Self {
+        let w = w.normalize();
+        let sign = 1.0f32.copysign(w.z);
+        let a = -1.0 / (sign + w.z);
+        let b = w.x * w.y * a;
+
+        let u = Vec3::new(1.0 + sign * w.x * w.x * a, sign * b, -sign * w.x);
+        let v = Vec3::new(b, sign + w.y * w.y * a, -w.y);
+
+        Onb { u, v, w }
+    }
+
+    // frame around w with u along the tangent, e.g. dpdu of a hit, so that
+    // anisotropic materials line up with the surface parameterization. Falls
+    // back to an arbitrary frame without a usable tangent.
+    pub fn from_tangent(w: Vec3, tangent: Option<Vec3>) -> Self {
+        let w = w.normalize();
+
+        match tangent.map(|tangent| tangent - w * w.dot(tangent)) {
+            Some(u) if u.len_squared() > 1e-12 => {
+                let u = u.normalize();
+                Onb { u, v: w.cross(u), w }
+            },
+            _ => Onb::from_w(w)
+        }
+    }
+
+    pub fn local(&self, a: Vec3) -> Vec3 {
+        self.u * a.x + self.v * a.y + self.w * a.z
+    }
+
+    pub fn to_local(&self, a: Vec3) -> Vec3 {
+        Vec3::new(a.dot(self.u), a.dot(self.v), a.dot(self.w))
+    }
+}
+
+// cosine weighted direction around +z, the density is z / pi
+pub fn random_cosine_direction() -> Vec3 {
+    let r1 = random::<f32>();
+    let r2 = random::<f32>();
+    let phi = 2.0 * PI * r1;
+    let r = r2.sqrt();
+
+    Vec3::new(phi.cos() * r, phi.sin() * r, (1.0 - r2).sqrt())
+}
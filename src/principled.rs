@@ -0,0 +1,361 @@
+use crate::ray::Ray;
+use crate::vec::Vec3;
+use crate::hitable::HitRecord;
+use crate::material::{Material, Scatter};
+use crate::texture::Texture;
+use crate::onb::{self, Onb};
+use crate::microfacet::{Ggx, Gtr1};
+use crate::math;
+use crate::spectrum;
+
+use std::f32::consts::PI;
+use std::sync::Arc;
+use rand::random;
+
+// Burley 2012/2015 "physically based shading at Disney", every lobe is
+// importance sampled and the reflection lobes are combined with the one
+// sample model so that the attenuation is f * cos / pdf of the whole mixture
+pub struct Principled {
+    base_color: Arc<Texture>,
+    metallic: f32,
+    roughness: f32,
+    anisotropy: f32,
+    specular: f32,
+    specular_tint: f32,
+    sheen: f32,
+    sheen_tint: f32,
+    clearcoat: f32,
+    clearcoat_gloss: f32,
+    transmission: f32,
+    ior: f32
+}
+
+struct Lobes {
+    diffuse: f32,
+    specular: f32,
+    clearcoat: f32,
+    transmission: f32
+}
+
+impl Principled {
+    pub fn new(base_color: Arc<Texture>) -> Self {
+        Principled {
+            base_color,
+            metallic: 0.0,
+            roughness: 0.5,
+            anisotropy: 0.0,
+            specular: 0.5,
+            specular_tint: 0.0,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            clearcoat: 0.0,
+            clearcoat_gloss: 1.0,
+            transmission: 0.0,
+            ior: 1.5
+        }
+    }
+
+    pub fn with_metallic(mut self, metallic: f32) -> Self {
+        self.metallic = metallic;
+        self
+    }
+
+    pub fn with_roughness(mut self, roughness: f32) -> Self {
+        self.roughness = roughness;
+        self
+    }
+
+    pub fn with_anisotropy(mut self, anisotropy: f32) -> Self {
+        self.anisotropy = anisotropy;
+        self
+    }
+
+    pub fn with_specular(mut self, specular: f32, specular_tint: f32) -> Self {
+        self.specular = specular;
+        self.specular_tint = specular_tint;
+        self
+    }
+
+    pub fn with_sheen(mut self, sheen: f32, sheen_tint: f32) -> Self {
+        self.sheen = sheen;
+        self.sheen_tint = sheen_tint;
+        self
+    }
+
+    pub fn with_clearcoat(mut self, clearcoat: f32, clearcoat_gloss: f32) -> Self {
+        self.clearcoat = clearcoat;
+        self.clearcoat_gloss = clearcoat_gloss;
+        self
+    }
+
+    pub fn with_transmission(mut self, transmission: f32, ior: f32) -> Self {
+        self.transmission = transmission;
+        self.ior = ior;
+        self
+    }
+
+    fn lobes(&self) -> Lobes {
+        let dielectric = 1.0 - self.metallic;
+
+        Lobes {
+            diffuse: dielectric * (1.0 - self.transmission),
+            // the transmission lobe brings its own fresnel reflection
+            specular: 1.0 - dielectric * self.transmission,
+            clearcoat: 0.25 * self.clearcoat,
+            transmission: dielectric * self.transmission
+        }
+    }
+
+    fn clearcoat_distribution(&self) -> Gtr1 {
+        Gtr1::new(lerp(0.1, 0.001, self.clearcoat_gloss))
+    }
+
+    fn specular_color(&self, base: Vec3) -> Vec3 {
+        let tint = lerp_vec(Vec3::new(1.0, 1.0, 1.0), color_tint(base), self.specular_tint);
+        lerp_vec(tint * (0.08 * self.specular), base, self.metallic)
+    }
+
+    fn eval_reflection(&self, base: Vec3, wo: Vec3, wi: Vec3, ggx: &Ggx, lobes: &Lobes) -> Vec3 {
+        let wh = (wo + wi).normalize();
+        let cos_d = wi.dot(wh);
+        let fh = schlick_weight(cos_d);
+        let white = Vec3::new(1.0, 1.0, 1.0);
+
+        let mut f = Vec3::zero();
+
+        if lobes.diffuse > 0.0 {
+            let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+            let fd = (1.0 + (fd90 - 1.0) * schlick_weight(wi.z)) * (1.0 + (fd90 - 1.0) * schlick_weight(wo.z));
+            let sheen = lerp_vec(white, color_tint(base), self.sheen_tint) * (self.sheen * fh);
+
+            f += (base * (fd / PI) + sheen) * lobes.diffuse;
+        }
+
+        let spec0 = self.specular_color(base);
+        let fresnel = spec0 + (white - spec0) * fh;
+        f += fresnel * (lobes.specular * ggx.d(wh) * ggx.g2(wo, wi) / (4.0 * wo.z * wi.z));
+
+        if lobes.clearcoat > 0.0 {
+            let fr = 0.04 + 0.96 * fh;
+            let gr = Ggx::new(0.25, 0.25).g2(wo, wi);
+            f += white * (lobes.clearcoat * self.clearcoat_distribution().d(wh) * fr * gr / (4.0 * wo.z * wi.z));
+        }
+
+        f
+    }
+
+    fn pdf_reflection(&self, wo: Vec3, wi: Vec3, ggx: &Ggx, lobes: &Lobes) -> f32 {
+        let wh = (wo + wi).normalize();
+        let total = lobes.diffuse + lobes.specular + lobes.clearcoat;
+
+        let pdf = lobes.diffuse * wi.z / PI
+            + lobes.specular * ggx.pdf_reflection(wo, wh)
+            + lobes.clearcoat * self.clearcoat_distribution().pdf_reflection(wo, wh);
+
+        pdf / total
+    }
+
+    fn sample_reflection(&self, wo: Vec3, ggx: &Ggx, lobes: &Lobes) -> Vec3 {
+        let total = lobes.diffuse + lobes.specular + lobes.clearcoat;
+        let choice = random::<f32>() * total;
+
+        if choice < lobes.diffuse {
+            onb::random_cosine_direction()
+        } else if choice < lobes.diffuse + lobes.specular {
+            let wm = ggx.sample_visible_normal(wo, random::<f32>(), random::<f32>());
+            math::reflect(-wo, wm)
+        } else {
+            let wm = self.clearcoat_distribution().sample(random::<f32>(), random::<f32>());
+            math::reflect(-wo, wm)
+        }
+    }
+
+    // rough dielectric interface, eta is the index of the side being entered
+    fn sample_transmission(&self, wo: Vec3, eta: f32, ggx: &Ggx, tint: Vec3) -> Option<(Vec3, Vec3)> {
+        let wm = ggx.sample_visible_normal(wo, random::<f32>(), random::<f32>());
+        let reflect_prob = math::fresnel_dielectric(wo.dot(wm), eta);
+
+        let (wi, attenuation) = if random::<f32>() < reflect_prob {
+            let reflected = math::reflect(-wo, wm);
+            if reflected.z <= 0.0 {
+                return None;
+            }
+            (reflected, Vec3::new(1.0, 1.0, 1.0))
+        } else {
+            let refracted = math::refract(-wo, wm, 1.0 / eta)?;
+            if refracted.z >= 0.0 {
+                return None;
+            }
+            (refracted, tint)
+        };
+
+        Some((wi, attenuation * (ggx.g2(wo, wi) / ggx.g1(wo))))
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Scatter {
+        let inside = ray.direction.dot(record.normal) > 0.0;
+        let frame = Onb::from_tangent(if inside { -record.shading_normal } else { record.shading_normal }, record.tangent);
+        let wo = frame.to_local(-ray.direction.normalize());
+        let ggx = Ggx::from_roughness(self.roughness, self.anisotropy);
+        let lobes = self.lobes();
+
+        // leaving a transmissive object, only the interface is left to handle
+        if inside && lobes.transmission > 0.0 {
+            return match self.sample_transmission(wo, 1.0 / self.ior, &ggx, Vec3::new(1.0, 1.0, 1.0)) {
+                Some((wi, attenuation)) => Scatter::new(attenuation, Some(Ray::new(record.p, frame.local(wi), ray.time))),
+                None => Scatter::new(Vec3::zero(), None)
+            };
+        }
+
+        let base = self.base_color.value(record.u, record.v, &record.p);
+        let total = lobes.diffuse + lobes.specular + lobes.clearcoat + lobes.transmission;
+        let transmission_prob = lobes.transmission / total;
+
+        if random::<f32>() < transmission_prob {
+            return match self.sample_transmission(wo, self.ior, &ggx, base) {
+                Some((wi, attenuation)) => {
+                    let weight = lobes.transmission / transmission_prob;
+                    Scatter::new(attenuation * weight, Some(Ray::new(record.p, frame.local(wi), ray.time)))
+                },
+                None => Scatter::new(Vec3::zero(), None)
+            };
+        }
+
+        let wi = self.sample_reflection(wo, &ggx, &lobes);
+        if wi.z <= 0.0 || wo.z <= 0.0 {
+            return Scatter::new(Vec3::zero(), None);
+        }
+
+        let pdf = (1.0 - transmission_prob) * self.pdf_reflection(wo, wi, &ggx, &lobes);
+        if pdf <= 0.0 {
+            return Scatter::new(Vec3::zero(), None);
+        }
+
+        let attenuation = self.eval_reflection(base, wo, wi, &ggx, &lobes) * (wi.z / pdf);
+        Scatter::new(attenuation, Some(Ray::new(record.p, frame.local(wi), ray.time)))
+    }
+}
+
+fn schlick_weight(cosine: f32) -> f32 {
+    (1.0 - cosine).max(0.0).min(1.0).powi(5)
+}
+
+fn color_tint(base: Vec3) -> Vec3 {
+    let luminance = spectrum::luminance(base);
+
+    if luminance > 0.0 {
+        base / luminance
+    } else {
+        Vec3::new(1.0, 1.0, 1.0)
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn lerp_vec(a: Vec3, b: Vec3, t: f32) -> Vec3 {
+    a + (b - a) * t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::ConstantTexture;
+
+    fn flat_hit<'a>(material: &'a Material) -> HitRecord<'a> {
+        HitRecord::new(1.0, Vec3::zero(), Vec3::new(0.0, 0.0, 1.0), material, 0.5, 0.5)
+            .with_frame(Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0))
+    }
+
+    fn incoming(direction: Vec3) -> Ray {
+        Ray::new(-direction, direction, 0.0)
+    }
+
+    fn white() -> Principled {
+        Principled::new(Arc::new(ConstantTexture::new(Vec3::new(1.0, 1.0, 1.0))))
+    }
+
+    fn mean_attenuation(material: &Principled, direction: Vec3, samples: usize) -> f32 {
+        let record = flat_hit(material);
+        let ray = incoming(direction);
+
+        (0..samples).map(|_| material.scatter(&ray, &record).attenuation.x).sum::<f32>() / samples as f32
+    }
+
+    #[test]
+    fn anisotropic_highlight_follows_the_tangent() {
+        let material = white().with_metallic(1.0).with_roughness(0.5).with_anisotropy(1.0);
+        let ray = incoming(Vec3::new(0.0, 0.0, -1.0));
+
+        let spread = |record: &HitRecord| {
+            let (mut x, mut y) = (0.0, 0.0);
+            for _ in 0..4000 {
+                if let Some(scattered) = material.scatter(&ray, record).ray {
+                    let direction = scattered.direction.normalize();
+                    x += direction.x * direction.x;
+                    y += direction.y * direction.y;
+                }
+            }
+            (x, y)
+        };
+
+        let mut record = flat_hit(&material);
+        let (x, y) = spread(&record);
+        assert!(x > 2.0 * y);
+
+        record.tangent = Some(Vec3::new(0.0, 1.0, 0.0));
+        let (x, y) = spread(&record);
+        assert!(y > 2.0 * x);
+    }
+
+    #[test]
+    fn reflection_stays_close_to_energy_conserving() {
+        let direction = Vec3::new(0.3, 0.0, -1.0).normalize();
+
+        // a white metal only loses light to the shadowing of the microfacets
+        let metal = mean_attenuation(&white().with_metallic(1.0).with_roughness(0.3), direction, 20000);
+        assert!(metal > 0.9 && metal < 1.01, "metal {}", metal);
+
+        // the disney diffuse and the layers on top of it are not strictly
+        // energy conserving, but they only gain a few percent
+        let layered = vec![
+            white().with_roughness(0.5),
+            white().with_roughness(0.8).with_sheen(1.0, 0.0),
+            white().with_roughness(0.5).with_clearcoat(1.0, 1.0)
+        ];
+
+        for material in &layered {
+            let albedo = mean_attenuation(material, direction, 20000);
+            assert!(albedo > 0.9 && albedo < 1.1, "albedo {}", albedo);
+        }
+    }
+
+    #[test]
+    fn clearcoat_adds_a_reflection_on_top() {
+        let base = Principled::new(Arc::new(ConstantTexture::new(Vec3::new(0.2, 0.2, 0.2)))).with_roughness(0.5);
+        let direction = Vec3::new(1.0, 0.0, -0.3).normalize();
+
+        let plain = mean_attenuation(&base, direction, 20000);
+        let coated = mean_attenuation(&base.with_clearcoat(1.0, 1.0), direction, 20000);
+        assert!(coated > plain + 0.02, "{} vs {}", coated, plain);
+    }
+
+    #[test]
+    fn transmission_passes_through_the_surface() {
+        let material = white().with_roughness(0.05).with_transmission(1.0, 1.5);
+        let record = flat_hit(&material);
+        let ray = incoming(Vec3::new(0.0, 0.0, -1.0));
+
+        let samples = 4000;
+        let transmitted = (0..samples)
+            .filter(|_| material.scatter(&ray, &record).ray.map_or(false, |r| r.direction.z < 0.0))
+            .count();
+
+        // only the fresnel reflection of about 4% at normal incidence stays outside
+        let fraction = transmitted as f32 / samples as f32;
+        assert!(fraction > 0.9 && fraction < 0.99, "transmitted {}", fraction);
+    }
+}
@@ -0,0 +1,247 @@
+use crate::hitable::Hitable;
+use crate::hitable::HitRecord;
+use crate::hitable::Area;
+use crate::aabb::AABB;
+use crate::vec::Vec3;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::onb::Onb;
+
+use rand::random;
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+// parallelogram spanned by two edges from a corner, the normal is u x v and
+// u, v run from 0 to 1 along the edges
+pub struct Quad {
+    pub q: Vec3,
+    pub u: Vec3,
+    pub v: Vec3,
+    pub material: Arc<Material>,
+    normal: Vec3,
+    w: Vec3,
+    d: f32
+}
+
+impl Quad {
+    pub fn new(q: Vec3, u: Vec3, v: Vec3, material: Arc<Material>) -> Self {
+        let n = u.cross(v);
+        let normal = n.normalize();
+
+        Quad { q, u, v, material, normal, w: n / n.dot(n), d: normal.dot(q) }
+    }
+}
+
+impl Area for Quad {
+    fn area(&self) -> f32 {
+        self.u.cross(self.v).len()
+    }
+}
+
+impl Hitable for Quad {
+    fn hit(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
+        let denominator = self.normal.dot(ray.direction);
+        if denominator.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = (self.d - self.normal.dot(ray.origin)) / denominator;
+        if t < t_range.start || t > t_range.end {
+            return None;
+        }
+
+        let p = ray.point_at_parameter(t);
+        let planar = p - self.q;
+        let alpha = self.w.dot(planar.cross(self.v));
+        let beta = self.w.dot(self.u.cross(planar));
+
+        if alpha < 0.0 || alpha > 1.0 || beta < 0.0 || beta > 1.0 {
+            return None;
+        }
+
+        Some(HitRecord::new(t, p, self.normal, &*self.material, alpha, beta).with_frame(self.u, self.v))
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        let corners = [self.q + self.u, self.q + self.v, self.q + self.u + self.v];
+        let bbox = corners.iter().fold(AABB::new(self.q, self.q), |bbox, c| AABB::surrounding_box(&bbox, &AABB::new(*c, *c)));
+
+        // quads lying in an axis plane would otherwise get a flat box
+        Some(AABB::new(bbox.min - 0.0001, bbox.max + 0.0001))
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f32 {
+        match self.hit(&Ray::new(origin, direction, 0.0), 0.001..std::f32::MAX) {
+            Some(record) => {
+                let distance_squared = record.t * record.t * direction.len_squared();
+                let cosine = (direction.dot(self.normal) / direction.len()).abs();
+                distance_squared / (cosine * self.area())
+            },
+            None => 0.0
+        }
+    }
+
+    fn random(&self, origin: Vec3) -> Vec3 {
+        self.q + self.u * random::<f32>() + self.v * random::<f32>() - origin
+    }
+}
+
+// flat disk or annulus around a center, u is the angle around the normal
+// and v runs from the inner to the outer radius
+pub struct Disk {
+    pub center: Vec3,
+    pub normal: Vec3,
+    pub radius: f32,
+    pub inner_radius: f32,
+    pub material: Arc<Material>,
+    frame: Onb
+}
+
+impl Disk {
+    pub fn new(center: Vec3, normal: Vec3, radius: f32, material: Arc<Material>) -> Self {
+        Disk::annulus(center, normal, 0.0, radius, material)
+    }
+
+    pub fn annulus(center: Vec3, normal: Vec3, inner_radius: f32, radius: f32, material: Arc<Material>) -> Self {
+        let frame = Onb::from_w(normal);
+        Disk { center, normal: frame.w, radius, inner_radius, material, frame }
+    }
+}
+
+impl Area for Disk {
+    fn area(&self) -> f32 {
+        PI * (self.radius * self.radius - self.inner_radius * self.inner_radius)
+    }
+}
+
+impl Hitable for Disk {
+    fn hit(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
+        let denominator = self.normal.dot(ray.direction);
+        if denominator.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = (self.center - ray.origin).dot(self.normal) / denominator;
+        if t < t_range.start || t > t_range.end {
+            return None;
+        }
+
+        let p = ray.point_at_parameter(t);
+        let local = self.frame.to_local(p - self.center);
+        let distance = (local.x * local.x + local.y * local.y).sqrt();
+
+        if distance > self.radius || distance < self.inner_radius {
+            return None;
+        }
+
+        let mut phi = local.y.atan2(local.x);
+        if phi < 0.0 {
+            phi += 2.0 * PI;
+        }
+
+        let v = (distance - self.inner_radius) / (self.radius - self.inner_radius);
+        let dpdu = self.frame.local(Vec3::new(-local.y, local.x, 0.0)) * (2.0 * PI);
+        let dpdv = self.frame.local(Vec3::new(local.x, local.y, 0.0) / distance.max(1e-6)) * (self.radius - self.inner_radius);
+
+        Some(HitRecord::new(t, p, self.normal, &*self.material, phi / (2.0 * PI), v).with_frame(dpdu, dpdv))
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        let extent = disk_extent(self.normal, self.radius) + 0.0001;
+        Some(AABB::new(self.center - extent, self.center + extent))
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f32 {
+        match self.hit(&Ray::new(origin, direction, 0.0), 0.001..std::f32::MAX) {
+            Some(record) => {
+                let distance_squared = record.t * record.t * direction.len_squared();
+                let cosine = (direction.dot(self.normal) / direction.len()).abs();
+                distance_squared / (cosine * self.area())
+            },
+            None => 0.0
+        }
+    }
+
+    fn random(&self, origin: Vec3) -> Vec3 {
+        let inner2 = self.inner_radius * self.inner_radius;
+        let r = (inner2 + random::<f32>() * (self.radius * self.radius - inner2)).sqrt();
+        let phi = 2.0 * PI * random::<f32>();
+        let point = self.center + self.frame.local(Vec3::new(r * phi.cos(), r * phi.sin(), 0.0));
+
+        point - origin
+    }
+}
+
+// half extent along each axis of a circle with the given unit normal, which
+// is the radius times the sine of the angle between axis and normal
+pub fn disk_extent(normal: Vec3, radius: f32) -> Vec3 {
+    Vec3::new(
+        (1.0 - normal.x * normal.x).max(0.0).sqrt(),
+        (1.0 - normal.y * normal.y).max(0.0).sqrt(),
+        (1.0 - normal.z * normal.z).max(0.0).sqrt()
+    ) * radius
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::PassThrough;
+    use crate::sphere;
+
+    fn unused() -> Arc<Material> {
+        Arc::new(PassThrough::new(Vec3::zero()))
+    }
+
+    // the density over all directions, estimated from uniform directions
+    fn solid_angle_integral(hitable: &Hitable, origin: Vec3) -> f32 {
+        let samples = 200000;
+        let sum: f32 = (0..samples)
+            .map(|_| hitable.pdf_value(origin, sphere::random_in_unit_sphere().normalize()))
+            .sum();
+
+        4.0 * PI * sum / samples as f32
+    }
+
+    #[test]
+    fn quad_hit_reports_the_parameterization() {
+        let quad = Quad::new(Vec3::new(0.0, 0.0, -1.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 4.0, 0.0), unused());
+        let record = quad.hit(&Ray::new(Vec3::new(0.5, 3.0, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0), 0.001..std::f32::MAX).unwrap();
+
+        assert!((record.t - 2.0).abs() < 1e-5);
+        assert!((record.u - 0.25).abs() < 1e-5 && (record.v - 0.75).abs() < 1e-5);
+        assert!(quad.hit(&Ray::new(Vec3::new(2.5, 3.0, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0), 0.001..std::f32::MAX).is_none());
+    }
+
+    #[test]
+    fn annulus_has_a_hole() {
+        let ring = Disk::annulus(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), 1.0, 2.0, unused());
+        let down = |x: f32| Ray::new(Vec3::new(x, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+
+        assert!(ring.hit(&down(0.5), 0.001..std::f32::MAX).is_none());
+        assert!(ring.hit(&down(1.5), 0.001..std::f32::MAX).is_some());
+        assert!(ring.hit(&down(2.5), 0.001..std::f32::MAX).is_none());
+    }
+
+    #[test]
+    fn light_sampling_densities_integrate_to_one() {
+        let quad = Quad::new(Vec3::new(-1.0, 1.0, -1.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 0.5, 2.0), unused());
+        assert!((solid_angle_integral(&quad, Vec3::zero()) - 1.0).abs() < 0.03);
+
+        let ring = Disk::annulus(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.3, -1.0, 0.0), 0.5, 1.5, unused());
+        assert!((solid_angle_integral(&ring, Vec3::zero()) - 1.0).abs() < 0.03);
+    }
+
+    #[test]
+    fn sampled_directions_reach_the_shape() {
+        let origin = Vec3::new(0.3, -2.0, 0.1);
+        let quad = Quad::new(Vec3::new(-1.0, 1.0, -1.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 0.5, 2.0), unused());
+        let ring = Disk::annulus(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.3, -1.0, 0.0), 0.5, 1.5, unused());
+
+        for shape in &[&quad as &Hitable, &ring] {
+            for _ in 0..1000 {
+                let direction = shape.random(origin);
+                assert!(shape.pdf_value(origin, direction) > 0.0);
+            }
+        }
+    }
+}
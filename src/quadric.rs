@@ -0,0 +1,348 @@
+use crate::hitable::Hitable;
+use crate::hitable::HitRecord;
+use crate::aabb::AABB;
+use crate::vec::Vec3;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::onb::Onb;
+use crate::quad::{Disk, disk_extent};
+use crate::math;
+
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+// Shapes are intersected in a local frame with the axis along z, the frame
+// is orthonormal so ray parameters carry over unchanged. Side surfaces map
+// the angle around the axis to u and the height to v, caps are disks.
+
+// cylinder from base along axis, the axis length is the height
+pub struct Cylinder {
+    base: Vec3,
+    height: f32,
+    radius: f32,
+    material: Arc<Material>,
+    frame: Onb,
+    caps: Option<[Disk; 2]>
+}
+
+impl Cylinder {
+    pub fn new(base: Vec3, axis: Vec3, radius: f32, material: Arc<Material>) -> Self {
+        Cylinder { base, height: axis.len(), radius, material, frame: Onb::from_w(axis), caps: None }
+    }
+
+    pub fn with_caps(mut self) -> Self {
+        self.caps = Some(caps(self.base, &self.frame, self.height, self.radius, self.radius, &self.material));
+        self
+    }
+}
+
+impl Hitable for Cylinder {
+    fn hit(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
+        let o = self.frame.to_local(ray.origin - self.base);
+        let d = self.frame.to_local(ray.direction);
+
+        let a = d.x * d.x + d.y * d.y;
+        let b = 2.0 * (o.x * d.x + o.y * d.y);
+        let c = o.x * o.x + o.y * o.y - self.radius * self.radius;
+
+        let side = side_hit(math::solve_quadratic(a, b, c), &o, &d, self.height, &t_range).map(|(t, p)| {
+            let normal = self.frame.local(Vec3::new(p.x, p.y, 0.0) / self.radius);
+            HitRecord::new(t, ray.point_at_parameter(t), normal, &*self.material, angle(&p), p.z / self.height)
+                .with_frame(around(&self.frame, &p), self.frame.w * self.height)
+        });
+
+        closest_cap(side, &self.caps, ray, t_range)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(caps_box(self.base, self.frame.w, self.height, self.radius, self.radius))
+    }
+}
+
+// cone or truncated cone from a base radius to a top radius along the axis
+pub struct Cone {
+    base: Vec3,
+    height: f32,
+    base_radius: f32,
+    top_radius: f32,
+    material: Arc<Material>,
+    frame: Onb,
+    caps: Option<[Disk; 2]>
+}
+
+impl Cone {
+    pub fn new(base: Vec3, axis: Vec3, base_radius: f32, top_radius: f32, material: Arc<Material>) -> Self {
+        Cone { base, height: axis.len(), base_radius, top_radius, material, frame: Onb::from_w(axis), caps: None }
+    }
+
+    pub fn with_caps(mut self) -> Self {
+        self.caps = Some(caps(self.base, &self.frame, self.height, self.base_radius, self.top_radius, &self.material));
+        self
+    }
+}
+
+impl Hitable for Cone {
+    fn hit(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
+        let o = self.frame.to_local(ray.origin - self.base);
+        let d = self.frame.to_local(ray.direction);
+
+        // x^2 + y^2 = (r0 + k z)^2
+        let k = (self.top_radius - self.base_radius) / self.height;
+        let r = self.base_radius + k * o.z;
+
+        let a = d.x * d.x + d.y * d.y - k * k * d.z * d.z;
+        let b = 2.0 * (o.x * d.x + o.y * d.y - k * r * d.z);
+        let c = o.x * o.x + o.y * o.y - r * r;
+
+        let side = side_hit(math::solve_quadratic(a, b, c), &o, &d, self.height, &t_range).map(|(t, p)| {
+            let gradient = Vec3::new(p.x, p.y, -k * (self.base_radius + k * p.z));
+            let normal = self.frame.local(gradient.normalize());
+
+            // along the side from the base to the top
+            let r = (p.x * p.x + p.y * p.y).sqrt().max(1e-6);
+            let dpdv = self.frame.local(Vec3::new(k * p.x / r, k * p.y / r, 1.0)) * self.height;
+
+            HitRecord::new(t, ray.point_at_parameter(t), normal, &*self.material, angle(&p), p.z / self.height)
+                .with_frame(around(&self.frame, &p), dpdv)
+        });
+
+        closest_cap(side, &self.caps, ray, t_range)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(caps_box(self.base, self.frame.w, self.height, self.base_radius, self.top_radius))
+    }
+}
+
+// torus around a center with the given axis, the major radius is the
+// distance from the center to the middle of the tube. u runs around the
+// axis and v around the tube.
+pub struct Torus {
+    center: Vec3,
+    major_radius: f32,
+    minor_radius: f32,
+    material: Arc<Material>,
+    frame: Onb
+}
+
+impl Torus {
+    pub fn new(center: Vec3, axis: Vec3, major_radius: f32, minor_radius: f32, material: Arc<Material>) -> Self {
+        Torus { center, major_radius, minor_radius, material, frame: Onb::from_w(axis) }
+    }
+}
+
+impl Hitable for Torus {
+    fn hit(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
+        let length = ray.direction.len() as f64;
+        let o = self.frame.to_local(ray.origin - self.center);
+        let d = self.frame.to_local(ray.direction);
+
+        // the quartic is far better conditioned for a unit direction
+        let (ox, oy, oz) = (o.x as f64, o.y as f64, o.z as f64);
+        let (dx, dy, dz) = (d.x as f64 / length, d.y as f64 / length, d.z as f64 / length);
+        let major2 = (self.major_radius as f64).powi(2);
+        let minor2 = (self.minor_radius as f64).powi(2);
+
+        // (|o + t d|^2 + R^2 - r^2)^2 = 4 R^2 ((ox + t dx)^2 + (oy + t dy)^2)
+        let e = ox * dx + oy * dy + oz * dz;
+        let g = ox * ox + oy * oy + oz * oz + major2 - minor2;
+
+        let roots = math::solve_quartic(
+            1.0,
+            4.0 * e,
+            4.0 * e * e + 2.0 * g - 4.0 * major2 * (dx * dx + dy * dy),
+            4.0 * e * g - 8.0 * major2 * (ox * dx + oy * dy),
+            g * g - 4.0 * major2 * (ox * ox + oy * oy)
+        );
+
+        let t = roots.iter()
+            .map(|root| (*root / length) as f32)
+            .find(|t| *t > t_range.start && *t < t_range.end)?;
+
+        let p = o + d * t;
+        let ring = Vec3::new(p.x, p.y, 0.0).normalize() * self.major_radius;
+        let tube = p - ring;
+
+        let ring_distance = (p.x * p.x + p.y * p.y).sqrt() - self.major_radius;
+        let mut tube_angle = p.z.atan2(ring_distance);
+        if tube_angle < 0.0 {
+            tube_angle += 2.0 * PI;
+        }
+
+        // around the tube in the plane of the axis and the ring direction
+        let ring_direction = Vec3::new(p.x, p.y, 0.0).normalize();
+        let dpdv = self.frame.local(ring_direction * -p.z + Vec3::new(0.0, 0.0, ring_distance)) * (2.0 * PI);
+
+        Some(HitRecord::new(
+            t,
+            ray.point_at_parameter(t),
+            self.frame.local(tube.normalize()),
+            &*self.material,
+            angle(&p),
+            tube_angle / (2.0 * PI)
+        ).with_frame(around(&self.frame, &p), dpdv))
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        let extent = disk_extent(self.frame.w, self.major_radius) + self.minor_radius;
+        Some(AABB::new(self.center - extent, self.center + extent))
+    }
+}
+
+// nearest root of the side surface within the ray range and the height,
+// together with the local hit point
+fn side_hit(roots: Option<(f32, f32)>, o: &Vec3, d: &Vec3, height: f32, t_range: &::std::ops::Range<f32>) -> Option<(f32, Vec3)> {
+    let (t0, t1) = roots?;
+
+    for &t in [t0, t1].iter() {
+        if t <= t_range.start || t >= t_range.end {
+            continue;
+        }
+
+        let p = *o + *d * t;
+        if p.z >= 0.0 && p.z <= height {
+            return Some((t, p));
+        }
+    }
+
+    None
+}
+
+fn caps(base: Vec3, frame: &Onb, height: f32, base_radius: f32, top_radius: f32, material: &Arc<Material>) -> [Disk; 2] {
+    [
+        Disk::new(base, -frame.w, base_radius, material.clone()),
+        Disk::new(base + frame.w * height, frame.w, top_radius, material.clone())
+    ]
+}
+
+fn closest_cap<'a>(side: Option<HitRecord<'a>>, caps: &'a Option<[Disk; 2]>, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord<'a>> {
+    let caps = match caps {
+        Some(caps) => caps,
+        None => return side
+    };
+
+    let mut closest = side;
+    for cap in caps.iter() {
+        let t_end = closest.map_or(t_range.end, |hit| hit.t);
+        if let Some(hit) = cap.hit(ray, t_range.start..t_end) {
+            closest = Some(hit);
+        }
+    }
+
+    closest
+}
+
+fn caps_box(base: Vec3, axis: Vec3, height: f32, base_radius: f32, top_radius: f32) -> AABB {
+    let top = base + axis * height;
+    let base_extent = disk_extent(axis, base_radius);
+    let top_extent = disk_extent(axis, top_radius);
+
+    AABB::surrounding_box(
+        &AABB::new(base - base_extent - 0.0001, base + base_extent + 0.0001),
+        &AABB::new(top - top_extent - 0.0001, top + top_extent + 0.0001)
+    )
+}
+
+// derivative of the position by the angle of `angle` around the axis
+fn around(frame: &Onb, p: &Vec3) -> Vec3 {
+    frame.local(Vec3::new(-p.y, p.x, 0.0)) * (2.0 * PI)
+}
+
+fn angle(p: &Vec3) -> f32 {
+    let mut phi = p.y.atan2(p.x);
+    if phi < 0.0 {
+        phi += 2.0 * PI;
+    }
+
+    phi / (2.0 * PI)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::PassThrough;
+
+    fn unused() -> Arc<Material> {
+        Arc::new(PassThrough::new(Vec3::zero()))
+    }
+
+    fn hit<'a>(hitable: &'a Hitable, origin: Vec3, direction: Vec3) -> Option<HitRecord<'a>> {
+        hitable.hit(&Ray::new(origin, direction, 0.0), 0.001..std::f32::MAX)
+    }
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).len() < 1e-3
+    }
+
+    #[test]
+    fn cylinder_side_and_caps() {
+        let open = Cylinder::new(Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 2.0, 0.0), 0.5, unused());
+        let record = hit(&open, Vec3::new(-3.0, 0.5, 0.0), Vec3::new(1.0, 0.0, 0.0)).unwrap();
+
+        assert!((record.t - 2.5).abs() < 1e-4);
+        assert!(close(record.normal, Vec3::new(-1.0, 0.0, 0.0)));
+        assert!((record.v - 0.75).abs() < 1e-4);
+
+        // straight down the open tube and past its ends
+        assert!(hit(&open, Vec3::new(0.0, 3.0, 0.0), Vec3::new(0.0, -1.0, 0.0)).is_none());
+        assert!(hit(&open, Vec3::new(-3.0, 1.5, 0.0), Vec3::new(1.0, 0.0, 0.0)).is_none());
+
+        let closed = Cylinder::new(Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 2.0, 0.0), 0.5, unused()).with_caps();
+        let record = hit(&closed, Vec3::new(0.1, 3.0, 0.0), Vec3::new(0.0, -1.0, 0.0)).unwrap();
+
+        assert!((record.t - 2.0).abs() < 1e-4);
+        assert!(close(record.normal, Vec3::new(0.0, 1.0, 0.0)));
+    }
+
+    #[test]
+    fn cone_normals_lean_towards_the_tip() {
+        // a 45 degree cone with its tip at the origin
+        let cone = Cone::new(Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 1.0, 0.0, unused());
+        let record = hit(&cone, Vec3::new(-3.0, -0.5, 0.0), Vec3::new(1.0, 0.0, 0.0)).unwrap();
+
+        assert!((record.t - 2.5).abs() < 1e-4);
+        assert!(close(record.normal, Vec3::new(-1.0, 1.0, 0.0).normalize()));
+
+        // the mirrored nappe above the tip is not part of the cone
+        assert!(hit(&cone, Vec3::new(-3.0, 0.5, 0.0), Vec3::new(1.0, 0.0, 0.0)).is_none());
+
+        let frustum = Cone::new(Vec3::zero(), Vec3::new(0.0, 0.0, 1.0), 1.0, 0.5, unused()).with_caps();
+        let record = hit(&frustum, Vec3::new(0.7, 0.0, -2.0), Vec3::new(0.0, 0.0, 1.0)).unwrap();
+
+        assert!((record.t - 2.0).abs() < 1e-4);
+        assert!(close(record.normal, Vec3::new(0.0, 0.0, -1.0)));
+        assert!(frustum.bounding_box().unwrap().hit(&Ray::new(Vec3::new(0.99, 0.0, -2.0), Vec3::new(0.0, 0.0, 1.0), 0.0), 0.0, 10.0));
+    }
+
+    #[test]
+    fn torus_has_a_hole() {
+        let torus = Torus::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 2.0, 0.5, unused());
+
+        // through the tube on both sides of the hole
+        let record = hit(&torus, Vec3::new(-5.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0)).unwrap();
+        assert!((record.t - 2.5).abs() < 1e-3);
+        assert!(close(record.normal, Vec3::new(-1.0, 0.0, 0.0)));
+
+        let record = hit(&torus, Vec3::new(0.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0)).unwrap();
+        assert!((record.t - 1.5).abs() < 1e-3);
+        assert!(close(record.normal, Vec3::new(-1.0, 0.0, 0.0)));
+
+        // down the axis and down the middle of the tube
+        assert!(hit(&torus, Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0)).is_none());
+        let record = hit(&torus, Vec3::new(2.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0)).unwrap();
+        assert!((record.t - 3.5).abs() < 1e-3);
+        assert!(close(record.normal, Vec3::new(0.0, 1.0, 0.0)));
+    }
+
+    #[test]
+    fn torus_ignores_the_direction_length() {
+        let torus = Torus::new(Vec3::zero(), Vec3::new(0.0, 0.0, 1.0), 1.0, 0.25, unused());
+        let origin = Vec3::new(-40.0, 0.3, 0.1);
+
+        let unit = hit(&torus, origin, Vec3::new(1.0, 0.0, 0.0)).unwrap();
+        let long = hit(&torus, origin, Vec3::new(7.0, 0.0, 0.0)).unwrap();
+
+        assert!((unit.t - 7.0 * long.t).abs() < 1e-3);
+        assert!(close(unit.p, long.p));
+    }
+}
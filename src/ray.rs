@@ -1,18 +1,24 @@
-use crate::vec;
-
-#[derive(Clone, Copy)]
-pub struct Ray {
-    pub origin: vec::Vec3,
-    pub direction: vec::Vec3,
-    pub time: f32
-}
-
-impl Ray {
-    pub fn new(origin: vec::Vec3, dir: vec::Vec3, time: f32) -> Self {
-        Ray { origin: origin, direction: dir, time }
-    }
-
-    pub fn point_at_parameter(&self, t: f32) -> vec::Vec3 {
-        return self.origin + self.direction * t;
-    }
+use crate::vec;
+
+#[derive(Clone, Copy)]
+pub struct Ray {
+    pub origin: vec::Vec3,
+    pub direction: vec::Vec3,
+    pub time: f32,
+    pub wavelength: Option<f32>
+}
+
+impl Ray {
+    pub fn new(origin: vec::Vec3, dir: vec::Vec3, time: f32) -> Self {
+        Ray { origin: origin, direction: dir, time, wavelength: None }
+    }
+
+    pub fn with_wavelength(mut self, wavelength: Option<f32>) -> Self {
+        self.wavelength = wavelength;
+        self
+    }
+
+    pub fn point_at_parameter(&self, t: f32) -> vec::Vec3 {
+        return self.origin + self.direction * t;
+    }
 }
\ No newline at end of file
@@ -1,65 +1,138 @@
-use crate::scene::Scene;
-use crate::ray::Ray;
-use crate::camera::Camera;
-use crate::vec::Vec3;
-use crate::hitable::Hitable;
-
-use lodepng::RGB;
-use rand::distributions::Uniform;
-use rand::prelude::*;
-use rayon::prelude::*;
-
-fn color_ray(r: &Ray, scene: &Scene, depth: u32) -> Vec3 {
-    let hit = scene.bvh.hit(&r, 0.001..std::f32::MAX);
-
-    match hit {
-        Some(rec) => {
-            let scattered = rec.material.scatter(&r, &rec);
-            let emitted = rec.material.emitted(rec.u, rec.v, rec.p);
-
-            if let Some(scattered_ray) = scattered.ray {
-                return emitted + color_ray(&scattered_ray, scene, depth + 1) * scattered.attenuation;
-            } else {
-                return emitted;
-            }
-        },
-        None => {
-                return Vec3::zero();
-            }
-    }
-}
-
-pub fn render(scene: &Scene, camera: &Camera, width: usize, height: usize, samples: usize) -> Vec<RGB<u8>> {
-    let nx = width;
-    let ny = height;
-    let ns = samples;
-
-    let mut pixels: Vec<RGB<u8>> = vec![RGB {r: 0, g: 0, b: 0}; nx * ny];
-
-    pixels.par_iter_mut().enumerate().for_each(|(i, p)| {
-        let x = i % nx;
-        let y = ny - (i - x) / nx;
-
-        let mut col = Vec3::new(0.0, 0.0, 0.0);
-
-        for _s in 0..ns {
-            let mut rng = thread_rng();
-            let uniform = Uniform::new(0.0, 1.0);
-            let (r1, r2) = (uniform.sample(&mut rng), uniform.sample(&mut rng));
-
-            let u = (x as f32 + r1) / nx as f32;
-            let v = (y as f32 + r2) / ny as f32;
-            let r = camera.get_ray(u, v);
-            col += color_ray(&r, *&scene, 0);
-        }
-
-        col /= ns as f32;
-        col = Vec3::new( col.x.sqrt(), col.y.sqrt(), col.z.sqrt());
-
-        p.r = (255.99 * col.x) as u8;
-        p.g = (255.99 * col.y) as u8;
-        p.b = (255.99 * col.z) as u8;
-    });
-
-    return pixels;
-}
\ No newline at end of file
+use crate::scene::Scene;
+use crate::ray::Ray;
+use crate::camera::Camera;
+use crate::vec::Vec3;
+use crate::hitable::Hitable;
+
+use lodepng::RGB;
+use rand::distributions::Uniform;
+use rand::prelude::*;
+use rayon::prelude::*;
+
+fn color_ray(r: &Ray, scene: &Scene, depth: u32) -> Vec3 {
+    let hit = scene.bvh.hit(&r, 0.001..std::f32::MAX);
+
+    let mut volume_emitted = Vec3::zero();
+    if scene.bvh.has_volume_emission() {
+        let t_end = hit.map_or(std::f32::MAX, |rec| rec.t);
+        volume_emitted = scene.bvh.volume_emission(&r, 0.001..t_end);
+    }
+
+    match hit {
+        Some(rec) => {
+            let emitted = volume_emitted + rec.material.emitted(&r, &rec);
+
+            // paths trapped by total internal reflection never escape
+            if depth >= scene.max_ray_depth {
+                return emitted;
+            }
+
+            let scattered = rec.material.scatter(&r, &rec);
+
+            if let Some(mut scattered_ray) = scattered.ray {
+                if scattered_ray.wavelength.is_none() {
+                    scattered_ray.wavelength = r.wavelength;
+                }
+
+                let mut attenuation = scattered.attenuation;
+                if !scene.lights.is_empty() && rec.material.scattering_pdf(&r, &rec, scattered_ray.direction).is_some() {
+                    // one sample from the mixture of the material and the
+                    // lights, weighted by the density of the whole mixture
+                    if random::<f32>() < 0.5 {
+                        scattered_ray.direction = scene.lights.random(rec.p);
+                    }
+
+                    let direction = scattered_ray.direction;
+                    let material_pdf = rec.material.scattering_pdf(&r, &rec, direction).unwrap_or(0.0);
+                    if material_pdf <= 0.0 {
+                        return emitted;
+                    }
+
+                    let mixture_pdf = 0.5 * material_pdf + 0.5 * scene.lights.pdf_value(rec.p, direction);
+                    attenuation = attenuation * (material_pdf / mixture_pdf);
+                }
+
+                return emitted + color_ray(&scattered_ray, scene, depth + 1) * attenuation;
+            } else {
+                return emitted;
+            }
+        },
+        None => {
+                return volume_emitted;
+            }
+    }
+}
+
+pub fn render(scene: &Scene, camera: &Camera, width: usize, height: usize, samples: usize) -> Vec<RGB<u8>> {
+    let nx = width;
+    let ny = height;
+    let ns = samples;
+
+    let mut pixels: Vec<RGB<u8>> = vec![RGB {r: 0, g: 0, b: 0}; nx * ny];
+
+    pixels.par_iter_mut().enumerate().for_each(|(i, p)| {
+        let x = i % nx;
+        let y = ny - (i - x) / nx;
+
+        let mut col = Vec3::new(0.0, 0.0, 0.0);
+
+        for _s in 0..ns {
+            let mut rng = thread_rng();
+            let uniform = Uniform::new(0.0, 1.0);
+            let (r1, r2) = (uniform.sample(&mut rng), uniform.sample(&mut rng));
+
+            let u = (x as f32 + r1) / nx as f32;
+            let v = (y as f32 + r2) / ny as f32;
+            let r = camera.get_ray(u, v);
+            col += color_ray(&r, *&scene, 0);
+        }
+
+        col /= ns as f32;
+        col = Vec3::new( col.x.sqrt(), col.y.sqrt(), col.z.sqrt());
+
+        p.r = (255.99 * col.x) as u8;
+        p.g = (255.99 * col.y) as u8;
+        p.b = (255.99 * col.z) as u8;
+    });
+
+    return pixels;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Material, Diffuse, DiffuseLight, PassThrough};
+    use crate::texture::ConstantTexture;
+    use crate::quad::Quad;
+    use std::sync::Arc;
+
+    // a white floor under a square light
+    fn floor_and_light(light_material: Arc<Material>) -> Vec<Box<Hitable>> {
+        let white: Arc<Material> = Arc::new(Diffuse::new(Arc::new(ConstantTexture::new(Vec3::new(0.8, 0.8, 0.8)))));
+
+        vec![
+            Box::new(Quad::new(Vec3::new(-5.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 10.0), Vec3::new(10.0, 0.0, 0.0), white)),
+            Box::new(Quad::new(Vec3::new(-0.5, 1.0, -0.5), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0), light_material))
+        ]
+    }
+
+    fn mean_radiance(scene: &Scene, samples: usize) -> f32 {
+        let ray = Ray::new(Vec3::new(0.3, 2.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        (0..samples).map(|_| color_ray(&ray, scene, 0).x).sum::<f32>() / samples as f32
+    }
+
+    #[test]
+    fn light_sampling_does_not_change_the_estimate() {
+        let light: Arc<Material> = Arc::new(DiffuseLight::new(Arc::new(ConstantTexture::new(Vec3::new(4.0, 4.0, 4.0)))));
+        let unused: Arc<Material> = Arc::new(PassThrough::new(Vec3::zero()));
+
+        let mut world = floor_and_light(Arc::clone(&light));
+        let plain = mean_radiance(&Scene::new(&mut world, 5), 100000);
+
+        let mut world = floor_and_light(light);
+        let lights = floor_and_light(unused).split_off(1);
+        let sampled = mean_radiance(&Scene::new(&mut world, 5).with_lights(lights), 20000);
+
+        assert!((plain - sampled).abs() < 0.03 * plain, "{} vs {}", plain, sampled);
+    }
+}
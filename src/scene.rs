@@ -1,30 +1,39 @@
-use crate::bvh_node::BvhTree;
-use crate::hitable::Hitable;
-use crate::renderer;
-use crate::camera::Camera;
-
-use lodepng::RGB;
-
-pub trait Renderable {
-     fn render(&self, camera: &Camera, width: usize, height: usize, samples: usize) -> Vec<RGB<u8>>;
-}
-
-pub struct Scene<'a> {
-    pub bvh: BvhTree<'a>,
-    pub max_ray_depth: u32
-}
-
-impl<'a> Scene<'a> {
-    pub fn new(models: &'a mut Vec<Box<Hitable>>, max_ray_depth: u32) -> Self {
-        Scene {
-            bvh: BvhTree::new(models),
-            max_ray_depth
-        }
-    }
-}
-
-impl<'a> Renderable for Scene<'a> {
-    fn render(&self, camera: &Camera, width: usize, height: usize, samples: usize) -> Vec<RGB<u8>> {
-        renderer::render(self, camera, width, height, samples)
-    }
+use crate::bvh_node::BvhTree;
+use crate::hitable::{Hitable, HitableList};
+use crate::renderer;
+use crate::camera::Camera;
+
+use lodepng::RGB;
+
+pub trait Renderable {
+     fn render(&self, camera: &Camera, width: usize, height: usize, samples: usize) -> Vec<RGB<u8>>;
+}
+
+pub struct Scene<'a> {
+    pub bvh: BvhTree<'a>,
+    pub lights: HitableList,
+    pub max_ray_depth: u32
+}
+
+impl<'a> Scene<'a> {
+    pub fn new(models: &'a mut Vec<Box<Hitable>>, max_ray_depth: u32) -> Self {
+        Scene {
+            bvh: BvhTree::new(models),
+            lights: HitableList::new(),
+            max_ray_depth
+        }
+    }
+
+    // shapes that diffuse surfaces send half of their rays to, they are
+    // only sampled and not part of the scene
+    pub fn with_lights(mut self, lights: Vec<Box<Hitable>>) -> Self {
+        self.lights = HitableList::from_list(lights);
+        self
+    }
+}
+
+impl<'a> Renderable for Scene<'a> {
+    fn render(&self, camera: &Camera, width: usize, height: usize, samples: usize) -> Vec<RGB<u8>> {
+        renderer::render(self, camera, width, height, samples)
+    }
 }
\ No newline at end of file
@@ -0,0 +1,471 @@
+//! Signed distance functions and a sphere traced hitable built from them.
+//!
+//! Primitives are centered at the origin and get placed with `Translated`,
+//! operators wrap other distance functions. Distances are negative inside.
+//! The tracer steps along the ray by the distance to the closest surface,
+//! which is only safe as long as the function never overestimates. `Twist`
+//! breaks that for strong twists, `DistanceField::with_step_scale` trades
+//! speed for robustness in that case.
+
+use crate::hitable::Hitable;
+use crate::hitable::HitRecord;
+use crate::aabb::AABB;
+use crate::vec::Vec3;
+use crate::material::Material;
+use crate::ray::Ray;
+
+use std::sync::Arc;
+
+const MAX_STEPS: usize = 256;
+const SURFACE_EPSILON: f32 = 0.0001;
+const GRADIENT_EPSILON: f32 = 0.0001;
+
+pub trait Sdf: Send + Sync {
+    fn distance(&self, p: Vec3) -> f32;
+
+    // normalized gradient from the tetrahedron of central differences
+    fn normal(&self, p: Vec3) -> Vec3 {
+        let h = GRADIENT_EPSILON;
+        let k0 = Vec3::new(1.0, -1.0, -1.0);
+        let k1 = Vec3::new(-1.0, -1.0, 1.0);
+        let k2 = Vec3::new(-1.0, 1.0, -1.0);
+        let k3 = Vec3::new(1.0, 1.0, 1.0);
+
+        let gradient = k0 * self.distance(p + k0 * h)
+            + k1 * self.distance(p + k1 * h)
+            + k2 * self.distance(p + k2 * h)
+            + k3 * self.distance(p + k3 * h);
+
+        gradient.normalize()
+    }
+}
+
+pub struct SphereSdf {
+    radius: f32
+}
+
+impl SphereSdf {
+    pub fn new(radius: f32) -> Self {
+        SphereSdf { radius }
+    }
+}
+
+impl Sdf for SphereSdf {
+    fn distance(&self, p: Vec3) -> f32 {
+        p.len() - self.radius
+    }
+}
+
+pub struct BoxSdf {
+    half_size: Vec3
+}
+
+impl BoxSdf {
+    pub fn new(half_size: Vec3) -> Self {
+        BoxSdf { half_size }
+    }
+}
+
+impl Sdf for BoxSdf {
+    fn distance(&self, p: Vec3) -> f32 {
+        box_distance(p, self.half_size)
+    }
+}
+
+// box with edges rounded by the radius, the outer size stays the same
+pub struct RoundedBoxSdf {
+    half_size: Vec3,
+    radius: f32
+}
+
+impl RoundedBoxSdf {
+    pub fn new(half_size: Vec3, radius: f32) -> Self {
+        RoundedBoxSdf { half_size, radius }
+    }
+}
+
+impl Sdf for RoundedBoxSdf {
+    fn distance(&self, p: Vec3) -> f32 {
+        box_distance(p, self.half_size - self.radius) - self.radius
+    }
+}
+
+// torus in the xz plane around the y axis
+pub struct TorusSdf {
+    major_radius: f32,
+    minor_radius: f32
+}
+
+impl TorusSdf {
+    pub fn new(major_radius: f32, minor_radius: f32) -> Self {
+        TorusSdf { major_radius, minor_radius }
+    }
+}
+
+impl Sdf for TorusSdf {
+    fn distance(&self, p: Vec3) -> f32 {
+        let ring = (p.x * p.x + p.z * p.z).sqrt() - self.major_radius;
+        (ring * ring + p.y * p.y).sqrt() - self.minor_radius
+    }
+}
+
+// segment from a to b swept by a sphere
+pub struct CapsuleSdf {
+    a: Vec3,
+    b: Vec3,
+    radius: f32
+}
+
+impl CapsuleSdf {
+    pub fn new(a: Vec3, b: Vec3, radius: f32) -> Self {
+        CapsuleSdf { a, b, radius }
+    }
+}
+
+impl Sdf for CapsuleSdf {
+    fn distance(&self, p: Vec3) -> f32 {
+        let pa = p - self.a;
+        let ba = self.b - self.a;
+        let h = clamp(pa.dot(ba) / ba.dot(ba), 0.0, 1.0);
+
+        (pa - ba * h).len() - self.radius
+    }
+}
+
+pub struct Translated {
+    sdf: Arc<Sdf>,
+    offset: Vec3
+}
+
+impl Translated {
+    pub fn new(sdf: Arc<Sdf>, offset: Vec3) -> Self {
+        Translated { sdf, offset }
+    }
+}
+
+impl Sdf for Translated {
+    fn distance(&self, p: Vec3) -> f32 {
+        self.sdf.distance(p - self.offset)
+    }
+}
+
+// polynomial smooth minimum, k is the size of the blend region and zero
+// gives the plain union
+pub struct SmoothUnion {
+    a: Arc<Sdf>,
+    b: Arc<Sdf>,
+    k: f32
+}
+
+impl SmoothUnion {
+    pub fn new(a: Arc<Sdf>, b: Arc<Sdf>, k: f32) -> Self {
+        SmoothUnion { a, b, k }
+    }
+}
+
+impl Sdf for SmoothUnion {
+    fn distance(&self, p: Vec3) -> f32 {
+        let d1 = self.a.distance(p);
+        let d2 = self.b.distance(p);
+
+        if self.k <= 0.0 {
+            return d1.min(d2);
+        }
+
+        let h = clamp(0.5 + 0.5 * (d2 - d1) / self.k, 0.0, 1.0);
+        lerp(d2, d1, h) - self.k * h * (1.0 - h)
+    }
+}
+
+// base with carve removed, blended over k
+pub struct SmoothSubtraction {
+    base: Arc<Sdf>,
+    carve: Arc<Sdf>,
+    k: f32
+}
+
+impl SmoothSubtraction {
+    pub fn new(base: Arc<Sdf>, carve: Arc<Sdf>, k: f32) -> Self {
+        SmoothSubtraction { base, carve, k }
+    }
+}
+
+impl Sdf for SmoothSubtraction {
+    fn distance(&self, p: Vec3) -> f32 {
+        let d1 = self.carve.distance(p);
+        let d2 = self.base.distance(p);
+
+        if self.k <= 0.0 {
+            return d2.max(-d1);
+        }
+
+        let h = clamp(0.5 - 0.5 * (d2 + d1) / self.k, 0.0, 1.0);
+        lerp(d2, -d1, h) + self.k * h * (1.0 - h)
+    }
+}
+
+// infinite repetition with the given cell size, axes with a zero period
+// are left alone. Shapes have to fit into a cell.
+pub struct Repeat {
+    sdf: Arc<Sdf>,
+    period: Vec3
+}
+
+impl Repeat {
+    pub fn new(sdf: Arc<Sdf>, period: Vec3) -> Self {
+        Repeat { sdf, period }
+    }
+}
+
+impl Sdf for Repeat {
+    fn distance(&self, p: Vec3) -> f32 {
+        let q = Vec3::new(
+            repeat(p.x, self.period.x),
+            repeat(p.y, self.period.y),
+            repeat(p.z, self.period.z)
+        );
+
+        self.sdf.distance(q)
+    }
+}
+
+// rotation around the y axis growing with the height, in radians per unit
+pub struct Twist {
+    sdf: Arc<Sdf>,
+    rate: f32
+}
+
+impl Twist {
+    pub fn new(sdf: Arc<Sdf>, rate: f32) -> Self {
+        Twist { sdf, rate }
+    }
+}
+
+impl Sdf for Twist {
+    fn distance(&self, p: Vec3) -> f32 {
+        let (sin, cos) = (self.rate * p.y).sin_cos();
+        let q = Vec3::new(cos * p.x - sin * p.z, p.y, sin * p.x + cos * p.z);
+
+        self.sdf.distance(q)
+    }
+}
+
+// sphere traced surface of a distance function, the bounding box has to
+// enclose the surface and limits the marching
+pub struct DistanceField {
+    sdf: Arc<Sdf>,
+    bbox: AABB,
+    material: Arc<Material>,
+    step_scale: f32
+}
+
+impl DistanceField {
+    pub fn new(sdf: Arc<Sdf>, bbox: AABB, material: Arc<Material>) -> Self {
+        DistanceField { sdf, bbox, material, step_scale: 1.0 }
+    }
+
+    // fraction of the distance taken per step, below one for functions
+    // that overestimate
+    pub fn with_step_scale(mut self, step_scale: f32) -> Self {
+        self.step_scale = step_scale;
+        self
+    }
+}
+
+impl Hitable for DistanceField {
+    fn hit(&self, ray: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
+        let (t_min, t_max) = self.bbox.intersect(ray, t_range.start, t_range.end)?;
+        let length = ray.direction.len();
+
+        // the side is unknown while starting on the surface, e.g. after a
+        // bounce, then the ray has to leave it before a hit counts
+        let mut side = 0.0;
+        let mut t = t_min;
+
+        for _ in 0..MAX_STEPS {
+            let p = ray.point_at_parameter(t);
+            let distance = self.sdf.distance(p);
+
+            if side == 0.0 {
+                if distance.abs() > SURFACE_EPSILON {
+                    side = distance.signum();
+                }
+            } else if distance * side < SURFACE_EPSILON {
+                return Some(HitRecord::new(t, p, self.sdf.normal(p), &*self.material, 0.0, 0.0));
+            }
+
+            t += (distance.abs() * self.step_scale).max(SURFACE_EPSILON) / length;
+            if t > t_max {
+                break;
+            }
+        }
+
+        None
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(self.bbox)
+    }
+}
+
+fn box_distance(p: Vec3, half_size: Vec3) -> f32 {
+    let q = Vec3::new(p.x.abs() - half_size.x, p.y.abs() - half_size.y, p.z.abs() - half_size.z);
+    let outside = Vec3::new(q.x.max(0.0), q.y.max(0.0), q.z.max(0.0));
+
+    outside.len() + q.x.max(q.y.max(q.z)).min(0.0)
+}
+
+fn repeat(x: f32, period: f32) -> f32 {
+    if period <= 0.0 {
+        return x;
+    }
+
+    x - period * (x / period).round()
+}
+
+fn clamp(x: f32, min: f32, max: f32) -> f32 {
+    x.max(min).min(max)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::PassThrough;
+
+    fn unused() -> Arc<Material> {
+        Arc::new(PassThrough::new(Vec3::zero()))
+    }
+
+    fn field(sdf: Arc<Sdf>, extent: f32) -> DistanceField {
+        DistanceField::new(sdf, AABB::new(Vec3::new(-extent, -extent, -extent), Vec3::new(extent, extent, extent)), unused())
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn primitive_distances() {
+        let sphere = SphereSdf::new(1.0);
+        assert!(close(sphere.distance(Vec3::new(0.0, 3.0, 0.0)), 2.0));
+        assert!(close(sphere.distance(Vec3::zero()), -1.0));
+
+        let cube = BoxSdf::new(Vec3::new(1.0, 2.0, 3.0));
+        assert!(close(cube.distance(Vec3::new(2.0, 0.0, 0.0)), 1.0));
+        assert!(close(cube.distance(Vec3::new(4.0, 6.0, 0.0)), 5.0));
+        assert!(close(cube.distance(Vec3::new(0.5, 0.0, 0.0)), -0.5));
+
+        // rounding keeps the faces and pulls in the corners
+        let rounded = RoundedBoxSdf::new(Vec3::new(1.0, 1.0, 1.0), 0.25);
+        assert!(close(rounded.distance(Vec3::new(2.0, 0.0, 0.0)), 1.0));
+        assert!(close(rounded.distance(Vec3::new(1.0, 1.0, 1.0)), 0.25 * 3.0f32.sqrt() - 0.25));
+
+        let torus = TorusSdf::new(2.0, 0.5);
+        assert!(close(torus.distance(Vec3::new(2.0, 0.0, 0.0)), -0.5));
+        assert!(close(torus.distance(Vec3::new(0.0, 1.0, 2.0)), 0.5));
+        assert!(close(torus.distance(Vec3::zero()), 1.5));
+
+        let capsule = CapsuleSdf::new(Vec3::zero(), Vec3::new(0.0, 2.0, 0.0), 0.5);
+        assert!(close(capsule.distance(Vec3::new(1.0, 1.0, 0.0)), 0.5));
+        assert!(close(capsule.distance(Vec3::new(0.0, 3.0, 0.0)), 0.5));
+        assert!(close(capsule.distance(Vec3::new(0.0, -2.0, 0.0)), 1.5));
+    }
+
+    #[test]
+    fn operators() {
+        let a: Arc<Sdf> = Arc::new(SphereSdf::new(1.0));
+        let b: Arc<Sdf> = Arc::new(Translated::new(Arc::new(SphereSdf::new(1.0)), Vec3::new(1.5, 0.0, 0.0)));
+        let middle = Vec3::new(0.75, 1.0, 0.0);
+
+        let union = SmoothUnion::new(Arc::clone(&a), Arc::clone(&b), 0.0);
+        assert!(close(union.distance(middle), a.distance(middle).min(b.distance(middle))));
+
+        // blending only ever adds material, and none far from the seam
+        let blend = SmoothUnion::new(Arc::clone(&a), Arc::clone(&b), 0.5);
+        assert!(blend.distance(middle) < union.distance(middle) - 0.05);
+        assert!(close(blend.distance(Vec3::new(-3.0, 0.0, 0.0)), 2.0));
+
+        let carved = SmoothSubtraction::new(Arc::clone(&a), Arc::clone(&b), 0.0);
+        assert!(carved.distance(Vec3::new(0.9, 0.0, 0.0)) > 0.0);
+        assert!(close(carved.distance(Vec3::new(-0.5, 0.0, 0.0)), -0.5));
+
+        // near the rim of the cut the blend removes more
+        let rim = Vec3::new(0.75, 0.9, 0.0);
+        let smooth_carved = SmoothSubtraction::new(Arc::clone(&a), b, 0.5);
+        assert!(smooth_carved.distance(rim) > carved.distance(rim) + 0.01);
+
+        let repeated = Repeat::new(Arc::clone(&a), Vec3::new(4.0, 0.0, 4.0));
+        assert!(close(repeated.distance(Vec3::new(8.0, 0.0, -4.0)), -1.0));
+        assert!(close(repeated.distance(Vec3::new(2.0, 0.0, 0.0)), 1.0));
+        assert!(close(repeated.distance(Vec3::new(0.0, 4.0, 0.0)), 3.0));
+
+        // a twist leaves the axis alone and rotates the slices
+        let slab: Arc<Sdf> = Arc::new(BoxSdf::new(Vec3::new(2.0, 10.0, 0.5)));
+        let twisted = Twist::new(slab, std::f32::consts::PI / 2.0);
+        assert!(close(twisted.distance(Vec3::new(1.5, 0.0, 0.0)), -0.5));
+        assert!(twisted.distance(Vec3::new(1.5, 1.0, 0.0)) > 0.9);
+        assert!(twisted.distance(Vec3::new(0.0, 1.0, 1.5)) < 0.0);
+    }
+
+    #[test]
+    fn normals_follow_the_gradient() {
+        let sphere = SphereSdf::new(2.0);
+        let p = Vec3::new(1.0, -1.0, 2.0f32.sqrt());
+        assert!((sphere.normal(p) - p.normalize()).len() < 1e-3);
+
+        let cube = BoxSdf::new(Vec3::new(1.0, 1.0, 1.0));
+        assert!((cube.normal(Vec3::new(0.2, 1.0, -0.3)) - Vec3::new(0.0, 1.0, 0.0)).len() < 1e-3);
+    }
+
+    #[test]
+    fn sphere_tracing_matches_the_analytic_sphere() {
+        let sphere = field(Arc::new(SphereSdf::new(1.0)), 1.01);
+        let ray = Ray::new(Vec3::new(-3.0, 0.5, 0.0), Vec3::new(2.0, 0.0, 0.0), 0.0);
+        let record = sphere.hit(&ray, 0.001..std::f32::MAX).unwrap();
+
+        let entry = -(0.75f32).sqrt();
+        assert!((record.p.x - entry).abs() < 1e-3);
+        assert!((record.t - (entry + 3.0) / 2.0).abs() < 1e-3);
+        assert!((record.normal - Vec3::new(entry, 0.5, 0.0)).len() < 1e-3);
+
+        // leaving from the surface does not find it again, from inside the far side is hit
+        let bounce = Ray::new(record.p, Vec3::new(-1.0, 0.0, 0.0), 0.0);
+        assert!(sphere.hit(&bounce, 0.0..std::f32::MAX).is_none());
+
+        let inside = Ray::new(Vec3::zero(), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let record = sphere.hit(&inside, 0.001..std::f32::MAX).unwrap();
+        assert!((record.t - 1.0).abs() < 1e-3);
+
+        assert!(sphere.hit(&Ray::new(Vec3::new(-3.0, 1.5, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0), 0.001..std::f32::MAX).is_none());
+    }
+
+    // first sign change along the ray by tiny fixed steps
+    fn brute_force_hit(sdf: &Sdf, ray: &Ray, t_max: f32) -> Option<f32> {
+        let steps = 20000;
+        (0..steps)
+            .map(|i| t_max * i as f32 / steps as f32)
+            .find(|t| sdf.distance(ray.point_at_parameter(*t)) < 0.0)
+    }
+
+    #[test]
+    fn reduced_steps_find_the_first_crossing_of_strong_twists() {
+        let slab: Arc<Sdf> = Arc::new(BoxSdf::new(Vec3::new(1.0, 2.0, 0.2)));
+        let twisted: Arc<Sdf> = Arc::new(Twist::new(slab, 4.0));
+        let traced = field(Arc::clone(&twisted), 2.1).with_step_scale(0.5);
+
+        for i in 0..40 {
+            let ray = Ray::new(Vec3::new(0.3, -1.9 + 3.8 * i as f32 / 39.0, -3.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+            let expected = brute_force_hit(&*twisted, &ray, 6.0);
+            let t = traced.hit(&ray, 0.001..std::f32::MAX).map(|record| record.t);
+
+            assert_eq!(t.is_some(), expected.is_some());
+            if let (Some(t), Some(expected)) = (t, expected) {
+                assert!((t - expected).abs() < 1e-3, "{} vs {}", t, expected);
+            }
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use crate::vec::Vec3;
+
+use lazy_static::lazy_static;
+
+pub const LAMBDA_MIN: f32 = 380.0;
+pub const LAMBDA_MAX: f32 = 780.0;
+
+// lm/W at the peak of the photopic response
+pub const LUMINOUS_EFFICACY: f32 = 683.0;
+
+const LAMBDA_STEP: f32 = 5.0;
+const BLACKBODY_TABLE_STEP: f32 = 100.0;
+const BLACKBODY_TABLE_MAX: f32 = 20000.0;
+
+lazy_static! {
+    static ref BLACKBODY_TABLE: Vec<Vec3> = generate_blackbody_table();
+    static ref WAVELENGTH_RGB_MEAN: Vec3 = mean_wavelength_rgb();
+}
+
+// index of refraction as a function of wavelength, the coefficients use
+// wavelengths in micrometers as is common in glass catalogues
+#[derive(Clone, Copy)]
+pub enum Dispersion {
+    Cauchy { a: f32, b: f32 },
+    Sellmeier { b: [f32; 3], c: [f32; 3] }
+}
+
+impl Dispersion {
+    pub fn bk7() -> Self {
+        Dispersion::Sellmeier {
+            b: [1.039_612_1, 0.231_792_34, 1.010_469_5],
+            c: [0.006_000_699, 0.020_017_914, 103.560_65]
+        }
+    }
+
+    pub fn fused_silica() -> Self {
+        Dispersion::Sellmeier {
+            b: [0.696_166_3, 0.407_942_6, 0.897_479_4],
+            c: [0.004_679_148, 0.013_512_063, 97.934_0]
+        }
+    }
+
+    pub fn dense_flint() -> Self {
+        Dispersion::Sellmeier {
+            b: [1.737_597, 0.313_747_35, 1.898_781],
+            c: [0.013_188_707, 0.062_306_814, 155.236_29]
+        }
+    }
+
+    pub fn diamond() -> Self {
+        Dispersion::Sellmeier {
+            b: [0.3306, 4.3356, 0.0],
+            c: [0.030_625, 0.011_236, 0.0]
+        }
+    }
+
+    pub fn ior(&self, lambda: f32) -> f32 {
+        let l = lambda / 1000.0;
+        let l2 = l * l;
+
+        match *self {
+            Dispersion::Cauchy { a, b } => a + b / l2,
+            Dispersion::Sellmeier { b, c } => {
+                let n2 = 1.0
+                    + b[0] * l2 / (l2 - c[0])
+                    + b[1] * l2 / (l2 - c[1])
+                    + b[2] * l2 / (l2 - c[2]);
+                n2.sqrt()
+            }
+        }
+    }
+}
+
+// multi-lobe gaussian fit of the CIE 1931 2 degree matching functions
+// (Wyman, Sloan and Shirley 2013)
+pub fn cie_xyz(lambda: f32) -> Vec3 {
+    let x = 1.056 * gaussian(lambda, 599.8, 37.9, 31.0)
+        + 0.362 * gaussian(lambda, 442.0, 16.0, 26.7)
+        - 0.065 * gaussian(lambda, 501.1, 20.4, 26.2);
+    let y = 0.821 * gaussian(lambda, 568.8, 46.9, 40.5)
+        + 0.286 * gaussian(lambda, 530.9, 16.3, 31.1);
+    let z = 1.217 * gaussian(lambda, 437.0, 11.8, 36.0)
+        + 0.681 * gaussian(lambda, 459.0, 26.0, 13.8);
+
+    Vec3::new(x, y, z)
+}
+
+// linear sRGB with D65 white point
+pub fn xyz_to_rgb(xyz: Vec3) -> Vec3 {
+    Vec3::new(
+        3.2406 * xyz.x - 1.5372 * xyz.y - 0.4986 * xyz.z,
+        -0.9689 * xyz.x + 1.8758 * xyz.y + 0.0415 * xyz.z,
+        0.0557 * xyz.x - 0.2040 * xyz.y + 1.0570 * xyz.z
+    )
+}
+
+pub fn luminance(rgb: Vec3) -> f32 {
+    0.2126 * rgb.x + 0.7152 * rgb.y + 0.0722 * rgb.z
+}
+
+// spectral radiance of a blackbody in W / (sr m^2 nm)
+pub fn planck(lambda: f32, kelvin: f32) -> f32 {
+    const H: f64 = 6.626_070_15e-34;
+    const C: f64 = 299_792_458.0;
+    const KB: f64 = 1.380_649e-23;
+
+    if kelvin <= 0.0 {
+        return 0.0;
+    }
+
+    let l = lambda as f64 * 1e-9;
+    let radiance = (2.0 * H * C * C) / (l.powi(5) * ((H * C / (l * KB * kelvin as f64)).exp() - 1.0));
+
+    (radiance * 1e-9) as f32
+}
+
+// linear sRGB radiance of a blackbody, scaled so that a 6500K blackbody has
+// a luminance of one
+pub fn blackbody_rgb(kelvin: f32) -> Vec3 {
+    if kelvin <= 0.0 {
+        return Vec3::zero();
+    }
+
+    let x = kelvin.min(BLACKBODY_TABLE_MAX) / BLACKBODY_TABLE_STEP;
+    let i = (x as usize).min(BLACKBODY_TABLE.len() - 2);
+    let f = x - i as f32;
+
+    BLACKBODY_TABLE[i] * (1.0 - f) + BLACKBODY_TABLE[i + 1] * f
+}
+
+// blackbody chromaticity with a luminance of one
+pub fn blackbody_color(kelvin: f32) -> Vec3 {
+    let rgb = blackbody_rgb(kelvin);
+    let y = luminance(rgb);
+
+    if y <= 0.0 {
+        return Vec3::zero();
+    }
+
+    rgb / y
+}
+
+// the rgb contribution of a single wavelength, normalized so that averaging
+// it over the visible range gives white
+pub fn wavelength_to_rgb(lambda: f32) -> Vec3 {
+    let rgb = xyz_to_rgb(cie_xyz(lambda));
+    Vec3::new(rgb.x.max(0.0), rgb.y.max(0.0), rgb.z.max(0.0)) / *WAVELENGTH_RGB_MEAN
+}
+
+// uniformly samples a visible wavelength, returns it with the weight that
+// converts a monochromatic path back to rgb
+pub fn sample_wavelength(u: f32) -> (f32, Vec3) {
+    let lambda = LAMBDA_MIN + u * (LAMBDA_MAX - LAMBDA_MIN);
+    (lambda, wavelength_to_rgb(lambda))
+}
+
+fn mean_wavelength_rgb() -> Vec3 {
+    let mut sum = Vec3::zero();
+    let mut samples = 0;
+    let mut lambda = LAMBDA_MIN;
+
+    while lambda <= LAMBDA_MAX {
+        let rgb = xyz_to_rgb(cie_xyz(lambda));
+        sum += Vec3::new(rgb.x.max(0.0), rgb.y.max(0.0), rgb.z.max(0.0));
+        samples += 1;
+        lambda += 1.0;
+    }
+
+    sum / samples as f32
+}
+
+fn gaussian(lambda: f32, mu: f32, sigma_low: f32, sigma_high: f32) -> f32 {
+    let sigma = if lambda < mu { sigma_low } else { sigma_high };
+    let t = (lambda - mu) / sigma;
+
+    (-0.5 * t * t).exp()
+}
+
+fn integrate_blackbody(kelvin: f32) -> Vec3 {
+    let mut xyz = Vec3::zero();
+    let mut lambda = LAMBDA_MIN;
+
+    while lambda <= LAMBDA_MAX {
+        xyz += cie_xyz(lambda) * (planck(lambda, kelvin) * LAMBDA_STEP);
+        lambda += LAMBDA_STEP;
+    }
+
+    let rgb = xyz_to_rgb(xyz);
+    Vec3::new(rgb.x.max(0.0), rgb.y.max(0.0), rgb.z.max(0.0))
+}
+
+fn generate_blackbody_table() -> Vec<Vec3> {
+    let reference = luminance(integrate_blackbody(6500.0));
+    let entries = (BLACKBODY_TABLE_MAX / BLACKBODY_TABLE_STEP) as usize + 1;
+
+    (0..entries)
+        .map(|i| integrate_blackbody(i as f32 * BLACKBODY_TABLE_STEP) / reference)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn planck_peaks_at_wien_wavelength() {
+        let peak = (300..1000).max_by(|&a, &b| {
+            planck(a as f32, 5000.0).partial_cmp(&planck(b as f32, 5000.0)).unwrap()
+        }).unwrap();
+
+        // Wien's displacement law, 2.898e6 nm K / 5000 K
+        assert!((peak as f32 - 579.6).abs() < 2.0);
+    }
+
+    #[test]
+    fn blackbody_is_white_at_6500_kelvin() {
+        let rgb = blackbody_rgb(6500.0);
+
+        assert!((luminance(rgb) - 1.0).abs() < 1e-3);
+        assert!(rgb.x > 0.9 && rgb.x < 1.1 && rgb.y > 0.9 && rgb.y < 1.1 && rgb.z > 0.9 && rgb.z < 1.1);
+    }
+
+    #[test]
+    fn blackbody_reddens_and_dims_when_cooling() {
+        let warm = blackbody_rgb(2500.0);
+        let hot = blackbody_rgb(3000.0);
+
+        assert!(warm.x > warm.y && warm.y > warm.z);
+        assert!(luminance(warm) < luminance(hot));
+        assert_eq!(blackbody_rgb(0.0), Vec3::zero());
+        assert!((luminance(blackbody_color(2500.0)) - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn glass_catalogue_indices() {
+        // catalogue values at the helium d line
+        assert!((Dispersion::bk7().ior(587.6) - 1.5168).abs() < 1e-3);
+        assert!((Dispersion::fused_silica().ior(587.6) - 1.4585).abs() < 1e-3);
+        assert!((Dispersion::diamond().ior(587.6) - 2.417).abs() < 5e-3);
+        assert!((Dispersion::Cauchy { a: 1.5, b: 0.005 }.ior(500.0) - 1.52).abs() < 1e-6);
+
+        // normal dispersion, blue light is refracted more than red
+        for dispersion in &[Dispersion::bk7(), Dispersion::fused_silica(), Dispersion::dense_flint(), Dispersion::diamond()] {
+            assert!(dispersion.ior(450.0) > dispersion.ior(550.0));
+            assert!(dispersion.ior(550.0) > dispersion.ior(650.0));
+        }
+    }
+
+    #[test]
+    fn sampled_wavelengths_average_to_white() {
+        let n = 4000;
+        let mut sum = Vec3::zero();
+
+        for i in 0..n {
+            let (lambda, rgb) = sample_wavelength((i as f32 + 0.5) / n as f32);
+            assert!(lambda >= LAMBDA_MIN && lambda <= LAMBDA_MAX);
+            assert!(rgb.x >= 0.0 && rgb.y >= 0.0 && rgb.z >= 0.0);
+            sum += rgb;
+        }
+
+        let mean = sum / n as f32;
+        assert!((mean - Vec3::new(1.0, 1.0, 1.0)).len() < 0.01);
+        assert!(wavelength_to_rgb(450.0).z > wavelength_to_rgb(450.0).x);
+        assert!(wavelength_to_rgb(650.0).x > wavelength_to_rgb(650.0).z);
+    }
+}
@@ -1,89 +1,143 @@
-use crate::ray::Ray;
-use crate::vec::Vec3;
-use crate::hitable::Hitable;
-use crate::hitable::HitRecord;
-use crate::material::Material;
-use crate::aabb::AABB;
-
-use rand::random;
-use std::sync::Arc;
-
-pub fn random_in_unit_sphere() -> Vec3 {
-    let mut p: Vec3;
-    while {
-         p = Vec3::new(random::<f32>(), random::<f32>(), random::<f32>()) * 2.0 - Vec3::new(1.0, 1.0, 1.0);
-         p.len_squared() >= 1.0
-    } {}
-
-    return p;
-}
-
-pub struct Sphere {
-    radius: f32,
-    center0: Vec3,
-    center1: Vec3,
-    time0: f32,
-    time1: f32,
-    material: Arc<Material>
-}
-
-impl Sphere {
-    pub fn new(orig: Vec3, rad: f32, material: Arc<Material>) -> Self {
-        Sphere { radius: rad, center0: orig , center1: orig, time0: 0.0, time1: 1.0, material }
-    }
-
-    pub fn new_moving_sphere(center0: Vec3, center1: Vec3, time0: f32, time1: f32, radius: f32, material: Arc<Material>) -> Self {
-        Sphere { center0, center1, time0, time1, radius, material }
-    }
-
-    pub fn center(&self, time: f32) -> Vec3 {
-         return self.center0 + (self.center1 - self.center0) * ((time - self.time0) / (self.time1 - self.time0));
-    }
-
-    pub fn get_sphere_uv(p: Vec3) -> (f32,f32) {
-        let phi = p.z.atan2(p.x);
-        let theta = p.y.asin();
-        let u = 1.0 - (phi + std::f32::consts::PI) / (2.0 * std::f32::consts::PI);
-        let v = (theta + std::f32::consts::PI / 2.0) / std::f32::consts::PI;
-
-        (u, v)
-    }
-}
-
-impl<'a> Hitable for Sphere {
-    fn hit(&self, r: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
-        let oc = r.origin - self.center(r.time);
-        let a = r.direction.dot(r.direction);
-        let b = oc.dot(r.direction);
-        let c = oc.dot(oc) - self.radius * self.radius;
-
-        let discriminant = b * b - a * c;
-
-        if discriminant > 0.0 {
-            let t = (-b - (b * b - a * c).sqrt()) / a;
-
-            if t < t_range.end && t > t_range.start {
-                let p = r.point_at_parameter(t);
-                let normal = (p - self.center(r.time)) / self.radius;
-                let uv = Sphere::get_sphere_uv( (p - self.center(r.time)) / self.radius );
-                return Some(HitRecord::new(t, p, normal, &*self.material, uv.0, uv.1));
-            }
-
-            let t = (-b + (b * b - a * c).sqrt()) / a;
-            if t < t_range.end && t > t_range.start {
-                let p = r.point_at_parameter(t);
-                let normal = (p - self.center(r.time)) / self.radius;
-                let uv = Sphere::get_sphere_uv( (p - self.center(r.time)) / self.radius );
-                return Some(HitRecord::new(t, p, normal, &*self.material, uv.0, uv.1));
-            }
-        }
-
-        return None;
-    }
-
-    fn bounding_box(&self) -> Option<AABB> {
-        let box0 = AABB::new(self.center0 - Vec3::new(self.radius, self.radius, self.radius), self.center0 + Vec3::new(self.radius, self.radius, self.radius));
-        let box1 = AABB::new(self.center1 - Vec3::new(self.radius, self.radius, self.radius), self.center1 + Vec3::new(self.radius, self.radius, self.radius));
-        return Some(AABB::surrounding_box(&box0, &box1));
-    }
-}
\ No newline at end of file
+use crate::ray::Ray;
+use crate::vec::Vec3;
+use crate::hitable::Hitable;
+use crate::hitable::HitRecord;
+use crate::hitable::Area;
+use crate::material::Material;
+use crate::aabb::AABB;
+
+use rand::random;
+use std::sync::Arc;
+
+pub fn random_in_unit_sphere() -> Vec3 {
+    let mut p: Vec3;
+    while {
+         p = Vec3::new(random::<f32>(), random::<f32>(), random::<f32>()) * 2.0 - Vec3::new(1.0, 1.0, 1.0);
+         p.len_squared() >= 1.0
+    } {}
+
+    return p;
+}
+
+pub struct Sphere {
+    radius: f32,
+    center0: Vec3,
+    center1: Vec3,
+    time0: f32,
+    time1: f32,
+    material: Arc<Material>
+}
+
+impl Sphere {
+    pub fn new(orig: Vec3, rad: f32, material: Arc<Material>) -> Self {
+        Sphere { radius: rad, center0: orig , center1: orig, time0: 0.0, time1: 1.0, material }
+    }
+
+    pub fn new_moving_sphere(center0: Vec3, center1: Vec3, time0: f32, time1: f32, radius: f32, material: Arc<Material>) -> Self {
+        Sphere { center0, center1, time0, time1, radius, material }
+    }
+
+    pub fn center(&self, time: f32) -> Vec3 {
+         return self.center0 + (self.center1 - self.center0) * ((time - self.time0) / (self.time1 - self.time0));
+    }
+
+    pub fn get_sphere_uv(p: Vec3) -> (f32,f32) {
+        let phi = p.z.atan2(p.x);
+        let theta = p.y.asin();
+        let u = 1.0 - (phi + std::f32::consts::PI) / (2.0 * std::f32::consts::PI);
+        let v = (theta + std::f32::consts::PI / 2.0) / std::f32::consts::PI;
+
+        (u, v)
+    }
+
+    // derivatives of the position by the uv of get_sphere_uv at the unit
+    // normal n, clamped at the poles where u degenerates
+    pub fn get_sphere_frame(n: Vec3, radius: f32) -> (Vec3, Vec3) {
+        let pi = std::f32::consts::PI;
+        let cos_theta = (n.x * n.x + n.z * n.z).sqrt().max(1e-6);
+
+        let dpdu = Vec3::new(n.z, 0.0, -n.x) * (2.0 * pi * radius);
+        let dpdv = Vec3::new(-n.y * n.x / cos_theta, cos_theta, -n.y * n.z / cos_theta) * (pi * radius);
+
+        (dpdu, dpdv)
+    }
+}
+
+impl Area for Sphere {
+    fn area(&self) -> f32 {
+        4.0 * std::f32::consts::PI * self.radius * self.radius
+    }
+}
+
+impl<'a> Hitable for Sphere {
+    fn hit(&self, r: &Ray, t_range: ::std::ops::Range<f32>) -> Option<HitRecord> {
+        let oc = r.origin - self.center(r.time);
+        let a = r.direction.dot(r.direction);
+        let b = oc.dot(r.direction);
+        let c = oc.dot(oc) - self.radius * self.radius;
+
+        let discriminant = b * b - a * c;
+
+        if discriminant > 0.0 {
+            let t = (-b - (b * b - a * c).sqrt()) / a;
+
+            if t < t_range.end && t > t_range.start {
+                let p = r.point_at_parameter(t);
+                let normal = (p - self.center(r.time)) / self.radius;
+                let uv = Sphere::get_sphere_uv( (p - self.center(r.time)) / self.radius );
+                let (dpdu, dpdv) = Sphere::get_sphere_frame(normal, self.radius);
+                return Some(HitRecord::new(t, p, normal, &*self.material, uv.0, uv.1).with_frame(dpdu, dpdv));
+            }
+
+            let t = (-b + (b * b - a * c).sqrt()) / a;
+            if t < t_range.end && t > t_range.start {
+                let p = r.point_at_parameter(t);
+                let normal = (p - self.center(r.time)) / self.radius;
+                let uv = Sphere::get_sphere_uv( (p - self.center(r.time)) / self.radius );
+                let (dpdu, dpdv) = Sphere::get_sphere_frame(normal, self.radius);
+                return Some(HitRecord::new(t, p, normal, &*self.material, uv.0, uv.1).with_frame(dpdu, dpdv));
+            }
+        }
+
+        return None;
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        let box0 = AABB::new(self.center0 - Vec3::new(self.radius, self.radius, self.radius), self.center0 + Vec3::new(self.radius, self.radius, self.radius));
+        let box1 = AABB::new(self.center1 - Vec3::new(self.radius, self.radius, self.radius), self.center1 + Vec3::new(self.radius, self.radius, self.radius));
+        return Some(AABB::surrounding_box(&box0, &box1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::PassThrough;
+
+    #[test]
+    fn frame_is_the_derivative_of_the_position_by_uv() {
+        let center = Vec3::new(1.0, -2.0, 3.0);
+        let sphere = Sphere::new(center, 2.0, Arc::new(PassThrough::new(Vec3::zero())));
+        let eps = 1e-3;
+
+        // uv after a step along a derivative, projected back onto the sphere
+        let uv_after = |p: Vec3, step: Vec3| Sphere::get_sphere_uv((p + step * eps - center).normalize());
+
+        for &direction in [Vec3::new(1.0, 0.3, 0.5), Vec3::new(0.2, -0.6, 1.0), Vec3::new(-0.7, 0.4, 0.6), Vec3::new(0.5, 0.8, -0.4)].iter() {
+            let direction = direction.normalize();
+            let ray = Ray::new(center + direction * 10.0, -direction, 0.0);
+            let record = sphere.hit(&ray, 0.001..std::f32::MAX).unwrap();
+            let (dpdu, dpdv) = (record.tangent.unwrap(), record.bitangent.unwrap());
+
+            assert!(dpdu.dot(record.normal).abs() < 1e-4 && dpdv.dot(record.normal).abs() < 1e-4);
+
+            let (u, v) = uv_after(record.p, dpdu);
+            assert!(((u - record.u) / eps - 1.0).abs() < 0.01, "{:?}", direction);
+            assert!(((v - record.v) / eps).abs() < 0.01, "{:?}", direction);
+
+            let (u, v) = uv_after(record.p, dpdv);
+            assert!(((u - record.u) / eps).abs() < 0.01, "{:?}", direction);
+            assert!(((v - record.v) / eps - 1.0).abs() < 0.01, "{:?}", direction);
+        }
+    }
+}
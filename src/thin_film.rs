@@ -0,0 +1,287 @@
+use crate::ray::Ray;
+use crate::vec::Vec3;
+use crate::hitable::HitRecord;
+use crate::material::{Material, Scatter};
+use crate::texture::Texture;
+use crate::onb::Onb;
+use crate::microfacet::Ggx;
+use crate::math;
+use crate::spectrum;
+
+use std::f32::consts::PI;
+use std::ops::{Add, Sub, Mul, Div};
+use std::sync::Arc;
+use rand::random;
+
+const SPECTRAL_SAMPLES: usize = 32;
+
+// representative wavelengths of the rgb channels, used to spread rgb
+// conductor constants over the spectrum
+const CHANNEL_WAVELENGTHS: [f32; 3] = [630.0, 532.0, 465.0];
+
+#[derive(Clone, Copy)]
+pub enum FilmBase {
+    Dielectric(f32),
+    Conductor { eta: Vec3, k: Vec3 }
+}
+
+// a thin transparent film on top of a dielectric or conductor, such as soap
+// bubbles (a film over a base with index 1.0) or oil on metal. The film
+// thickness in nanometers is mapped from the texture onto [min, max].
+pub struct ThinFilm {
+    base: FilmBase,
+    film_ior: f32,
+    thickness: Arc<Texture>,
+    thickness_min: f32,
+    thickness_max: f32,
+    roughness: f32
+}
+
+impl ThinFilm {
+    pub fn new(base: FilmBase, film_ior: f32, thickness: Arc<Texture>, thickness_min: f32, thickness_max: f32) -> Self {
+        ThinFilm { base, film_ior, thickness, thickness_min, thickness_max, roughness: 0.0 }
+    }
+
+    pub fn soap_bubble(thickness: Arc<Texture>) -> Self {
+        ThinFilm::new(FilmBase::Dielectric(1.0), 1.33, thickness, 250.0, 850.0)
+    }
+
+    pub fn with_roughness(mut self, roughness: f32) -> Self {
+        self.roughness = roughness;
+        self
+    }
+
+    fn reflectance_rgb(&self, cos_i: f32, thickness: f32) -> Vec3 {
+        let mut rgb = Vec3::zero();
+
+        for i in 0..SPECTRAL_SAMPLES {
+            let lambda = spectrum::LAMBDA_MIN
+                + (i as f32 + 0.5) / SPECTRAL_SAMPLES as f32 * (spectrum::LAMBDA_MAX - spectrum::LAMBDA_MIN);
+            let base_ior = match self.base {
+                FilmBase::Dielectric(ior) => Complex::new(ior, 0.0),
+                FilmBase::Conductor { eta, k } => Complex::new(channel_lerp(eta, lambda), channel_lerp(k, lambda))
+            };
+
+            rgb += spectrum::wavelength_to_rgb(lambda) * reflectance(cos_i, self.film_ior, thickness, base_ior, lambda);
+        }
+
+        let rgb = rgb / SPECTRAL_SAMPLES as f32;
+        Vec3::new(rgb.x.max(0.0).min(1.0), rgb.y.max(0.0).min(1.0), rgb.z.max(0.0).min(1.0))
+    }
+}
+
+impl Material for ThinFilm {
+    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Scatter {
+        let inside = ray.direction.dot(record.normal) > 0.0;
+        let frame = Onb::from_w(if inside { -record.shading_normal } else { record.shading_normal });
+        let wo = frame.to_local(-ray.direction.normalize());
+        let ggx = Ggx::from_roughness(self.roughness, 0.0);
+        let wm = ggx.sample_visible_normal(wo, random::<f32>(), random::<f32>());
+        let cos_o = wo.dot(wm);
+
+        let reflected = math::reflect(-wo, wm);
+        let shadowing = ggx.g2(wo, reflected) / ggx.g1(wo);
+
+        let base_ior = match self.base {
+            FilmBase::Dielectric(ior) => ior,
+            FilmBase::Conductor { .. } => {
+                if inside || reflected.z <= 0.0 {
+                    return Scatter::new(Vec3::zero(), None);
+                }
+
+                let t = self.thickness.scalar(record.u, record.v, &record.p);
+                let thickness = self.thickness_min + (self.thickness_max - self.thickness_min) * t;
+                let attenuation = self.reflectance_rgb(cos_o, thickness) * shadowing;
+                return Scatter::new(attenuation, Some(Ray::new(record.p, frame.local(reflected), ray.time)));
+            }
+        };
+
+        // the film sits on the outside, leaving the base is a plain interface
+        let (reflectance, eta) = if inside {
+            let r = math::fresnel_dielectric(cos_o, 1.0 / base_ior);
+            (Vec3::new(r, r, r), 1.0 / base_ior)
+        } else {
+            let t = self.thickness.scalar(record.u, record.v, &record.p);
+            let thickness = self.thickness_min + (self.thickness_max - self.thickness_min) * t;
+            (self.reflectance_rgb(cos_o, thickness), base_ior)
+        };
+
+        let reflect_prob = (reflectance.x + reflectance.y + reflectance.z) / 3.0;
+
+        if random::<f32>() < reflect_prob {
+            if reflected.z <= 0.0 {
+                return Scatter::new(Vec3::zero(), None);
+            }
+            let attenuation = reflectance * (shadowing / reflect_prob);
+            return Scatter::new(attenuation, Some(Ray::new(record.p, frame.local(reflected), ray.time)));
+        }
+
+        match math::refract(-wo, wm, 1.0 / eta) {
+            Some(refracted) if refracted.z < 0.0 => {
+                let transmitted = (Vec3::new(1.0, 1.0, 1.0) - reflectance) / (1.0 - reflect_prob);
+                let attenuation = transmitted * (ggx.g2(wo, refracted) / ggx.g1(wo));
+                Scatter::new(attenuation, Some(Ray::new(record.p, frame.local(refracted), ray.time)))
+            },
+            _ => Scatter::new(Vec3::zero(), None)
+        }
+    }
+}
+
+// Airy summation over the multiple reflections inside a film on a substrate,
+// averaged over s and p polarization. The incident medium is air.
+pub fn reflectance(cos_i: f32, film_ior: f32, thickness: f32, base_ior: Complex, lambda: f32) -> f32 {
+    let n1 = Complex::new(1.0, 0.0);
+    let n2 = Complex::new(film_ior, 0.0);
+    let n3 = base_ior;
+
+    let cos1 = Complex::new(cos_i, 0.0);
+    let sin2_1 = Complex::new(1.0 - cos_i * cos_i, 0.0);
+    let one = Complex::new(1.0, 0.0);
+    let cos2 = (one - sin2_1 / (n2 * n2)).sqrt();
+    let cos3 = (one - sin2_1 / (n3 * n3)).sqrt();
+
+    let r12_s = (n1 * cos1 - n2 * cos2) / (n1 * cos1 + n2 * cos2);
+    let r23_s = (n2 * cos2 - n3 * cos3) / (n2 * cos2 + n3 * cos3);
+    let r12_p = (n2 * cos1 - n1 * cos2) / (n2 * cos1 + n1 * cos2);
+    let r23_p = (n3 * cos2 - n2 * cos3) / (n3 * cos2 + n2 * cos3);
+
+    // phase difference of one round trip through the film
+    let delta = n2 * cos2 * Complex::new(4.0 * PI * thickness / lambda, 0.0);
+    let phase = (Complex::new(0.0, 1.0) * delta).exp();
+
+    let r_s = (r12_s + r23_s * phase) / (one + r12_s * r23_s * phase);
+    let r_p = (r12_p + r23_p * phase) / (one + r12_p * r23_p * phase);
+
+    (0.5 * (r_s.norm_squared() + r_p.norm_squared())).min(1.0)
+}
+
+fn channel_lerp(values: Vec3, lambda: f32) -> f32 {
+    let [r, g, b] = CHANNEL_WAVELENGTHS;
+
+    if lambda >= r {
+        values.x
+    } else if lambda >= g {
+        values.y + (values.x - values.y) * (lambda - g) / (r - g)
+    } else if lambda >= b {
+        values.z + (values.y - values.z) * (lambda - b) / (g - b)
+    } else {
+        values.z
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Complex {
+    pub re: f32,
+    pub im: f32
+}
+
+impl Complex {
+    pub fn new(re: f32, im: f32) -> Self {
+        Complex { re, im }
+    }
+
+    pub fn norm_squared(&self) -> f32 {
+        self.re * self.re + self.im * self.im
+    }
+
+    // principal square root
+    pub fn sqrt(&self) -> Complex {
+        let norm = self.norm_squared().sqrt();
+        let re = (0.5 * (norm + self.re)).max(0.0).sqrt();
+        let im = (0.5 * (norm - self.re)).max(0.0).sqrt();
+
+        Complex::new(re, if self.im < 0.0 { -im } else { im })
+    }
+
+    pub fn exp(&self) -> Complex {
+        let scale = self.re.exp();
+        Complex::new(scale * self.im.cos(), scale * self.im.sin())
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+
+    fn add(self, other: Complex) -> Complex {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, other: Complex) -> Complex {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+
+    fn div(self, other: Complex) -> Complex {
+        let denominator = other.norm_squared();
+        Complex::new(
+            (self.re * other.re + self.im * other.im) / denominator,
+            (self.im * other.re - self.re * other.im) / denominator
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::ConstantTexture;
+
+    #[test]
+    fn vanishing_film_leaves_the_bare_interface() {
+        for &cos_i in &[1.0, 0.7, 0.3] {
+            let film = reflectance(cos_i, 1.33, 0.0, Complex::new(1.5, 0.0), 550.0);
+            assert!((film - math::fresnel_dielectric(cos_i, 1.5)).abs() < 1e-4);
+
+            // a film matching the air is invisible at any thickness
+            let metal = reflectance(cos_i, 1.0, 400.0, Complex::new(0.2, 3.9), 550.0);
+            assert!((metal - math::fresnel_conductor(cos_i, 0.2, 3.9)).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn quarter_wave_film_cancels_the_reflection() {
+        let lambda = 550.0;
+        let film_ior = 1.5f32.sqrt();
+        let quarter = reflectance(1.0, film_ior, lambda / (4.0 * film_ior), Complex::new(1.5, 0.0), lambda);
+        let half = reflectance(1.0, film_ior, lambda / (2.0 * film_ior), Complex::new(1.5, 0.0), lambda);
+
+        assert!(quarter < 1e-4);
+        assert!((half - math::fresnel_dielectric(1.0, 1.5)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn soap_bubble_colors_change_with_thickness() {
+        let bubble = ThinFilm::soap_bubble(Arc::new(ConstantTexture::new(Vec3::zero())));
+        let thin = bubble.reflectance_rgb(1.0, 300.0);
+        let thick = bubble.reflectance_rgb(1.0, 500.0);
+
+        assert!((thin - thick).len() > 0.02);
+        assert!(thin.x >= 0.0 && thin.x <= 1.0 && thick.z >= 0.0 && thick.z <= 1.0);
+    }
+
+    #[test]
+    fn complex_square_root_takes_the_principal_branch() {
+        let root = Complex::new(-4.0, 0.0).sqrt();
+        assert!(root.re.abs() < 1e-6 && (root.im - 2.0).abs() < 1e-6);
+
+        let root = Complex::new(3.0, -4.0).sqrt();
+        assert!((root.re - 2.0).abs() < 1e-5 && (root.im + 1.0).abs() < 1e-5);
+    }
+}
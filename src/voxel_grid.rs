@@ -0,0 +1,447 @@
+//! Voxel grids holding scalar volume data (density, temperature, ...).
+//!
+//! Grids are stored with x varying fastest, then y, then z, and map their
+//! voxels onto a world space bounding box. Voxel values sit at the voxel
+//! centers and are trilinearly interpolated in between; lookups outside the
+//! bounds return zero.
+//!
+//! Two file formats are supported:
+//!
+//! * raw: nothing but `nx * ny * nz` little-endian `f32` values. Resolution
+//!   and bounds have to be supplied by the caller.
+//! * vxg: a small binary header followed by the raw values. All fields are
+//!   little-endian:
+//!
+//! ```text
+//! offset  size  field
+//!      0     8  magic, the bytes "VOXGRID\0"
+//!      8     4  u32 version, currently 1
+//!     12     4  u32 nx
+//!     16     4  u32 ny
+//!     20     4  u32 nz
+//!     24    12  f32 x, y, z of the bounds minimum
+//!     36    12  f32 x, y, z of the bounds maximum
+//!     48   4*n  f32 voxel values, n = nx * ny * nz
+//! ```
+//!
+//! Both formats reject voxel values that are negative, infinite or nan.
+
+use crate::vec::Vec3;
+use crate::aabb::AABB;
+
+use std::fs::File;
+use std::io::{self, Read, BufReader};
+use std::path::Path;
+
+const VXG_MAGIC: &[u8; 8] = b"VOXGRID\0";
+const VXG_VERSION: u32 = 1;
+const VXG_HEADER_SIZE: usize = 48;
+
+const BRICK_SIZE: usize = 8;
+
+pub trait VoxelGrid: Send + Sync {
+    fn resolution(&self) -> (usize, usize, usize);
+    fn bounds(&self) -> AABB;
+    fn voxel(&self, x: usize, y: usize, z: usize) -> f32;
+    fn max_value(&self) -> f32;
+
+    fn lookup(&self, p: Vec3) -> f32 {
+        let bounds = self.bounds();
+        let (nx, ny, nz) = self.resolution();
+
+        if p.x < bounds.min.x || p.y < bounds.min.y || p.z < bounds.min.z
+            || p.x > bounds.max.x || p.y > bounds.max.y || p.z > bounds.max.z {
+            return 0.0;
+        }
+
+        let extent = bounds.max - bounds.min;
+        let gx = (p.x - bounds.min.x) / extent.x * nx as f32 - 0.5;
+        let gy = (p.y - bounds.min.y) / extent.y * ny as f32 - 0.5;
+        let gz = (p.z - bounds.min.z) / extent.z * nz as f32 - 0.5;
+
+        let (x0, x1, fx) = cell(gx, nx);
+        let (y0, y1, fy) = cell(gy, ny);
+        let (z0, z1, fz) = cell(gz, nz);
+
+        let c00 = lerp(self.voxel(x0, y0, z0), self.voxel(x1, y0, z0), fx);
+        let c10 = lerp(self.voxel(x0, y1, z0), self.voxel(x1, y1, z0), fx);
+        let c01 = lerp(self.voxel(x0, y0, z1), self.voxel(x1, y0, z1), fx);
+        let c11 = lerp(self.voxel(x0, y1, z1), self.voxel(x1, y1, z1), fx);
+
+        lerp(lerp(c00, c10, fy), lerp(c01, c11, fy), fz)
+    }
+}
+
+pub struct DenseGrid {
+    nx: usize,
+    ny: usize,
+    nz: usize,
+    data: Vec<f32>,
+    bounds: AABB,
+    max_value: f32
+}
+
+impl DenseGrid {
+    pub fn new(nx: usize, ny: usize, nz: usize, data: Vec<f32>, bounds: AABB) -> Self {
+        assert_eq!(data.len(), nx * ny * nz, "voxel data does not match the grid resolution");
+
+        let max_value = data.iter().cloned().fold(0.0, f32::max);
+        DenseGrid { nx, ny, nz, data, bounds, max_value }
+    }
+
+    pub fn from_fn<F: Fn(Vec3) -> f32>(nx: usize, ny: usize, nz: usize, bounds: AABB, f: F) -> Self {
+        let extent = bounds.max - bounds.min;
+        let mut data = Vec::with_capacity(nx * ny * nz);
+
+        for z in 0..nz {
+            for y in 0..ny {
+                for x in 0..nx {
+                    let p = Vec3::new(
+                        bounds.min.x + (x as f32 + 0.5) / nx as f32 * extent.x,
+                        bounds.min.y + (y as f32 + 0.5) / ny as f32 * extent.y,
+                        bounds.min.z + (z as f32 + 0.5) / nz as f32 * extent.z
+                    );
+                    data.push(f(p));
+                }
+            }
+        }
+
+        DenseGrid::new(nx, ny, nz, data, bounds)
+    }
+
+    pub fn from_raw<P: AsRef<Path>>(path: P, nx: usize, ny: usize, nz: usize, bounds: AABB) -> io::Result<Self> {
+        DenseGrid::parse_raw(&read_file(path)?, nx, ny, nz, bounds)
+    }
+
+    pub fn from_vxg<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        DenseGrid::parse_vxg(&read_file(path)?)
+    }
+
+    pub fn parse_raw(bytes: &[u8], nx: usize, ny: usize, nz: usize, bounds: AABB) -> io::Result<Self> {
+        let expected = grid_bytes(nx, ny, nz)?;
+        if bytes.len() != expected {
+            return Err(invalid_data(format!(
+                "raw grid has {} bytes, expected {} for a {}x{}x{} grid", bytes.len(), expected, nx, ny, nz
+            )));
+        }
+
+        Ok(DenseGrid::new(nx, ny, nz, read_voxels(bytes)?, bounds))
+    }
+
+    pub fn parse_vxg(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() < VXG_HEADER_SIZE {
+            return Err(invalid_data(format!("vxg file is only {} bytes, header needs {}", bytes.len(), VXG_HEADER_SIZE)));
+        }
+
+        if &bytes[0..8] != VXG_MAGIC {
+            return Err(invalid_data("not a vxg file, magic bytes do not match".to_string()));
+        }
+
+        let version = read_u32(&bytes[8..]);
+        if version != VXG_VERSION {
+            return Err(invalid_data(format!("unsupported vxg version {}", version)));
+        }
+
+        let nx = read_u32(&bytes[12..]) as usize;
+        let ny = read_u32(&bytes[16..]) as usize;
+        let nz = read_u32(&bytes[20..]) as usize;
+        let floats = read_f32s(&bytes[24..VXG_HEADER_SIZE]);
+        let bounds = AABB::new(
+            Vec3::new(floats[0], floats[1], floats[2]),
+            Vec3::new(floats[3], floats[4], floats[5])
+        );
+
+        // also rejects nan bounds
+        if !(bounds.min.x < bounds.max.x && bounds.min.y < bounds.max.y && bounds.min.z < bounds.max.z) {
+            return Err(invalid_data(format!("vxg bounds {:?} - {:?} are empty", bounds.min, bounds.max)));
+        }
+
+        let payload = &bytes[VXG_HEADER_SIZE..];
+        let expected = grid_bytes(nx, ny, nz)?;
+        if payload.len() != expected {
+            return Err(invalid_data(format!(
+                "vxg payload has {} bytes, expected {} for a {}x{}x{} grid", payload.len(), expected, nx, ny, nz
+            )));
+        }
+
+        Ok(DenseGrid::new(nx, ny, nz, read_voxels(payload)?, bounds))
+    }
+}
+
+impl VoxelGrid for DenseGrid {
+    fn resolution(&self) -> (usize, usize, usize) {
+        (self.nx, self.ny, self.nz)
+    }
+
+    fn bounds(&self) -> AABB {
+        self.bounds
+    }
+
+    fn voxel(&self, x: usize, y: usize, z: usize) -> f32 {
+        self.data[x + self.nx * (y + self.ny * z)]
+    }
+
+    fn max_value(&self) -> f32 {
+        self.max_value
+    }
+}
+
+/// Stores the grid in bricks of 8x8x8 voxels and drops every brick whose
+/// values are all below a threshold, which keeps mostly empty smoke
+/// simulations small in memory.
+pub struct SparseGrid {
+    nx: usize,
+    ny: usize,
+    nz: usize,
+    bricks_x: usize,
+    bricks_y: usize,
+    bricks: Vec<Option<Box<[f32]>>>,
+    bounds: AABB,
+    max_value: f32
+}
+
+impl SparseGrid {
+    pub fn from_dense(grid: &DenseGrid, threshold: f32) -> Self {
+        let bricks_x = (grid.nx + BRICK_SIZE - 1) / BRICK_SIZE;
+        let bricks_y = (grid.ny + BRICK_SIZE - 1) / BRICK_SIZE;
+        let bricks_z = (grid.nz + BRICK_SIZE - 1) / BRICK_SIZE;
+
+        let mut bricks = Vec::with_capacity(bricks_x * bricks_y * bricks_z);
+        let mut max_value: f32 = 0.0;
+
+        for bz in 0..bricks_z {
+            for by in 0..bricks_y {
+                for bx in 0..bricks_x {
+                    let mut brick = vec![0.0; BRICK_SIZE * BRICK_SIZE * BRICK_SIZE];
+                    let mut occupied = false;
+
+                    for z in 0..BRICK_SIZE {
+                        for y in 0..BRICK_SIZE {
+                            for x in 0..BRICK_SIZE {
+                                let (gx, gy, gz) = (bx * BRICK_SIZE + x, by * BRICK_SIZE + y, bz * BRICK_SIZE + z);
+                                if gx >= grid.nx || gy >= grid.ny || gz >= grid.nz {
+                                    continue;
+                                }
+
+                                let value = grid.voxel(gx, gy, gz);
+                                if value > threshold {
+                                    occupied = true;
+                                }
+                                brick[x + BRICK_SIZE * (y + BRICK_SIZE * z)] = value;
+                            }
+                        }
+                    }
+
+                    if occupied {
+                        max_value = brick.iter().cloned().fold(max_value, f32::max);
+                        bricks.push(Some(brick.into_boxed_slice()));
+                    } else {
+                        bricks.push(None);
+                    }
+                }
+            }
+        }
+
+        SparseGrid {
+            nx: grid.nx,
+            ny: grid.ny,
+            nz: grid.nz,
+            bricks_x,
+            bricks_y,
+            bricks,
+            bounds: grid.bounds,
+            max_value
+        }
+    }
+
+    pub fn from_vxg<P: AsRef<Path>>(path: P, threshold: f32) -> io::Result<Self> {
+        Ok(SparseGrid::from_dense(&DenseGrid::from_vxg(path)?, threshold))
+    }
+}
+
+impl VoxelGrid for SparseGrid {
+    fn resolution(&self) -> (usize, usize, usize) {
+        (self.nx, self.ny, self.nz)
+    }
+
+    fn bounds(&self) -> AABB {
+        self.bounds
+    }
+
+    fn voxel(&self, x: usize, y: usize, z: usize) -> f32 {
+        let brick = (x / BRICK_SIZE) + self.bricks_x * ((y / BRICK_SIZE) + self.bricks_y * (z / BRICK_SIZE));
+
+        match self.bricks[brick] {
+            Some(ref values) => {
+                let (lx, ly, lz) = (x % BRICK_SIZE, y % BRICK_SIZE, z % BRICK_SIZE);
+                values[lx + BRICK_SIZE * (ly + BRICK_SIZE * lz)]
+            },
+            None => 0.0
+        }
+    }
+
+    fn max_value(&self) -> f32 {
+        self.max_value
+    }
+}
+
+fn cell(g: f32, n: usize) -> (usize, usize, f32) {
+    let g = g.max(0.0).min((n - 1) as f32);
+    let i0 = g.floor() as usize;
+    let i1 = (i0 + 1).min(n - 1);
+
+    (i0, i1, g - i0 as f32)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+// size of the voxel values in bytes, checked since the resolution may come
+// from an untrusted header
+fn grid_bytes(nx: usize, ny: usize, nz: usize) -> io::Result<usize> {
+    if nx == 0 || ny == 0 || nz == 0 {
+        return Err(invalid_data(format!("grid has an empty resolution {}x{}x{}", nx, ny, nz)));
+    }
+
+    nx.checked_mul(ny)
+        .and_then(|n| n.checked_mul(nz))
+        .and_then(|n| n.checked_mul(4))
+        .ok_or_else(|| invalid_data(format!("grid resolution {}x{}x{} is too large", nx, ny, nz)))
+}
+
+fn read_file<P: AsRef<Path>>(path: P) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    BufReader::new(File::open(path)?).read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn read_f32s(bytes: &[u8]) -> Vec<f32> {
+    bytes.chunks(4).map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect()
+}
+
+// densities of a file, an infinite or nan voxel would make the majorant of
+// the delta tracking in `HeterogeneousMedium` useless and hang the render
+fn read_voxels(bytes: &[u8]) -> io::Result<Vec<f32>> {
+    let values = read_f32s(bytes);
+    match values.iter().position(|value| !(value.is_finite() && *value >= 0.0)) {
+        Some(i) => Err(invalid_data(format!("voxel {} has the value {}, densities must be finite and not negative", i, values[i]))),
+        None => Ok(values)
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_bounds() -> AABB {
+        AABB::new(Vec3::zero(), Vec3::new(1.0, 1.0, 1.0))
+    }
+
+    fn vxg_bytes(nx: u32, ny: u32, nz: u32, values: &[f32]) -> Vec<u8> {
+        let mut bytes = VXG_MAGIC.to_vec();
+        for value in &[VXG_VERSION, nx, ny, nz] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in [0.0f32, 0.0, 0.0, 1.0, 1.0, 1.0].iter().chain(values) {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn error_message(result: io::Result<DenseGrid>) -> String {
+        match result {
+            Ok(_) => panic!("expected an error"),
+            Err(err) => {
+                assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+                err.to_string()
+            }
+        }
+    }
+
+    #[test]
+    fn lookup_interpolates_between_voxel_centers() {
+        let grid = DenseGrid::new(2, 1, 1, vec![1.0, 3.0], unit_bounds());
+
+        assert_eq!(grid.lookup(Vec3::new(0.25, 0.5, 0.5)), 1.0);
+        assert_eq!(grid.lookup(Vec3::new(0.5, 0.5, 0.5)), 2.0);
+        assert_eq!(grid.lookup(Vec3::new(0.75, 0.5, 0.5)), 3.0);
+        // clamped towards the bounds, zero outside of them
+        assert_eq!(grid.lookup(Vec3::new(0.9, 0.1, 0.9)), 3.0);
+        assert_eq!(grid.lookup(Vec3::new(1.1, 0.5, 0.5)), 0.0);
+    }
+
+    #[test]
+    fn parses_vxg() {
+        let grid = DenseGrid::parse_vxg(&vxg_bytes(2, 2, 1, &[0.0, 1.0, 2.0, 4.0])).unwrap();
+
+        assert_eq!(grid.resolution(), (2, 2, 1));
+        assert_eq!(grid.voxel(0, 1, 0), 2.0);
+        assert_eq!(grid.max_value(), 4.0);
+        assert_eq!(grid.bounds().max, Vec3::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn rejects_malformed_vxg() {
+        assert!(error_message(DenseGrid::parse_vxg(b"VOXGRID\0")).contains("header"));
+
+        let mut bytes = vxg_bytes(1, 1, 1, &[1.0]);
+        bytes[0] = b'X';
+        assert!(error_message(DenseGrid::parse_vxg(&bytes)).contains("magic"));
+
+        let mut bytes = vxg_bytes(1, 1, 1, &[1.0]);
+        bytes[8] = 2;
+        assert!(error_message(DenseGrid::parse_vxg(&bytes)).contains("version"));
+
+        assert!(error_message(DenseGrid::parse_vxg(&vxg_bytes(0, 1, 1, &[]))).contains("empty resolution"));
+        assert!(error_message(DenseGrid::parse_vxg(&vxg_bytes(2, 1, 1, &[1.0]))).contains("expected 8"));
+    }
+
+    #[test]
+    fn rejects_overflowing_resolution() {
+        let max = u32::max_value();
+        let message = error_message(DenseGrid::parse_vxg(&vxg_bytes(max, max, max, &[1.0])));
+        assert!(message.contains("too large"));
+
+        let message = error_message(DenseGrid::parse_raw(&[], usize::max_value(), 2, 1, unit_bounds()));
+        assert!(message.contains("too large"));
+    }
+
+    #[test]
+    fn sparse_grid_matches_dense_grid() {
+        let dense = DenseGrid::from_fn(20, 9, 3, unit_bounds(), |p| if p.x > 0.5 { p.y } else { 0.0 });
+        let sparse = SparseGrid::from_dense(&dense, 0.0);
+
+        assert!(sparse.bricks.iter().any(|brick| brick.is_none()));
+        assert_eq!(sparse.max_value(), dense.max_value());
+
+        for z in 0..3 {
+            for y in 0..9 {
+                for x in 0..20 {
+                    assert_eq!(sparse.voxel(x, y, z), dense.voxel(x, y, z));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_densities() {
+        let message = error_message(DenseGrid::parse_vxg(&vxg_bytes(2, 1, 1, &[1.0, std::f32::INFINITY])));
+        assert!(message.contains("voxel 1") && message.contains("inf"));
+
+        assert!(error_message(DenseGrid::parse_vxg(&vxg_bytes(1, 1, 1, &[std::f32::NAN]))).contains("finite"));
+        assert!(error_message(DenseGrid::parse_vxg(&vxg_bytes(1, 1, 1, &[-0.5]))).contains("negative"));
+
+        let bytes: Vec<u8> = [0.5f32, std::f32::INFINITY].iter().flat_map(|value| value.to_le_bytes().to_vec()).collect();
+        let message = error_message(DenseGrid::parse_raw(&bytes, 2, 1, 1, unit_bounds()));
+        assert!(message.contains("voxel 1") && message.contains("inf"));
+    }
+}